@@ -21,6 +21,7 @@
 pub type Count = u8;    // only value 1 to 4 are supported
 pub type Offset = u32;  // can point in the middle of the buffer
 pub type Stride = u8;   // I don't believe HW supports more
+pub type InstanceRate = u8; // 0 for per-vertex data, N to advance once every N instances
 
 #[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
 #[repr(u8)]
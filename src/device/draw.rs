@@ -25,7 +25,7 @@ pub trait DrawList {
     fn bind_program(&mut self, b::Program);
     fn bind_array_buffer(&mut self, b::ArrayBuffer);
     fn bind_attribute(&mut self, ::AttributeSlot, b::Buffer, a::Count,
-                      a::Type, a::Stride, a::Offset);
+                      a::Type, a::Stride, a::Offset, a::InstanceRate);
     fn bind_index(&mut self, b::Buffer);
     fn bind_frame_buffer(&mut self, b::FrameBuffer);
     /// Unbind any surface from the specified target slot
@@ -37,21 +37,56 @@ pub trait DrawList {
                            t::Level, Option<t::Layer>);
     fn bind_uniform_block(&mut self, b::Program, ::UniformBufferSlot,
                           ::UniformBlockIndex, b::Buffer);
+    /// Bind a range of the buffer to the uniform block, the offset has to be
+    /// a multiple of `Capabilities::get_uniform_buffer_offset_alignment`
+    fn bind_uniform_block_range(&mut self, b::Program, ::UniformBufferSlot,
+                                ::UniformBlockIndex, b::Buffer,
+                                offset: uint, size: uint);
     fn bind_uniform(&mut self, ::shade::Location, ::shade::UniformValue);
     fn bind_texture(&mut self, ::TextureSlot, ::tex::TextureKind, b::Texture,
                     Option<::SamplerHandle>);
+    /// Bind a level of the texture as an image for load/store access
+    fn bind_image(&mut self, ::ImageSlot, b::Texture, t::Level,
+                  ::tex::ImageAccess, ::tex::Format);
     fn set_primitive(&mut self, ::state::Primitive);
     fn set_viewport(&mut self, t::Rect);
     fn set_scissor(&mut self, Option<t::Rect>);
     fn set_depth_stencil(&mut self, Option<::state::Depth>,
                          Option<::state::Stencil>, ::state::CullMode);
     fn set_blend(&mut self, Option<::state::Blend>);
+    /// Override the blend state for a single color target
+    fn set_blend_target(&mut self, t::ColorIndex, Option<::state::Blend>);
+    fn set_depth_range(&mut self, ::state::DepthRange);
+    fn set_depth_clamp(&mut self, bool);
+    fn set_clip_planes(&mut self, ::state::ClipPlaneMask);
     fn set_color_mask(&mut self, ::state::ColorMask);
+    fn set_logic_op(&mut self, Option<::state::LogicOp>);
+    fn begin_occlusion_query(&mut self, b::Query);
+    fn end_occlusion_query(&mut self);
+    /// Record the time at which the previous commands complete
+    fn query_timestamp(&mut self, b::Query);
+    /// Skip the draw calls until `end_conditional` if the query got no samples
+    fn begin_conditional(&mut self, b::Query, ::ConditionalMode);
+    fn end_conditional(&mut self);
     fn update_buffer(&mut self, b::Buffer, Box<::Blob + Send>);
     fn update_texture(&mut self, ::tex::TextureKind, b::Texture,
                       ::tex::ImageInfo, Box<::Blob + Send>);
     fn call_clear(&mut self, t::ClearData);
-    fn call_draw(&mut self, ::PrimitiveType, ::VertexCount, ::VertexCount);
-    fn call_draw_indexed(&mut self, ::PrimitiveType, ::IndexType, ::IndexCount,
-                         ::IndexCount);
+    /// Clear a color target with an integer format to the given values
+    fn call_clear_color_int(&mut self, t::ColorIndex, [i32, ..4]);
+    /// Clear a color target with an unsigned integer format to the given values
+    fn call_clear_color_uint(&mut self, t::ColorIndex, [u32, ..4]);
+    /// Draw `count` vertices from `start`, once for each instance if set
+    fn call_draw(&mut self, ::PrimitiveType, start: ::VertexCount, count: ::VertexCount,
+                 instances: Option<::InstanceCount>);
+    /// Draw `count` indices from `start`, adding the base vertex to each index
+    fn call_draw_indexed(&mut self, ::PrimitiveType, ::IndexType, start: ::IndexCount,
+                         count: ::IndexCount, base: i32,
+                         instances: Option<::InstanceCount>);
+    /// Draw with the parameters stored in a buffer, the number of draws being
+    /// read from another buffer and capped at `max_count`
+    fn call_draw_indexed_indirect_count(&mut self, ::PrimitiveType, ::IndexType,
+                                        commands: b::Buffer, offset: uint,
+                                        count: b::Buffer, count_offset: uint,
+                                        max_count: u32, stride: u32);
 }
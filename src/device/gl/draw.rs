@@ -15,6 +15,7 @@
 //! OpenGL implementation of the `DrawList`
 
 use std::slice;
+use serial;
 
 pub struct DrawList {
     buf: Vec<::Command>,
@@ -27,9 +28,48 @@ impl DrawList {
         }
     }
 
+    /// Create a list with room for `capacity` commands. Clearing the list
+    /// keeps its storage, so a list reused every frame only allocates when it
+    /// records more commands than ever before. The memory is held as long as
+    /// the list lives however, and the commands carrying data, such as the
+    /// buffer updates, still allocate for it.
+    pub fn with_capacity(capacity: uint) -> DrawList {
+        DrawList {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Number of commands the list can hold without allocating.
+    pub fn capacity(&self) -> uint {
+        self.buf.capacity()
+    }
+
     pub fn iter<'a>(&'a self) -> slice::Items<'a, ::Command> {
         self.buf.iter()
     }
+
+    /// Move the commands of another list to the end of this one, keeping
+    /// their order.
+    pub fn append(&mut self, other: DrawList) {
+        self.buf.extend(other.buf.move_iter());
+    }
+
+    /// Encode the commands in a stable binary format, see the `serial` module.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for com in self.buf.iter() {
+            serial::encode(com, &mut out);
+        }
+        out
+    }
+
+    /// Rebuild a list from the data produced by `serialize`. The objects it
+    /// refers to must still exist when the list is submitted.
+    pub fn deserialize(data: &[u8]) -> Result<DrawList, serial::SerialError> {
+        serial::decode(data).map(|buf| DrawList {
+            buf: buf,
+        })
+    }
 }
 
 impl ::draw::DrawList for DrawList {
@@ -47,8 +87,9 @@ impl ::draw::DrawList for DrawList {
 
     fn bind_attribute(&mut self, slot: ::AttributeSlot, buf: super::Buffer,
                       count: ::attrib::Count, atype: ::attrib::Type,
-                      stride: ::attrib::Stride, offset: ::attrib::Offset) {
-        self.buf.push(::BindAttribute(slot, buf, count, atype, stride, offset));
+                      stride: ::attrib::Stride, offset: ::attrib::Offset,
+                      rate: ::attrib::InstanceRate) {
+        self.buf.push(::BindAttribute(slot, buf, count, atype, stride, offset, rate));
     }
 
     fn bind_index(&mut self, buf: super::Buffer) {
@@ -77,6 +118,12 @@ impl ::draw::DrawList for DrawList {
         self.buf.push(::BindUniformBlock(prog, slot, index, buf));
     }
 
+    fn bind_uniform_block_range(&mut self, prog: super::Program, slot: ::UniformBufferSlot,
+                                index: ::UniformBlockIndex, buf: super::Buffer,
+                                offset: uint, size: uint) {
+        self.buf.push(::BindUniformBlockRange(prog, slot, index, buf, offset, size));
+    }
+
     fn bind_uniform(&mut self, loc: ::shade::Location, value: ::shade::UniformValue) {
         self.buf.push(::BindUniform(loc, value));
     }
@@ -85,6 +132,11 @@ impl ::draw::DrawList for DrawList {
         self.buf.push(::BindTexture(slot, kind, tex, sampler));
     }
 
+    fn bind_image(&mut self, slot: ::ImageSlot, tex: super::Texture, level: ::target::Level,
+                  access: ::tex::ImageAccess, format: ::tex::Format) {
+        self.buf.push(::BindImage(slot, tex, level, access, format));
+    }
+
     fn set_primitive(&mut self, prim: ::state::Primitive) {
         self.buf.push(::SetPrimitiveState(prim));
     }
@@ -106,10 +158,50 @@ impl ::draw::DrawList for DrawList {
         self.buf.push(::SetBlendState(blend));
     }
 
+    fn set_blend_target(&mut self, index: ::target::ColorIndex, blend: Option<::state::Blend>) {
+        self.buf.push(::SetBlendTarget(index, blend));
+    }
+
     fn set_color_mask(&mut self, mask: ::state::ColorMask) {
         self.buf.push(::SetColorMask(mask));
     }
 
+    fn set_depth_range(&mut self, range: ::state::DepthRange) {
+        self.buf.push(::SetDepthRange(range));
+    }
+
+    fn set_depth_clamp(&mut self, enable: bool) {
+        self.buf.push(::SetDepthClamp(enable));
+    }
+
+    fn set_clip_planes(&mut self, mask: ::state::ClipPlaneMask) {
+        self.buf.push(::SetClipPlanes(mask));
+    }
+
+    fn begin_occlusion_query(&mut self, query: super::Query) {
+        self.buf.push(::BeginOcclusionQuery(query));
+    }
+
+    fn end_occlusion_query(&mut self) {
+        self.buf.push(::EndOcclusionQuery);
+    }
+
+    fn query_timestamp(&mut self, query: super::Query) {
+        self.buf.push(::QueryTimestamp(query));
+    }
+
+    fn begin_conditional(&mut self, query: super::Query, mode: ::ConditionalMode) {
+        self.buf.push(::BeginConditional(query, mode));
+    }
+
+    fn end_conditional(&mut self) {
+        self.buf.push(::EndConditional);
+    }
+
+    fn set_logic_op(&mut self, op: Option<::state::LogicOp>) {
+        self.buf.push(::SetLogicOp(op));
+    }
+
     fn update_buffer(&mut self, buf: super::Buffer, data: Box<::Blob + Send>) {
         self.buf.push(::UpdateBuffer(buf, data));
     }
@@ -119,17 +211,34 @@ impl ::draw::DrawList for DrawList {
         self.buf.push(::UpdateTexture(kind, tex, info, data));
     }
 
+    fn call_clear_color_int(&mut self, index: ::target::ColorIndex, color: [i32, ..4]) {
+        self.buf.push(::ClearColorInt(index, color));
+    }
+
+    fn call_clear_color_uint(&mut self, index: ::target::ColorIndex, color: [u32, ..4]) {
+        self.buf.push(::ClearColorUint(index, color));
+    }
+
     fn call_clear(&mut self, data: ::target::ClearData) {
         self.buf.push(::Clear(data));
     }
 
     fn call_draw(&mut self, ptype: ::PrimitiveType, start: ::VertexCount,
-                 count: ::VertexCount) {
-        self.buf.push(::Draw(ptype, start, count));
+                 count: ::VertexCount, instances: Option<::InstanceCount>) {
+        self.buf.push(::Draw(ptype, start, count, instances));
     }
 
     fn call_draw_indexed(&mut self, ptype: ::PrimitiveType, itype: ::IndexType,
-                         start: ::IndexCount, count: ::IndexCount) {
-        self.buf.push(::DrawIndexed(ptype, itype, start, count));
+                         start: ::IndexCount, count: ::IndexCount, base: i32,
+                         instances: Option<::InstanceCount>) {
+        self.buf.push(::DrawIndexed(ptype, itype, start, count, base, instances));
+    }
+
+    fn call_draw_indexed_indirect_count(&mut self, ptype: ::PrimitiveType,
+                                        itype: ::IndexType, commands: super::Buffer,
+                                        offset: uint, count: super::Buffer,
+                                        count_offset: uint, max_count: u32, stride: u32) {
+        self.buf.push(::DrawIndexedIndirectCount(ptype, itype, commands, offset,
+                                                 count, count_offset, max_count, stride));
     }
 }
@@ -22,8 +22,8 @@ extern crate gl;
 extern crate libc;
 
 use log;
-use std::{fmt, str};
-use std::collections::HashSet;
+use std::{cmp, fmt, mem, str};
+use std::collections::{HashMap, HashSet};
 use a = super::attrib;
 
 pub use self::draw::DrawList;
@@ -41,6 +41,7 @@ pub type FrameBuffer    = gl::types::GLuint;
 pub type Surface        = gl::types::GLuint;
 pub type Sampler        = gl::types::GLuint;
 pub type Texture        = gl::types::GLuint;
+pub type Query          = gl::types::GLuint;
 
 fn get_uint(name: gl::types::GLenum) -> uint {
     let mut value = 0 as gl::types::GLint;
@@ -48,6 +49,12 @@ fn get_uint(name: gl::types::GLenum) -> uint {
     value as uint
 }
 
+fn get_float(name: gl::types::GLenum, default: f32) -> f32 {
+    let mut value = default as gl::types::GLfloat;
+    unsafe { gl::GetFloatv(name, &mut value) };
+    value as f32
+}
+
 /// Get a statically allocated string from the implementation using
 /// `glGetString`. Fails if it `GLenum` cannot be handled by the
 /// implementation's `gl::GetString` function.
@@ -229,10 +236,183 @@ fn target_to_gl(target: super::target::Target) -> gl::types::GLenum {
     }
 }
 
+/// Set the swap interval with the first swap control extension the provider
+/// supports, and return the name of the command used.
+fn set_swap_interval_with(provider: &super::GlProvider, interval: i32)
+                          -> Result<&'static str, ()> {
+    // the interval to use when negative ones, for adaptive sync, are not accepted
+    let regular = if interval < 0 { -interval } else { interval };
+    let load = |extension: &str, function: &str| -> Option<*const libc::c_void> {
+        if provider.is_extension_supported(extension) {
+            let ptr = provider.get_proc_address(function);
+            if ptr.is_null() { None } else { Some(ptr) }
+        } else {
+            None
+        }
+    };
+    match load("WGL_EXT_swap_control", "wglSwapIntervalEXT") {
+        Some(ptr) => {
+            let swap_interval: extern "system" fn(libc::c_int) -> libc::c_int =
+                unsafe { mem::transmute(ptr) };
+            let value = if provider.is_extension_supported("WGL_EXT_swap_control_tear") {
+                interval
+            } else {
+                regular
+            };
+            // returns a `BOOL`
+            return if swap_interval(value as libc::c_int) != 0 {
+                Ok("wglSwapIntervalEXT")
+            } else {
+                Err(())
+            }
+        },
+        None => (),
+    }
+    match load("GLX_MESA_swap_control", "glXSwapIntervalMESA") {
+        Some(ptr) => {
+            let swap_interval: extern "system" fn(libc::c_uint) -> libc::c_int =
+                unsafe { mem::transmute(ptr) };
+            // returns 0 on success
+            return if swap_interval(regular as libc::c_uint) == 0 {
+                Ok("glXSwapIntervalMESA")
+            } else {
+                Err(())
+            }
+        },
+        None => (),
+    }
+    match load("GLX_SGI_swap_control", "glXSwapIntervalSGI") {
+        // the synchronization can't be disabled with this one
+        Some(ptr) if regular > 0 => {
+            let swap_interval: extern "system" fn(libc::c_int) -> libc::c_int =
+                unsafe { mem::transmute(ptr) };
+            if swap_interval(regular as libc::c_int) == 0 {
+                Ok("glXSwapIntervalSGI")
+            } else {
+                Err(())
+            }
+        },
+        _ => Err(()),
+    }
+}
+
+fn reset_status_to_reason(status: gl::types::GLenum) -> Option<super::ResetReason> {
+    match status {
+        gl::NO_ERROR => None,
+        gl::GUILTY_CONTEXT_RESET => Some(super::ResetGuilty),
+        gl::INNOCENT_CONTEXT_RESET => Some(super::ResetInnocent),
+        _ => Some(super::ResetUnknown),
+    }
+}
+
+/// Check that `size` bytes starting at `offset` fit in a buffer of `buffer_size` bytes.
+fn check_range(buffer_size: uint, offset: uint, size: uint) -> Result<(), super::BufferError> {
+    match offset.checked_add(&size) {
+        Some(end) if end <= buffer_size => Ok(()),
+        _ => Err(super::BufferOutOfRange),
+    }
+}
+
+/// Check that data of the given size can be uploaded into an immutable storage,
+/// which can't be resized.
+fn check_storage_update(storage_size: uint, flags: super::StorageFlags, size: uint)
+                        -> Result<(), super::BufferError> {
+    if !flags.contains(super::StorageDynamic) {
+        return Err(super::UnsupportedBufferOperation)
+    }
+    check_range(storage_size, 0, size)
+}
+
+fn storage_flags_to_gl(flags: super::StorageFlags) -> gl::types::GLbitfield {
+    let mut bits = 0;
+    if flags.contains(super::StorageDynamic) { bits |= gl::DYNAMIC_STORAGE_BIT; }
+    if flags.contains(super::StorageMapRead) { bits |= gl::MAP_READ_BIT; }
+    if flags.contains(super::StorageMapWrite) { bits |= gl::MAP_WRITE_BIT; }
+    if flags.contains(super::StoragePersistent) { bits |= gl::MAP_PERSISTENT_BIT; }
+    if flags.contains(super::StorageCoherent) { bits |= gl::MAP_COHERENT_BIT; }
+    bits
+}
+
+/// Check that the offset is a multiple of the alignment, any offset is fine
+/// when the alignment is not known.
+fn is_aligned(offset: uint, alignment: uint) -> bool {
+    alignment == 0 || offset % alignment == 0
+}
+
+/// Objects waiting to be deleted, in batches of a single call per type.
+struct DeletionQueue {
+    buffers: Vec<Buffer>,
+    surfaces: Vec<Surface>,
+    textures: Vec<Texture>,
+    samplers: Vec<Sampler>,
+    queries: Vec<Query>,
+}
+
+impl DeletionQueue {
+    fn new() -> DeletionQueue {
+        DeletionQueue {
+            buffers: Vec::new(),
+            surfaces: Vec::new(),
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            queries: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffers.is_empty() && self.surfaces.is_empty() && self.textures.is_empty() &&
+        self.samplers.is_empty() && self.queries.is_empty()
+    }
+
+    /// Delete all the queued objects and empty the queue.
+    fn flush(&mut self) {
+        self.flush_with([gl::DeleteBuffers, gl::DeleteRenderbuffers, gl::DeleteTextures,
+                         gl::DeleteSamplers, gl::DeleteQueries]);
+    }
+
+    /// Empty the queue, calling each of the given functions once with all the
+    /// objects of a type, in the order of the fields.
+    fn flush_with(&mut self, funs: [DeleteFn, ..5]) {
+        fn delete(names: &mut Vec<gl::types::GLuint>, fun: DeleteFn) {
+            if !names.is_empty() {
+                unsafe {
+                    fun(names.len() as gl::types::GLsizei, names.as_ptr());
+                }
+                names.clear();
+            }
+        }
+        delete(&mut self.buffers, funs[0]);
+        delete(&mut self.surfaces, funs[1]);
+        delete(&mut self.textures, funs[2]);
+        delete(&mut self.samplers, funs[3]);
+        delete(&mut self.queries, funs[4]);
+    }
+}
+
+/// A function deleting a number of GL objects, like `glDeleteBuffers`.
+type DeleteFn = unsafe fn(gl::types::GLsizei, *const gl::types::GLuint);
+
 /// An OpenGL back-end with GLSL shaders
 pub struct GlBackEnd {
     caps: super::Capabilities,
     info: Info,
+    stats: super::FrameStats,
+    /// Names of the imported buffers, which are not deleted by the back-end
+    imported_buffers: HashSet<Buffer>,
+    /// Names of the imported textures, which are not deleted by the back-end
+    imported_textures: HashSet<Texture>,
+    /// Sizes and flags of the buffers with an immutable storage
+    immutable_buffers: HashMap<Buffer, (uint, super::StorageFlags)>,
+    /// Objects deleted by the user, to be released at the end of `submit`
+    deletions: DeletionQueue,
+    /// Reflection of the linked programs, for the validation
+    programs: HashMap<Program, super::shade::ProgramInfo>,
+    /// Whether the reset status is only exposed by `GL_ARB_robustness`
+    robustness_arb: bool,
+    /// Whether the draw lists are validated before being submitted
+    validation: bool,
+    /// Number of commands to submit between two flushes
+    max_batch_size: Option<uint>,
 }
 
 impl GlBackEnd {
@@ -240,23 +420,146 @@ impl GlBackEnd {
     pub fn new(provider: &super::GlProvider) -> GlBackEnd {
         gl::load_with(|s| provider.get_proc_address(s));
         let info = Info::get();
+        let render_targets_supported = info.version >= Version(3, 0, None, "")
+            || info.is_extension_supported("GL_ARB_framebuffer_object");
+        let uniform_block_supported = info.version >= Version(3, 1, None, "")
+            || info.is_extension_supported("GL_ARB_uniform_buffer_object");
         let caps = super::Capabilities {
             shader_model: shade::get_model(),
             max_draw_buffers: get_uint(gl::MAX_DRAW_BUFFERS),
             max_texture_size: get_uint(gl::MAX_TEXTURE_SIZE),
+            max_texture_units: get_uint(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS),
             max_vertex_attributes: get_uint(gl::MAX_VERTEX_ATTRIBS),
-            uniform_block_supported: info.version >= Version(3, 1, None, "")
-                || info.is_extension_supported("GL_ARB_uniform_buffer_object"),
+            max_color_attachments: if render_targets_supported {
+                get_uint(gl::MAX_COLOR_ATTACHMENTS)
+            } else {
+                0
+            },
+            max_samples: if render_targets_supported {
+                get_uint(gl::MAX_SAMPLES)
+            } else {
+                0
+            },
+            max_uniform_block_size: if uniform_block_supported {
+                get_uint(gl::MAX_UNIFORM_BLOCK_SIZE)
+            } else {
+                0
+            },
+            uniform_buffer_offset_alignment: if uniform_block_supported {
+                get_uint(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT)
+            } else {
+                0
+            },
+            max_anisotropy: if info.is_extension_supported("GL_EXT_texture_filter_anisotropic") {
+                get_float(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT, 1.0)
+            } else {
+                1.0
+            },
+            uniform_block_supported: uniform_block_supported,
+            render_targets_supported: render_targets_supported,
             array_buffer_supported: info.version >= Version(3, 0, None, "")
                 || info.is_extension_supported("GL_ARB_vertex_array_object"),
             immutable_storage_supported: info.version >= Version(4, 2, None, "")
                 || info.is_extension_supported("GL_ARB_texture_storage"),
             sampler_objects_supported: info.version >= Version(3, 3, None, "")
                 || info.is_extension_supported("GL_ARB_sampler_objects"),
+            image_load_store_supported: info.version >= Version(4, 2, None, "")
+                || info.is_extension_supported("GL_ARB_shader_image_load_store"),
+            draw_buffer_blend_supported: info.version >= Version(4, 0, None, "")
+                || info.is_extension_supported("GL_ARB_draw_buffers_blend"),
+            depth_clamp_supported: info.version >= Version(3, 2, None, "")
+                || info.is_extension_supported("GL_ARB_depth_clamp"),
+            conditional_render_supported: info.version >= Version(3, 0, None, "")
+                || info.is_extension_supported("GL_NV_conditional_render"),
+            debug_label_supported: info.version >= Version(4, 3, None, "")
+                || info.is_extension_supported("GL_KHR_debug"),
+            copy_buffer_supported: info.version >= Version(3, 1, None, "")
+                || info.is_extension_supported("GL_ARB_copy_buffer"),
+            copy_image_supported: info.version >= Version(4, 3, None, "")
+                || info.is_extension_supported("GL_ARB_copy_image"),
+            clear_buffer_supported: info.version >= Version(4, 3, None, "")
+                || info.is_extension_supported("GL_ARB_clear_buffer_object"),
+            float_color_buffer_supported: info.version >= Version(3, 0, None, "")
+                || info.is_extension_supported("GL_ARB_color_buffer_float"),
+            draw_base_vertex_supported: info.version >= Version(3, 2, None, "")
+                || info.is_extension_supported("GL_ARB_draw_elements_base_vertex"),
+            instance_draw_supported: info.version >= Version(3, 1, None, "")
+                || info.is_extension_supported("GL_ARB_draw_instanced"),
+            instance_rate_supported: info.version >= Version(3, 3, None, "")
+                || info.is_extension_supported("GL_ARB_instanced_arrays"),
+            robustness_supported: info.version >= Version(4, 5, None, "")
+                || info.is_extension_supported("GL_KHR_robustness")
+                || info.is_extension_supported("GL_ARB_robustness"),
+            parallel_compile_supported: info.is_extension_supported("GL_KHR_parallel_shader_compile"),
+            texture_view_supported: info.version >= Version(4, 3, None, "")
+                || info.is_extension_supported("GL_ARB_texture_view"),
+            buffer_storage_supported: info.version >= Version(4, 4, None, "")
+                || info.is_extension_supported("GL_ARB_buffer_storage"),
+            // the core entry point only, GL_ARB_indirect_parameters has its own
+            indirect_count_supported: info.version >= Version(4, 6, None, ""),
+            timer_query_supported: info.version >= Version(3, 3, None, "")
+                || info.is_extension_supported("GL_ARB_timer_query"),
+            buffer_texture_supported: info.version >= Version(3, 1, None, "")
+                || info.is_extension_supported("GL_ARB_texture_buffer_object"),
         };
+        let robustness_arb = !(info.version >= Version(4, 5, None, "")
+            || info.is_extension_supported("GL_KHR_robustness"));
+
         GlBackEnd {
             caps: caps,
             info: info,
+            stats: super::FrameStats::new(),
+            imported_buffers: HashSet::new(),
+            imported_textures: HashSet::new(),
+            immutable_buffers: HashMap::new(),
+            deletions: DeletionQueue::new(),
+            programs: HashMap::new(),
+            robustness_arb: robustness_arb,
+            validation: cfg!(not(ndebug)),
+            max_batch_size: None,
+        }
+    }
+
+    /// Enable or disable the validation of the draw lists on `submit`, which
+    /// reports the first draw call missing a resource of its program and
+    /// skips the whole list. Enabled by default in debug builds.
+    pub fn set_validation(&mut self, enable: bool) {
+        self.validation = enable;
+    }
+
+    fn remember_program(&mut self, prog: &Result<::ProgramHandle, ()>) {
+        match *prog {
+            Ok(ref handle) => {
+                self.programs.insert(handle.get_name(), handle.get_info().clone());
+            },
+            Err(_) => (),
+        }
+    }
+
+    /// Set the number of frames to wait for between buffer swaps, using the
+    /// platform swap control extension loaded through the provider: 1 enables
+    /// vertical sync, 0 disables it, and -1 requests adaptive sync where it is
+    /// available, falling back to the vertical sync otherwise. Returns false
+    /// if the platform has no swap control accepting the interval.
+    pub fn set_swap_interval(&mut self, provider: &super::GlProvider, interval: i32) -> bool {
+        match set_swap_interval_with(provider, interval) {
+            Ok(name) => {
+                info!("Swap interval set to {} with {}", interval, name);
+                true
+            },
+            Err(()) => {
+                warn!("Swap interval {} is not supported by the platform", interval);
+                false
+            },
+        }
+    }
+
+    fn set_label(&mut self, identifier: gl::types::GLenum, name: gl::types::GLuint, label: &str) {
+        if self.caps.debug_label_supported {
+            unsafe {
+                gl::ObjectLabel(identifier, name, label.len() as gl::types::GLsizei,
+                                label.as_ptr() as *const gl::types::GLchar);
+            }
         }
     }
 
@@ -282,6 +585,15 @@ impl GlBackEnd {
         &self.info
     }
 
+    fn get_buffer_size(&mut self, target: gl::types::GLenum, buffer: Buffer) -> uint {
+        let mut size = 0 as gl::types::GLint;
+        gl::BindBuffer(target, buffer);
+        unsafe {
+            gl::GetBufferParameteriv(target, gl::BUFFER_SIZE, &mut size);
+        }
+        size as uint
+    }
+
     fn update_buffer_internal(&mut self, buffer: Buffer, data: &super::Blob,
                               usage: super::BufferUsage) {
         gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
@@ -297,12 +609,36 @@ impl GlBackEnd {
         }
     }
 
+    /// Update a buffer, only within its storage if it is immutable.
+    fn update_buffer_checked(&mut self, name: Buffer, data: &super::Blob,
+                             usage: super::BufferUsage) {
+        match self.immutable_buffers.find_copy(&name) {
+            Some((size, flags)) => match check_storage_update(size, flags, data.get_size()) {
+                Ok(()) => {
+                    gl::BindBuffer(gl::ARRAY_BUFFER, name);
+                    unsafe {
+                        gl::BufferSubData(gl::ARRAY_BUFFER, 0,
+                                          data.get_size() as gl::types::GLsizeiptr,
+                                          data.get_address() as *const gl::types::GLvoid);
+                    }
+                },
+                Err(e) => error!("Ignored update of the immutable buffer {}: {}", name, e),
+            },
+            None => self.update_buffer_internal(name, data, usage),
+        }
+    }
+
     fn process(&mut self, cmd: &super::Command) {
         match *cmd {
             super::Clear(ref data) => {
+                // the masks and the scissor of the last draw state would
+                // restrict the clear, they get reset by the next draw call.
+                // The clear values are carried by the command and set each
+                // time, so they don't depend on the previous clears.
+                gl::Disable(gl::SCISSOR_TEST);
                 let mut flags = match data.color {
-                    //gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
                     Some(super::target::Color([r,g,b,a])) => {
+                        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
                         gl::ClearColor(r, g, b, a);
                         gl::COLOR_BUFFER_BIT
                     },
@@ -320,6 +656,20 @@ impl GlBackEnd {
                 });
                 gl::Clear(flags);
             },
+            super::ClearColorInt(index, color) => {
+                gl::Disable(gl::SCISSOR_TEST);
+                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+                unsafe {
+                    gl::ClearBufferiv(gl::COLOR, index as gl::types::GLint, color.as_ptr());
+                }
+            },
+            super::ClearColorUint(index, color) => {
+                gl::Disable(gl::SCISSOR_TEST);
+                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+                unsafe {
+                    gl::ClearBufferuiv(gl::COLOR, index as gl::types::GLint, color.as_ptr());
+                }
+            },
             super::BindProgram(program) => {
                 gl::UseProgram(program);
             },
@@ -330,7 +680,7 @@ impl GlBackEnd {
                     error!("Ignored VAO bind command: {}", array_buffer)
                 }
             },
-            super::BindAttribute(slot, buffer, count, el_type, stride, offset) => {
+            super::BindAttribute(slot, buffer, count, el_type, stride, offset, rate) => {
                 let gl_type = match el_type {
                     a::Int(_, a::U8, a::Unsigned)  => gl::UNSIGNED_BYTE,
                     a::Int(_, a::U8, a::Signed)    => gl::BYTE,
@@ -376,6 +726,12 @@ impl GlBackEnd {
                     },
                     _ => ()
                 }
+                if self.caps.instance_rate_supported {
+                    gl::VertexAttribDivisor(slot as gl::types::GLuint,
+                                            rate as gl::types::GLuint);
+                } else if rate != 0 {
+                    error!("Instanced arrays are not supported, slot {} is per vertex", slot);
+                }
                 gl::EnableVertexAttribArray(slot as gl::types::GLuint);
             },
             super::BindIndex(buffer) => {
@@ -407,14 +763,42 @@ impl GlBackEnd {
                 gl::UniformBlockBinding(program, slot as gl::types::GLuint, loc as gl::types::GLuint);
                 gl::BindBufferBase(gl::UNIFORM_BUFFER, loc as gl::types::GLuint, buffer);
             },
+            super::BindUniformBlockRange(program, slot, loc, buffer, offset, size) => {
+                let alignment = self.caps.uniform_buffer_offset_alignment;
+                if !is_aligned(offset, alignment) {
+                    error!("Ignored uniform block bind at offset {}, the alignment is {}",
+                           offset, alignment);
+                    return
+                }
+                gl::UniformBlockBinding(program, slot as gl::types::GLuint, loc as gl::types::GLuint);
+                gl::BindBufferRange(gl::UNIFORM_BUFFER, loc as gl::types::GLuint, buffer,
+                                    offset as gl::types::GLintptr,
+                                    size as gl::types::GLsizeiptr);
+            },
             super::BindUniform(loc, uniform) => {
                 shade::bind_uniform(loc as gl::types::GLint, uniform);
             },
             super::BindTexture(slot, kind, texture, sampler) => {
+                if slot as uint >= self.caps.max_texture_units {
+                    error!("Ignored texture bind to unit {}, only {} units are supported",
+                           slot, self.caps.max_texture_units);
+                    return
+                }
+                match sampler {
+                    Some(::Handle(_, ref info))
+                        if kind == ::tex::TextureRect && !info.is_rect_compatible() => {
+                        error!("Ignored texture bind of rectangle texture {} with \
+                                incompatible sampler {}", texture, info);
+                        return
+                    },
+                    _ => (),
+                }
                 let anchor = tex::bind_texture(
                     gl::TEXTURE0 + slot as gl::types::GLenum,
                     kind, texture);
                 match sampler {
+                    // buffer textures have no sampler state
+                    Some(_) if kind == ::tex::TextureBuffer => (),
                     Some(::Handle(sam, ref info)) => {
                         if self.caps.sampler_objects_supported {
                             gl::BindSampler(slot as gl::types::GLenum, sam);
@@ -426,6 +810,22 @@ impl GlBackEnd {
                     None => ()
                 }
             },
+            super::BindImage(slot, texture, level, access, format) => {
+                if !self.caps.image_load_store_supported {
+                    error!("Ignored image bind command: {}", texture);
+                    return
+                }
+                let gl_access = match access {
+                    super::tex::ReadOnly  => gl::READ_ONLY,
+                    super::tex::WriteOnly => gl::WRITE_ONLY,
+                    super::tex::ReadWrite => gl::READ_WRITE,
+                };
+                match tex::format_to_gl(format) {
+                    Ok(gl_format) => gl::BindImageTexture(slot as gl::types::GLuint, texture,
+                        level as gl::types::GLint, gl::TRUE, 0, gl_access, gl_format),
+                    Err(_) => error!("Unsupported image format: {}", format),
+                }
+            },
             super::SetPrimitiveState(prim) => {
                 state::bind_primitive(prim);
             },
@@ -439,41 +839,171 @@ impl GlBackEnd {
                 state::bind_stencil(stencil, cull);
                 state::bind_depth(depth);
             },
+            super::SetDepthRange(range) => {
+                state::bind_depth_range(range);
+            },
+            super::SetClipPlanes(mask) => {
+                state::bind_clip_planes(mask);
+            },
+            super::SetDepthClamp(enable) => {
+                if self.caps.depth_clamp_supported {
+                    state::bind_depth_clamp(enable);
+                } else if enable {
+                    error!("Ignored depth clamp command");
+                }
+            },
             super::SetBlendState(blend) => {
                 state::bind_blend(blend);
             },
+            super::SetBlendTarget(index, blend) => {
+                if self.caps.draw_buffer_blend_supported {
+                    state::bind_blend_target(index, blend);
+                } else {
+                    error!("Ignored blend state of the color target {}", index);
+                }
+            },
             super::SetColorMask(mask) => {
                 state::bind_color_mask(mask);
             },
+            super::SetLogicOp(op) => {
+                state::bind_logic_op(op);
+            },
+            super::BeginOcclusionQuery(query) => {
+                gl::BeginQuery(gl::SAMPLES_PASSED, query);
+            },
+            super::EndOcclusionQuery => {
+                gl::EndQuery(gl::SAMPLES_PASSED);
+            },
+            super::QueryTimestamp(query) => {
+                if !self.caps.timer_query_supported {
+                    error!("Ignored timestamp query {}, not supported", query);
+                    return
+                }
+                gl::QueryCounter(query, gl::TIMESTAMP);
+            },
+            super::BeginConditional(query, mode) => {
+                if !self.caps.conditional_render_supported {
+                    error!("Ignored conditional render on query {}", query);
+                    return
+                }
+                let gl_mode = match mode {
+                    super::ConditionalWait         => gl::QUERY_WAIT,
+                    super::ConditionalNoWait       => gl::QUERY_NO_WAIT,
+                    super::ConditionalRegionWait   => gl::QUERY_BY_REGION_WAIT,
+                    super::ConditionalRegionNoWait => gl::QUERY_BY_REGION_NO_WAIT,
+                };
+                gl::BeginConditionalRender(query, gl_mode);
+            },
+            super::EndConditional => {
+                if self.caps.conditional_render_supported {
+                    gl::EndConditionalRender();
+                }
+            },
             super::UpdateBuffer(buffer, ref data) => {
-                self.update_buffer_internal(buffer, *data, super::UsageDynamic);
+                self.update_buffer_checked(buffer, *data, super::UsageDynamic);
             },
             super::UpdateTexture(kind, texture, image_info, ref data) => {
-                match tex::update_texture(kind, texture, &image_info, *data) {
+                match tex::update_texture(kind, texture, &image_info, *data, None) {
                     Ok(_) => (),
-                    Err(_) => unimplemented!(),
+                    Err(e) => error!("Ignored the update of texture {}: {}", texture, e),
                 }
             },
-            super::Draw(prim_type, start, count) => {
-                gl::DrawArrays(
-                    primitive_to_gl(prim_type),
-                    start as gl::types::GLsizei,
-                    count as gl::types::GLsizei
-                );
+            super::Draw(prim_type, start, count, instances) => {
+                match instances {
+                    None => gl::DrawArrays(
+                        primitive_to_gl(prim_type),
+                        start as gl::types::GLsizei,
+                        count as gl::types::GLsizei
+                    ),
+                    Some(num) if self.caps.instance_draw_supported => gl::DrawArraysInstanced(
+                        primitive_to_gl(prim_type),
+                        start as gl::types::GLsizei,
+                        count as gl::types::GLsizei,
+                        num as gl::types::GLsizei
+                    ),
+                    Some(_) => {
+                        error!("Ignored instanced draw, not supported");
+                        return
+                    },
+                }
                 self.check();
             },
-            super::DrawIndexed(prim_type, index_type, start, count) => {
+            super::DrawIndexed(prim_type, index_type, start, count, base, instances) => {
                 let (offset, gl_index) = match index_type {
                     a::U8  => (start * 1u32, gl::UNSIGNED_BYTE),
                     a::U16 => (start * 2u32, gl::UNSIGNED_SHORT),
                     a::U32 => (start * 4u32, gl::UNSIGNED_INT),
                 };
+                if instances.is_some() && !self.caps.instance_draw_supported {
+                    error!("Ignored instanced draw, not supported");
+                    return
+                }
+                if base != 0 && !self.caps.draw_base_vertex_supported {
+                    error!("Ignored indexed draw with base vertex {}, not supported", base);
+                    return
+                }
+                match (base, instances) {
+                    (0, None) => unsafe {
+                        gl::DrawElements(
+                            primitive_to_gl(prim_type),
+                            count as gl::types::GLsizei,
+                            gl_index,
+                            offset as *const gl::types::GLvoid
+                        );
+                    },
+                    (_, None) => unsafe {
+                        gl::DrawElementsBaseVertex(
+                            primitive_to_gl(prim_type),
+                            count as gl::types::GLsizei,
+                            gl_index,
+                            offset as *const gl::types::GLvoid,
+                            base as gl::types::GLint
+                        );
+                    },
+                    (0, Some(num)) => unsafe {
+                        gl::DrawElementsInstanced(
+                            primitive_to_gl(prim_type),
+                            count as gl::types::GLsizei,
+                            gl_index,
+                            offset as *const gl::types::GLvoid,
+                            num as gl::types::GLsizei
+                        );
+                    },
+                    (_, Some(num)) => unsafe {
+                        gl::DrawElementsInstancedBaseVertex(
+                            primitive_to_gl(prim_type),
+                            count as gl::types::GLsizei,
+                            gl_index,
+                            offset as *const gl::types::GLvoid,
+                            num as gl::types::GLsizei,
+                            base as gl::types::GLint
+                        );
+                    },
+                }
+                self.check();
+            },
+            super::DrawIndexedIndirectCount(prim_type, index_type, commands, offset,
+                                            count, count_offset, max_count, stride) => {
+                if !self.caps.indirect_count_supported {
+                    error!("Ignored indirect draw with the count from buffer {}, not supported",
+                           count);
+                    return
+                }
+                let gl_index = match index_type {
+                    a::U8  => gl::UNSIGNED_BYTE,
+                    a::U16 => gl::UNSIGNED_SHORT,
+                    a::U32 => gl::UNSIGNED_INT,
+                };
+                gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, commands);
+                gl::BindBuffer(gl::PARAMETER_BUFFER, count);
                 unsafe {
-                    gl::DrawElements(
+                    gl::MultiDrawElementsIndirectCount(
                         primitive_to_gl(prim_type),
-                        count as gl::types::GLsizei,
                         gl_index,
-                        offset as *const gl::types::GLvoid
+                        offset as *const gl::types::GLvoid,
+                        count_offset as gl::types::GLintptr,
+                        max_count as gl::types::GLsizei,
+                        stride as gl::types::GLsizei
                     );
                 }
                 self.check();
@@ -493,7 +1023,7 @@ impl super::ApiBackEnd<DrawList> for GlBackEnd {
             gl::GenBuffers(1, &mut name);
         }
         info!("\tCreated buffer {}", name);
-        ::Handle(name, ())
+        ::Handle(name, ::BufferInfo)
     }
 
     fn create_array_buffer(&mut self) -> Result<ArrayBuffer, ()> {
@@ -526,6 +1056,38 @@ impl super::ApiBackEnd<DrawList> for GlBackEnd {
             let level = if prog.is_err() { log::ERROR } else { log::WARN };
             log!(level, "\tProgram link log: {}", log);
         });
+        self.remember_program(&prog);
+        prog
+    }
+
+    fn relink_program(&mut self, prog: &::ProgramHandle, shaders: &[::ShaderHandle])
+                      -> Result<(), ()> {
+        let (result, log) = shade::relink_program(&self.caps, prog, shaders);
+        log.map(|log| {
+            let level = if result.is_err() { log::ERROR } else { log::WARN };
+            log!(level, "\tProgram link log: {}", log);
+        });
+        result
+    }
+
+    fn link_program_async(&mut self, shaders: &[::ShaderHandle]) -> ::PendingProgram {
+        ::PendingProgram(shade::start_program(shaders))
+    }
+
+    fn is_program_ready(&mut self, pending: &::PendingProgram) -> bool {
+        let &::PendingProgram(name) = pending;
+        // without the extension, the link status query waits for the linker
+        !self.caps.parallel_compile_supported || shade::is_program_ready(name)
+    }
+
+    fn finish_program(&mut self, pending: ::PendingProgram) -> Result<::ProgramHandle, ()> {
+        let ::PendingProgram(name) = pending;
+        let (prog, log) = shade::finish_program(&self.caps, name);
+        log.map(|log| {
+            let level = if prog.is_err() { log::ERROR } else { log::WARN };
+            log!(level, "\tProgram link log: {}", log);
+        });
+        self.remember_program(&prog);
         prog
     }
 
@@ -539,10 +1101,15 @@ impl super::ApiBackEnd<DrawList> for GlBackEnd {
     }
 
     fn create_surface(&mut self, info: ::tex::SurfaceInfo) -> Result<::SurfaceHandle, ::SurfaceError> {
+        if info.format.is_float() && !self.caps.float_color_buffer_supported {
+            error!("Float color buffers are not supported: {}", info.format);
+            return Err(::UnsupportedSurfaceFormat)
+        }
         tex::make_surface(&info).map(|suf| ::Handle(suf, info))
     }
 
     fn create_texture(&mut self, info: ::tex::TextureInfo) -> Result<::TextureHandle, ::TextureError> {
+        try!(info.check_kind());
         let name = if self.caps.immutable_storage_supported {
             tex::make_with_storage(&info)
         } else {
@@ -551,6 +1118,41 @@ impl super::ApiBackEnd<DrawList> for GlBackEnd {
         name.map(|tex| ::Handle(tex, info))
     }
 
+    fn create_texture_immutable(&mut self, info: ::tex::TextureInfo)
+                                -> Result<::TextureHandle, ::TextureError> {
+        if !self.caps.immutable_storage_supported {
+            return Err(::UnsupportedTextureStorage)
+        }
+        try!(info.check_kind());
+        tex::make_with_storage(&info).map(|tex| ::Handle(tex, info))
+    }
+
+    fn create_texture_view(&mut self, src: &::TextureHandle, format: ::tex::Format,
+                           levels: (u8, u8), layers: (u16, u16))
+                           -> Result<::TextureHandle, ::TextureError> {
+        // views can only be made of immutable storage
+        if !self.caps.texture_view_supported || !self.caps.immutable_storage_supported {
+            return Err(::UnsupportedTextureView)
+        }
+        let info = try!(src.get_info().to_view_info(format, levels, layers));
+        let name = try!(tex::make_view(src.get_name(), &info, levels.val0(), layers.val0()));
+        Ok(::Handle(name, info))
+    }
+
+    fn create_buffer_texture(&mut self, buffer: &::BufferHandle, format: ::tex::Format)
+                             -> Result<::TextureHandle, ::TextureError> {
+        if !self.caps.buffer_texture_supported {
+            return Err(::UnsupportedTextureBuffer)
+        }
+        let name = try!(tex::make_buffer_texture(buffer.get_name(), format));
+        Ok(::Handle(name, ::tex::TextureInfo {
+            mipmap_range: (0, 0),
+            kind: ::tex::TextureBuffer,
+            format: format,
+            .. ::tex::TextureInfo::new()
+        }))
+    }
+
     fn create_sampler(&mut self, info: ::tex::SamplerInfo) -> ::SamplerHandle {
         let sam = if self.caps.sampler_objects_supported {
             tex::make_sampler(&info)
@@ -560,11 +1162,50 @@ impl super::ApiBackEnd<DrawList> for GlBackEnd {
         ::Handle(sam, info)
     }
 
+    fn create_query(&mut self) -> ::QueryHandle {
+        let mut name = 0 as Query;
+        unsafe {
+            gl::GenQueries(1, &mut name);
+        }
+        info!("\tCreated query {}", name);
+        ::Handle(name, ::QueryInfo)
+    }
+
+    fn create_buffer_storage(&mut self, size: uint, flags: super::StorageFlags)
+                             -> Result<::BufferHandle, ::BufferError> {
+        if !self.caps.buffer_storage_supported {
+            error!("Immutable buffer storage is not supported");
+            return Err(::UnsupportedBufferOperation)
+        }
+        let buffer = self.create_buffer();
+        gl::BindBuffer(gl::ARRAY_BUFFER, buffer.get_name());
+        unsafe {
+            gl::BufferStorage(gl::ARRAY_BUFFER, size as gl::types::GLsizeiptr,
+                              ::std::ptr::null(), storage_flags_to_gl(flags));
+        }
+        self.immutable_buffers.insert(buffer.get_name(), (size, flags));
+        Ok(buffer)
+    }
+
+    fn import_buffer(&mut self, name: Buffer) -> ::BufferHandle {
+        info!("\tImported buffer {}", name);
+        self.imported_buffers.insert(name);
+        ::Handle(name, ::BufferInfo)
+    }
+
+    fn import_texture(&mut self, name: Texture, info: ::tex::TextureInfo) -> ::TextureHandle {
+        info!("\tImported texture {}", name);
+        self.imported_textures.insert(name);
+        ::Handle(name, info)
+    }
+
     fn delete_buffer(&mut self, handle: ::BufferHandle) {
         let name = handle.get_name();
-        unsafe {
-            gl::DeleteBuffers(1, &name);
+        if self.imported_buffers.remove(&name) {
+            return
         }
+        self.immutable_buffers.remove(&name);
+        self.deletions.buffers.push(name);
     }
 
     fn delete_shader(&mut self, handle: ::ShaderHandle) {
@@ -572,51 +1213,253 @@ impl super::ApiBackEnd<DrawList> for GlBackEnd {
     }
 
     fn delete_program(&mut self, handle: ::ProgramHandle) {
+        self.programs.remove(&handle.get_name());
         gl::DeleteProgram(handle.get_name());
     }
 
     fn delete_surface(&mut self, handle: ::SurfaceHandle) {
         let name = handle.get_name();
-        unsafe {
-            gl::DeleteRenderbuffers(1, &name);
-        }
+        self.deletions.surfaces.push(name);
     }
 
     fn delete_texture(&mut self, handle: ::TextureHandle) {
         let name = handle.get_name();
-        unsafe {
-            gl::DeleteTextures(1, &name);
+        if self.imported_textures.remove(&name) {
+            return
         }
+        self.deletions.textures.push(name);
     }
 
     fn delete_sampler(&mut self, handle: ::SamplerHandle) {
         let name = handle.get_name();
-        unsafe {
-            gl::DeleteSamplers(1, &name);
+        self.deletions.samplers.push(name);
+    }
+
+    fn delete_query(&mut self, handle: ::QueryHandle) {
+        let name = handle.get_name();
+        self.deletions.queries.push(name);
+    }
+
+    fn flush_deletions(&mut self) {
+        if !self.deletions.is_empty() {
+            self.deletions.flush();
         }
     }
 
+    fn set_buffer_label(&mut self, handle: &::BufferHandle, label: &str) {
+        self.set_label(gl::BUFFER, handle.get_name(), label);
+    }
+
+    fn set_texture_label(&mut self, handle: &::TextureHandle, label: &str) {
+        self.set_label(gl::TEXTURE, handle.get_name(), label);
+    }
+
+    fn set_program_label(&mut self, handle: &::ProgramHandle, label: &str) {
+        self.set_label(gl::PROGRAM, handle.get_name(), label);
+    }
+
     fn update_buffer(&mut self, buffer: ::BufferHandle, data: &super::Blob,
                      usage: super::BufferUsage) {
-        self.update_buffer_internal(buffer.get_name(), data, usage);
+        self.update_buffer_checked(buffer.get_name(), data, usage);
+    }
+
+    fn copy_buffer(&mut self, src: ::BufferHandle, src_offset: uint,
+                   dst: ::BufferHandle, dst_offset: uint, size: uint)
+                   -> Result<(), ::BufferError> {
+        if !self.caps.copy_buffer_supported {
+            error!("Buffer copy is not supported");
+            return Err(::UnsupportedBufferOperation)
+        }
+        let src_size = self.get_buffer_size(gl::COPY_READ_BUFFER, src.get_name());
+        let dst_size = self.get_buffer_size(gl::COPY_WRITE_BUFFER, dst.get_name());
+        try!(check_range(src_size, src_offset, size));
+        try!(check_range(dst_size, dst_offset, size));
+        gl::CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER,
+                              src_offset as gl::types::GLintptr,
+                              dst_offset as gl::types::GLintptr,
+                              size as gl::types::GLsizeiptr);
+        Ok(())
+    }
+
+    fn set_block_member(&mut self, buf: &::BufferHandle, block: &::shade::BlockVar, name: &str,
+                        value: ::shade::UniformValue)
+                        -> Result<(), ::shade::BlockMemberError> {
+        let (offset, data) = try!(block.encode_member(name, value));
+        let target = gl::UNIFORM_BUFFER;
+        let size = self.get_buffer_size(target, buf.get_name());
+        if check_range(size, offset, data.len()).is_err() {
+            return Err(::shade::ErrorMemberOutOfRange)
+        }
+        unsafe {
+            gl::BufferSubData(target, offset as gl::types::GLintptr,
+                              data.len() as gl::types::GLsizeiptr,
+                              data.as_ptr() as *const gl::types::GLvoid);
+        }
+        Ok(())
+    }
+
+    fn clear_buffer(&mut self, buf: ::BufferHandle, value: u32) {
+        let target = gl::ARRAY_BUFFER;
+        if self.caps.clear_buffer_supported {
+            gl::BindBuffer(target, buf.get_name());
+            unsafe {
+                gl::ClearBufferData(target, gl::R32UI, gl::RED_INTEGER, gl::UNSIGNED_INT,
+                                    &value as *const u32 as *const gl::types::GLvoid);
+            }
+        } else {
+            let size = self.get_buffer_size(target, buf.get_name());
+            let data = Vec::from_elem(size / mem::size_of::<u32>(), value);
+            unsafe {
+                gl::BufferSubData(target, 0,
+                                  (data.len() * mem::size_of::<u32>()) as gl::types::GLsizeiptr,
+                                  data.as_ptr() as *const gl::types::GLvoid);
+            }
+        }
     }
 
     fn update_texture(&mut self, texture: &::TextureHandle, img: &::tex::ImageInfo,
                       data: &super::Blob) -> Result<(), ::TextureError> {
-        tex::update_texture(texture.get_info().kind, texture.get_name(), img, data)
+        self.update_texture_sub(texture, img, data, None)
+    }
+
+    fn update_texture_sub(&mut self, texture: &::TextureHandle, img: &::tex::ImageInfo,
+                          data: &super::Blob, src_row_length: Option<u16>)
+                          -> Result<(), ::TextureError> {
+        try!(texture.get_info().check_update(img));
+        tex::update_texture(texture.get_info().kind, texture.get_name(), img, data,
+                            src_row_length)
+    }
+
+    fn set_unpack_alignment(&mut self, alignment: u8) {
+        match alignment {
+            1 | 2 | 4 | 8 => gl::PixelStorei(gl::UNPACK_ALIGNMENT, alignment as gl::types::GLint),
+            _ => error!("Ignored invalid unpack alignment {}", alignment),
+        }
+    }
+
+    fn set_pack_alignment(&mut self, alignment: u8) {
+        match alignment {
+            1 | 2 | 4 | 8 => gl::PixelStorei(gl::PACK_ALIGNMENT, alignment as gl::types::GLint),
+            _ => error!("Ignored invalid pack alignment {}", alignment),
+        }
+    }
+
+    fn copy_texture(&mut self, src: &::TextureHandle, src_img: &::tex::ImageInfo,
+                    dst: &::TextureHandle, dst_level: u8, (x, y, z): (u16, u16, u16))
+                    -> Result<(), ::TextureError> {
+        let dst_img = ::tex::ImageInfo {
+            xoffset: x,
+            yoffset: y,
+            zoffset: z,
+            mipmap: dst_level,
+            .. *src_img
+        };
+        if !src.get_info().contains(src_img) || !dst.get_info().contains(&dst_img) {
+            return Err(::TextureOutOfRange)
+        }
+        if self.caps.copy_image_supported {
+            tex::copy_image(src.get_info().kind, src.get_name(), src_img,
+                            dst.get_info().kind, dst.get_name(), &dst_img);
+        } else {
+            let fbos = [self.create_frame_buffer(), self.create_frame_buffer()];
+            tex::blit_image((fbos[0], fbos[1]), src.get_info().kind, src.get_name(), src_img,
+                            dst.get_info().kind, dst.get_name(), &dst_img);
+            unsafe {
+                gl::DeleteFramebuffers(2, fbos.as_ptr());
+            }
+        }
+        Ok(())
     }
 
     fn submit(&mut self, list: &DrawList) {
         //TODO: clear state, when we have caching
-        for com in list.iter() {
+        if self.validation {
+            match super::validate::validate(list.iter(), &self.programs) {
+                Ok(()) => (),
+                Err(e) => {
+                    error!("Invalid draw list, skipped: {}", e);
+                    self.flush_deletions();
+                    return
+                },
+            }
+        }
+        for (i, com) in list.iter().enumerate() {
+            match self.max_batch_size {
+                Some(size) if i > 0 && i % size == 0 => gl::Flush(),
+                _ => (),
+            }
+            self.stats.record(com);
             self.process(com);
         }
+        self.flush_deletions();
+    }
+
+    fn check_device_reset(&mut self) -> Option<super::ResetReason> {
+        if !self.caps.robustness_supported {
+            None
+        } else if self.robustness_arb {
+            reset_status_to_reason(gl::GetGraphicsResetStatusARB())
+        } else {
+            reset_status_to_reason(gl::GetGraphicsResetStatus())
+        }
+    }
+
+    fn get_timestamp(&mut self, handle: &::QueryHandle) -> Option<u64> {
+        if !self.caps.timer_query_supported {
+            return None
+        }
+        let name = handle.get_name();
+        let mut available = gl::FALSE as gl::types::GLuint;
+        unsafe {
+            gl::GetQueryObjectuiv(name, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        if available == gl::FALSE as gl::types::GLuint {
+            return None
+        }
+        let mut time = 0 as gl::types::GLuint64;
+        unsafe {
+            gl::GetQueryObjectui64v(name, gl::QUERY_RESULT, &mut time);
+        }
+        Some(time as u64)
+    }
+
+    fn get_frame_stats(&self) -> super::FrameStats {
+        self.stats.clone()
+    }
+
+    fn reset_frame_stats(&mut self) {
+        self.stats = super::FrameStats::new();
+    }
+
+    fn set_max_batch_size(&mut self, size: Option<uint>) {
+        self.max_batch_size = size.map(|s| cmp::max(s, 1));
+    }
+
+    fn flush(&mut self) {
+        gl::Flush();
+    }
+
+    fn finish(&mut self) {
+        self.flush_deletions();
+        gl::Finish();
+    }
+}
+
+impl Drop for GlBackEnd {
+    fn drop(&mut self) {
+        self.flush_deletions();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Version;
+    use std::{mem, ptr};
+    use super::{Version, DeletionQueue, check_range, is_aligned, reset_status_to_reason};
+    use super::{check_storage_update, set_swap_interval_with};
+    use super::libc;
+    use super::tex::{get_row_alignment, get_image_size, mipmap_range_params, lod_range_params};
+    use super::{gl, DrawList};
+    use ListTrait = draw::DrawList;
 
     #[test]
     fn test_version_parse() {
@@ -632,4 +1475,253 @@ mod tests {
         assert_eq!(Version::parse("1.2.3.h3l1o. W0rld"), Ok(Version(1, 2, Some(3), "W0rld")));
         assert_eq!(Version::parse("1.2.3 h3l1o. W0rld"), Ok(Version(1, 2, Some(3), "h3l1o. W0rld")));
     }
+
+    #[test]
+    fn test_check_range() {
+        assert_eq!(check_range(16, 4, 8), Ok(()));
+        assert_eq!(check_range(16, 8, 8), Ok(()));
+        assert_eq!(check_range(16, 12, 8), Err(::BufferOutOfRange));
+        assert_eq!(check_range(16, -1u, 2), Err(::BufferOutOfRange));
+    }
+
+    #[test]
+    fn test_storage_update() {
+        let dynamic = ::StorageDynamic | ::StorageMapWrite;
+        assert_eq!(check_storage_update(64, dynamic, 64), Ok(()));
+        assert_eq!(check_storage_update(64, dynamic, 128), Err(::BufferOutOfRange));
+        assert_eq!(check_storage_update(64, ::StorageMapWrite, 16),
+                   Err(::UnsupportedBufferOperation));
+    }
+
+    #[test]
+    fn test_row_alignment() {
+        // a 3-wide RGB8 row takes 9 bytes
+        assert_eq!(get_row_alignment(3 * 3), 1);
+        assert_eq!(get_row_alignment(3 * 2), 2);
+        assert_eq!(get_row_alignment(3 * 4), 4);
+        assert_eq!(get_row_alignment(4 * 4), 8);
+    }
+
+    #[test]
+    fn test_image_size() {
+        use tex::{ImageInfo, RGBA8};
+        // a 2x2 crop out of a 4-wide RGBA8 source
+        let img = ImageInfo { width: 2, height: 2, format: RGBA8, .. ImageInfo::new() };
+        assert_eq!(get_image_size(&img, None), 16);
+        assert_eq!(get_image_size(&img, Some(4)), 24);
+        assert_eq!(get_image_size(&img, Some(3)), 20);
+        // the rows of the crop itself are packed
+        assert_eq!(get_row_alignment(2 * 4), 8);
+    }
+
+    #[test]
+    fn test_blit_attachment() {
+        use tex::{RGBA8, DEPTH24, DEPTH24STENCIL8, STENCIL8};
+        use super::tex::blit_attachment;
+        assert_eq!(blit_attachment(RGBA8), (gl::COLOR_ATTACHMENT0, gl::COLOR_BUFFER_BIT));
+        assert_eq!(blit_attachment(DEPTH24), (gl::DEPTH_ATTACHMENT, gl::DEPTH_BUFFER_BIT));
+        assert_eq!(blit_attachment(DEPTH24STENCIL8), (gl::DEPTH_STENCIL_ATTACHMENT,
+                   gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT));
+        assert_eq!(blit_attachment(STENCIL8), (gl::STENCIL_ATTACHMENT, gl::STENCIL_BUFFER_BIT));
+    }
+
+    #[test]
+    fn test_integer_image() {
+        use attrib::{IntRaw, IntNormalized, IntAsFloat};
+        use tex::{Integer, Unsigned, R, RG, RGBA, ReadWrite};
+        let mut list = DrawList::new();
+        list.bind_image(0, 1, 0, ReadWrite, Integer(R, 32, IntRaw));
+        list.bind_image(1, 2, 0, ReadWrite, Unsigned(RGBA, 16, IntRaw));
+        let formats: Vec<gl::types::GLenum> = list.iter().filter_map(|com| match *com {
+            ::BindImage(_, _, _, _, format) => super::tex::format_to_gl(format).ok(),
+            _ => None,
+        }).collect();
+        assert_eq!(formats, vec![gl::R32I, gl::RGBA16UI]);
+        assert_eq!(super::tex::format_to_gl(Unsigned(RG, 16, IntNormalized)), Ok(gl::RG16));
+        // unsized or unknown integer formats are refused rather than aborting
+        assert_eq!(super::tex::format_to_gl(Integer(R, 32, IntNormalized)), Err(()));
+        assert_eq!(super::tex::format_to_gl(Unsigned(R, 8, IntAsFloat)), Err(()));
+    }
+
+    #[test]
+    fn test_buffer_texture_format() {
+        use attrib::{F16, F32, IntRaw, IntNormalized};
+        use tex::{Float, Integer, Unsigned, R, RG, RGB, RGBA};
+        let formats = [(Float(RGB, F32), gl::RGB32F), (Float(RG, F16), gl::RG16F),
+                       (Integer(R, 8, IntRaw), gl::R8I), (Unsigned(RGB, 32, IntRaw), gl::RGB32UI),
+                       (Unsigned(RGBA, 16, IntNormalized), gl::RGBA16)];
+        // every format accepted for buffer textures has a sized GL format
+        for &(format, expected) in formats.iter() {
+            assert!(format.is_buffer_compatible());
+            assert_eq!(super::tex::format_to_gl(format), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn test_mipmap_params() {
+        use tex::{TextureInfo, SamplerInfo, Trilinear, Tile};
+        let tinfo = TextureInfo { mipmap_range: (2, 6), .. TextureInfo::new() };
+        assert_eq!(mipmap_range_params(tinfo.mipmap_range).as_slice(),
+                   [(gl::TEXTURE_BASE_LEVEL, 2), (gl::TEXTURE_MAX_LEVEL, 6)].as_slice());
+        let mut sinfo = SamplerInfo::new(Trilinear, Tile);
+        sinfo.lod_range = (2.0, 4.5);
+        assert_eq!(lod_range_params(sinfo.lod_range).as_slice(),
+                   [(gl::TEXTURE_MIN_LOD, 2.0), (gl::TEXTURE_MAX_LOD, 4.5)].as_slice());
+    }
+
+    #[test]
+    fn test_deletion_queue() {
+        let mut queue = DeletionQueue::new();
+        assert!(queue.is_empty());
+        for name in range(1u32, 4) {
+            queue.textures.push(name);
+        }
+        assert!(!queue.is_empty());
+        assert_eq!(queue.textures, vec![1, 2, 3]);
+        assert!(queue.buffers.is_empty());
+        queue.flush_with([mock_delete, mock_delete, mock_delete, mock_delete, mock_delete]);
+        // the three textures are deleted by a single call
+        assert_eq!(unsafe { DELETE_CALLS }, (1, 3));
+        assert!(queue.is_empty());
+    }
+
+    /// The number of calls to `mock_delete`, and of the names it was given.
+    static mut DELETE_CALLS: (uint, gl::types::GLsizei) = (0, 0);
+
+    unsafe fn mock_delete(n: gl::types::GLsizei, _: *const gl::types::GLuint) {
+        let (calls, names) = DELETE_CALLS;
+        DELETE_CALLS = (calls + 1, names + n);
+    }
+
+    #[test]
+    fn test_reset_status() {
+        assert_eq!(reset_status_to_reason(gl::NO_ERROR), None);
+        assert_eq!(reset_status_to_reason(gl::GUILTY_CONTEXT_RESET), Some(::ResetGuilty));
+        assert_eq!(reset_status_to_reason(gl::INNOCENT_CONTEXT_RESET), Some(::ResetInnocent));
+        assert_eq!(reset_status_to_reason(gl::UNKNOWN_CONTEXT_RESET), Some(::ResetUnknown));
+    }
+
+    #[test]
+    fn test_uniform_block_range() {
+        let mut list = DrawList::new();
+        list.bind_uniform_block_range(1, 0, 2, 3, 256, 256);
+        let commands: Vec<&::Command> = list.iter().collect();
+        assert_eq!(commands.len(), 1);
+        match *commands[0] {
+            ::BindUniformBlockRange(1, 0, 2, 3, 256, 256) => (),
+            ref other => fail!("Unexpected command {}", other),
+        }
+        assert!(is_aligned(256, 256));
+        assert!(is_aligned(256, 0));
+        assert!(!is_aligned(128, 256));
+    }
+
+    #[test]
+    fn test_clear_values() {
+        use target::{ClearData, Color};
+        let mut list = DrawList::new();
+        let red = Color([1.0, 0.0, 0.0, 1.0]);
+        let blue = Color([0.0, 0.0, 1.0, 1.0]);
+        list.call_clear(ClearData { color: Some(red), depth: None, stencil: None });
+        list.call_clear(ClearData { color: Some(blue), depth: Some(1.0), stencil: None });
+        let colors: Vec<Option<Color>> = list.iter().map(|com| match *com {
+            ::Clear(ref data) => data.color,
+            ref other => fail!("Unexpected command {}", other),
+        }).collect();
+        assert_eq!(colors, vec![Some(red), Some(blue)]);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut world = DrawList::new();
+        world.bind_program(1);
+        world.call_draw(::TriangleList, 0, 3, None);
+        let mut ui = DrawList::new();
+        ui.bind_program(2);
+        ui.call_draw(::TriangleStrip, 0, 4, None);
+        world.append(ui);
+        let commands: Vec<String> = world.iter().map(|com| format!("{}", com)).collect();
+        assert_eq!(commands, vec![
+            "BindProgram(1)".to_string(),
+            "Draw(TriangleList, 0, 3, None)".to_string(),
+            "BindProgram(2)".to_string(),
+            "Draw(TriangleStrip, 0, 4, None)".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_indirect_count() {
+        let mut list = DrawList::new();
+        list.call_draw_indexed_indirect_count(::TriangleList, ::attrib::U16, 3, 0, 4, 16, 64, 20);
+        let commands: Vec<String> = list.iter().map(|com| format!("{}", com)).collect();
+        assert_eq!(commands, vec![
+            "DrawIndexedIndirectCount(TriangleList, U16, 3, 0, 4, 16, 64, 20)".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut list = DrawList::with_capacity(64);
+        let capacity = list.capacity();
+        assert!(capacity >= 64);
+        for _ in range(0u, 3) {
+            list.clear();
+            for i in range(0u32, 32) {
+                list.bind_program(i);
+                list.call_draw(::TriangleList, 0, 3, None);
+            }
+            assert_eq!(list.iter().count(), 64);
+            assert_eq!(list.capacity(), capacity);
+        }
+    }
+
+    static mut SWAP_INTERVAL: libc::c_int = -100;
+
+    extern "system" fn mock_swap_interval(interval: libc::c_int) -> libc::c_int {
+        unsafe { SWAP_INTERVAL = interval; }
+        0
+    }
+
+    /// A provider loading the GLX swap control commands, which all record
+    /// the interval they are given.
+    struct MockProvider {
+        extensions: Vec<&'static str>,
+    }
+
+    impl ::GlProvider for MockProvider {
+        fn get_proc_address(&self, name: &str) -> *const libc::c_void {
+            match name {
+                "glXSwapIntervalMESA" | "glXSwapIntervalSGI" => {
+                    let fun: extern "system" fn(libc::c_int) -> libc::c_int = mock_swap_interval;
+                    unsafe { mem::transmute(fun) }
+                },
+                _ => ptr::null(),
+            }
+        }
+        fn is_extension_supported(&self, extension: &str) -> bool {
+            self.extensions.iter().any(|e| *e == extension)
+        }
+    }
+
+    #[test]
+    fn test_swap_interval() {
+        let last = || unsafe { SWAP_INTERVAL };
+        // the commands can be loaded, but the extensions are not advertised
+        let none = MockProvider { extensions: Vec::new() };
+        assert_eq!(set_swap_interval_with(&none, 1), Err(()));
+        assert_eq!(last(), -100);
+        // SGI can't disable the synchronization
+        let sgi = MockProvider { extensions: vec!["GLX_SGI_swap_control"] };
+        assert_eq!(set_swap_interval_with(&sgi, 1), Ok("glXSwapIntervalSGI"));
+        assert_eq!(last(), 1);
+        assert_eq!(set_swap_interval_with(&sgi, 0), Err(()));
+        assert_eq!(last(), 1);
+        let mesa = MockProvider { extensions: vec!["GLX_SGI_swap_control",
+                                                   "GLX_MESA_swap_control"] };
+        assert_eq!(set_swap_interval_with(&mesa, 0), Ok("glXSwapIntervalMESA"));
+        assert_eq!(last(), 0);
+        // no adaptive sync without the tear control
+        assert_eq!(set_swap_interval_with(&mesa, -1), Ok("glXSwapIntervalMESA"));
+        assert_eq!(last(), 1);
+    }
 }
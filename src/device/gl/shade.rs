@@ -99,6 +99,7 @@ fn get_program_iv(program: super::Program, query: gl::types::GLenum) -> gl::type
 enum StorageType {
     Var(s::BaseType, s::ContainerType),
     Sampler(s::BaseType, s::SamplerType),
+    Image(s::BaseType, s::SamplerType),
     Unknown,
 }
 
@@ -137,6 +138,10 @@ impl StorageType {
 
             // TODO: double matrices
 
+            gl::SAMPLER_BUFFER               => Sampler(s::BaseF32, s::SamplerBuffer),
+            gl::INT_SAMPLER_BUFFER           => Sampler(s::BaseI32, s::SamplerBuffer),
+            gl::UNSIGNED_INT_SAMPLER_BUFFER  => Sampler(s::BaseU32, s::SamplerBuffer),
+
             gl::SAMPLER_1D                   => Sampler(s::BaseF32, s::Sampler1D(s::NoArray, s::NoShadow)),
             gl::SAMPLER_1D_ARRAY             => Sampler(s::BaseF32, s::Sampler1D(s::Array,   s::NoShadow)),
             gl::SAMPLER_1D_SHADOW            => Sampler(s::BaseF32, s::Sampler1D(s::NoArray, s::Shadow)),
@@ -159,6 +164,15 @@ impl StorageType {
 
             // TODO: unsigned samplers
 
+            gl::IMAGE_1D                     => Image(s::BaseF32, s::Sampler1D(s::NoArray, s::NoShadow)),
+            gl::IMAGE_1D_ARRAY               => Image(s::BaseF32, s::Sampler1D(s::Array,   s::NoShadow)),
+            gl::IMAGE_2D                     => Image(s::BaseF32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::IMAGE_2D_ARRAY               => Image(s::BaseF32, s::Sampler2D(s::Array,   s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::IMAGE_3D                     => Image(s::BaseF32, s::Sampler3D),
+            gl::IMAGE_CUBE                   => Image(s::BaseF32, s::SamplerCube(s::NoShadow)),
+            gl::INT_IMAGE_2D                 => Image(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::UNSIGNED_INT_IMAGE_2D        => Image(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+
             _ => Unknown,
         }
     }
@@ -230,13 +244,72 @@ fn query_blocks(caps: &::Capabilities, prog: super::Program) -> Vec<s::BlockVar>
             name: name,
             size: size as uint,
             usage: usage,
+            members: query_block_members(prog, i),
         }
     }).collect()
 }
 
-fn query_parameters(prog: super::Program) -> (Vec<s::UniformVar>, Vec<s::SamplerVar>) {
+fn query_block_members(prog: super::Program, block: gl::types::GLuint) -> Vec<s::BlockMember> {
+    let mut num = 0;
+    unsafe {
+        gl::GetActiveUniformBlockiv(prog, block, gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS, &mut num);
+    }
+    let mut indices = Vec::from_elem(num as uint, 0 as gl::types::GLint);
+    unsafe {
+        gl::GetActiveUniformBlockiv(prog, block, gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+            indices.as_mut_slice().as_mut_ptr());
+    }
+    let indices: Vec<gl::types::GLuint> = indices.iter()
+        .map(|&i| i as gl::types::GLuint).collect();
+    let (types, sizes, row_majors, offsets, array_strides, matrix_strides) = {
+        let query = |pname| {
+            let mut values = Vec::from_elem(num as uint, 0 as gl::types::GLint);
+            unsafe {
+                gl::GetActiveUniformsiv(prog, num as gl::types::GLsizei,
+                    indices.as_slice().as_ptr(), pname, values.as_mut_slice().as_mut_ptr());
+            }
+            values
+        };
+        (query(gl::UNIFORM_TYPE), query(gl::UNIFORM_SIZE), query(gl::UNIFORM_IS_ROW_MAJOR),
+         query(gl::UNIFORM_OFFSET), query(gl::UNIFORM_ARRAY_STRIDE),
+         query(gl::UNIFORM_MATRIX_STRIDE))
+    };
+    let max_len = get_program_iv(prog, gl::ACTIVE_UNIFORM_MAX_LENGTH);
+    let mut name = String::with_capacity(max_len as uint);
+    name.grow(max_len as uint, '\0');
+    indices.iter().enumerate().map(|(k, &i)| {
+        let mut length = 0;
+        unsafe {
+            gl::GetActiveUniformName(prog, i, max_len, &mut length,
+                name.as_slice().as_ptr() as *mut gl::types::GLchar);
+        }
+        let real_name = name.as_slice().slice_to(length as uint).to_string();
+        let (base, container) = match StorageType::new(types[k] as gl::types::GLenum) {
+            Var(b, s::Matrix(_, c, r)) if row_majors[k] != 0 => (b, s::Matrix(s::RowMajor, c, r)),
+            Var(b, c) => (b, c),
+            _ => {
+                error!("Unrecognized member storage: {}", types[k]);
+                (s::BaseF32, s::Single)
+            }
+        };
+        info!("\t\t\tMember '{}' at offset {}\t{}\t{}", real_name, offsets[k], base, container);
+        s::BlockMember {
+            name: real_name,
+            count: sizes[k] as uint,
+            base_type: base,
+            container: container,
+            offset: offsets[k] as uint,
+            array_stride: array_strides[k] as uint,
+            matrix_stride: matrix_strides[k] as uint,
+        }
+    }).collect()
+}
+
+fn query_parameters(prog: super::Program)
+                    -> (Vec<s::UniformVar>, Vec<s::SamplerVar>, Vec<s::ImageVar>) {
     let mut uniforms = Vec::new();
     let mut textures = Vec::new();
+    let mut images = Vec::new();
     let total_num = get_program_iv(prog, gl::ACTIVE_UNIFORMS);
     let indices: Vec<_> = range(0, total_num as gl::types::GLuint).collect();
     let mut block_indices = Vec::from_elem(total_num as uint, 0 as gl::types::GLint);
@@ -281,23 +354,96 @@ fn query_parameters(prog: super::Program) -> (Vec<s::UniformVar>, Vec<s::Sampler
                     sampler_type: sam_type,
                 });
             },
+            Image(base, img_type) => {
+                info!("\t\tImage[{}] = '{}'\t{}\t{}", loc, real_name, base, img_type);
+                images.push(s::ImageVar {
+                    name: real_name,
+                    location: loc as uint,
+                    base_type: base,
+                    image_type: img_type,
+                });
+            },
             Unknown => {
                 error!("Unrecognized uniform storage: {}", storage);
             },
         }
     }
-    (uniforms, textures)
+    (uniforms, textures, images)
 }
 
 pub fn create_program(caps: &::Capabilities, shaders: &[::ShaderHandle])
         -> (Result<::ProgramHandle, ()>, Option<String>) {
+    let name = start_program(shaders);
+    finish_program(caps, name)
+}
+
+/// Create a program and start linking it, without querying the result.
+pub fn start_program(shaders: &[::ShaderHandle]) -> super::Program {
     let name = gl::CreateProgram();
+    attach_and_link(name, shaders);
+    name
+}
+
+/// Check if the driver has finished linking the program in the background.
+/// Requires `GL_KHR_parallel_shader_compile`.
+pub fn is_program_ready(name: super::Program) -> bool {
+    get_program_iv(name, gl::COMPLETION_STATUS_KHR) != 0
+}
+
+/// Wait for the program started with `start_program` to be linked.
+pub fn finish_program(caps: &::Capabilities, name: super::Program)
+        -> (Result<::ProgramHandle, ()>, Option<String>) {
+    let (info, log) = query_link_result(caps, name);
+    (info.map(|info| ::Handle(name, info)), log)
+}
+
+/// Replace the shaders of an existing program and link it again. Fails if the
+/// new shaders don't link, or if the new interface differs from the one of the
+/// handle. The shaders are checked by linking them into a temporary program
+/// first, so that the program is left untouched on failure.
+pub fn relink_program(caps: &::Capabilities, prog: &::ProgramHandle, shaders: &[::ShaderHandle])
+        -> (Result<(), ()>, Option<String>) {
+    let name = prog.get_name();
+    let temp = gl::CreateProgram();
+    let (info, log) = link_program(caps, temp, shaders);
+    gl::DeleteProgram(temp);
+    match info {
+        Ok(ref info) if *info == *prog.get_info() => (),
+        Ok(_) => {
+            error!("The interface of program {} would change after relinking", name);
+            return (Err(()), log)
+        },
+        Err(_) => return (Err(()), log),
+    }
+    // the detached shaders are released if they were deleted already
+    let count = get_program_iv(name, gl::ATTACHED_SHADERS);
+    let mut attached = Vec::from_elem(count as uint, 0 as super::Shader);
+    unsafe {
+        gl::GetAttachedShaders(name, count, ::std::ptr::mut_null(), attached.as_mut_ptr());
+    }
+    for &sh in attached.iter() {
+        gl::DetachShader(name, sh);
+    }
+    let (info, _) = link_program(caps, name, shaders);
+    (info.map(|_| ()), log)
+}
+
+fn link_program(caps: &::Capabilities, name: super::Program, shaders: &[::ShaderHandle])
+        -> (Result<s::ProgramInfo, ()>, Option<String>) {
+    attach_and_link(name, shaders);
+    query_link_result(caps, name)
+}
+
+fn attach_and_link(name: super::Program, shaders: &[::ShaderHandle]) {
     for sh in shaders.iter() {
         gl::AttachShader(name, sh.get_name());
     }
     gl::LinkProgram(name);
     info!("\tLinked program {}", name);
+}
 
+fn query_link_result(caps: &::Capabilities, name: super::Program)
+        -> (Result<s::ProgramInfo, ()>, Option<String>) {
     // get info message
     let status = get_program_iv(name, gl::LINK_STATUS);
     let mut length  = get_program_iv(name, gl::INFO_LOG_LENGTH);
@@ -315,14 +461,14 @@ pub fn create_program(caps: &::Capabilities, shaders: &[::ShaderHandle])
     };
 
     let prog = if status != 0 {
-        let (uniforms, textures) = query_parameters(name);
-        let info = s::ProgramInfo {
+        let (uniforms, textures, images) = query_parameters(name);
+        Ok(s::ProgramInfo {
             attributes: query_attributes(name),
             uniforms: uniforms,
             blocks: query_blocks(caps, name),
             textures: textures,
-        };
-        Ok(::Handle(name, info))
+            images: images,
+        })
     } else {
         Err(())
     };
@@ -336,6 +482,11 @@ pub fn bind_uniform(loc: gl::types::GLint, uniform: s::UniformValue) {
         s::ValueF32(val) => gl::Uniform1f(loc, val),
         s::ValueI32Vec(val) => unsafe { gl::Uniform4iv(loc, 1, val.as_ptr()) },
         s::ValueF32Vec(val) => unsafe { gl::Uniform4fv(loc, 1, val.as_ptr()) },
+        s::ValueF32Vec2(val) => unsafe { gl::Uniform2fv(loc, 1, val.as_ptr()) },
+        s::ValueF32Vec3(val) => unsafe { gl::Uniform3fv(loc, 1, val.as_ptr()) },
         s::ValueF32Matrix(val) => unsafe{ gl::UniformMatrix4fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF32MatrixTransposed(val) => unsafe {
+            gl::UniformMatrix4fv(loc, 1, gl::TRUE, val[0].as_ptr())
+        },
     }
 }
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use s = super::super::state;
-use super::super::target::{Color, Rect};
+use super::super::target::{Color, ColorIndex, Rect};
 use super::gl;
 
 pub fn bind_primitive(p: s::Primitive) {
@@ -103,6 +103,29 @@ pub fn bind_depth(depth: Option<s::Depth>) {
     }
 }
 
+pub fn bind_depth_range(range: s::DepthRange) {
+    gl::DepthRange(range.near as gl::types::GLclampd, range.far as gl::types::GLclampd);
+}
+
+pub fn bind_clip_planes(mask: s::ClipPlaneMask) {
+    for i in range(0u, 8) {
+        let plane = gl::CLIP_DISTANCE0 + i as gl::types::GLenum;
+        if mask & (1 << i) != 0 {
+            gl::Enable(plane);
+        } else {
+            gl::Disable(plane);
+        }
+    }
+}
+
+pub fn bind_depth_clamp(enable: bool) {
+    if enable {
+        gl::Enable(gl::DEPTH_CLAMP);
+    } else {
+        gl::Disable(gl::DEPTH_CLAMP);
+    }
+}
+
 fn map_operation(op: s::StencilOp) -> gl::types::GLenum {
     match op {
         s::OpKeep          => gl::KEEP,
@@ -183,13 +206,40 @@ pub fn bind_blend(blend: Option<s::Blend>) {
                 map_factor(b.alpha.source),
                 map_factor(b.alpha.destination),
             );
-            let Color([r, g, b, a]) = b.value;
-            gl::BlendColor(r, g, b, a);
+            if b.uses_constant() {
+                let Color([r, g, b, a]) = b.value;
+                gl::BlendColor(r, g, b, a);
+            }
         },
         None => gl::Disable(gl::BLEND),
     }
 }
 
+pub fn bind_blend_target(index: ColorIndex, blend: Option<s::Blend>) {
+    let buf = index as gl::types::GLuint;
+    match blend {
+        Some(b) => {
+            gl::Enablei(gl::BLEND, buf);
+            gl::BlendEquationSeparatei(buf,
+                map_equation(b.color.equation),
+                map_equation(b.alpha.equation),
+            );
+            gl::BlendFuncSeparatei(buf,
+                map_factor(b.color.source),
+                map_factor(b.color.destination),
+                map_factor(b.alpha.source),
+                map_factor(b.alpha.destination),
+            );
+            // the constant color is shared among all the targets
+            if b.uses_constant() {
+                let Color([r, g, b, a]) = b.value;
+                gl::BlendColor(r, g, b, a);
+            }
+        },
+        None => gl::Disablei(gl::BLEND, buf),
+    }
+}
+
 pub fn bind_color_mask(mask: s::ColorMask) {
     gl::ColorMask(
         if (mask & s::Red  ).is_empty() {gl::FALSE} else {gl::TRUE},
@@ -198,3 +248,34 @@ pub fn bind_color_mask(mask: s::ColorMask) {
         if (mask & s::Alpha).is_empty() {gl::FALSE} else {gl::TRUE},
     );
 }
+
+fn map_logic_op(op: s::LogicOp) -> gl::types::GLenum {
+    match op {
+        s::LogicClear        => gl::CLEAR,
+        s::LogicAnd          => gl::AND,
+        s::LogicAndReverse   => gl::AND_REVERSE,
+        s::LogicCopy         => gl::COPY,
+        s::LogicAndInverted  => gl::AND_INVERTED,
+        s::LogicNoop         => gl::NOOP,
+        s::LogicXor          => gl::XOR,
+        s::LogicOr           => gl::OR,
+        s::LogicNor          => gl::NOR,
+        s::LogicEquiv        => gl::EQUIV,
+        s::LogicInvert       => gl::INVERT,
+        s::LogicOrReverse    => gl::OR_REVERSE,
+        s::LogicCopyInverted => gl::COPY_INVERTED,
+        s::LogicOrInverted   => gl::OR_INVERTED,
+        s::LogicNand         => gl::NAND,
+        s::LogicSet          => gl::SET,
+    }
+}
+
+pub fn bind_logic_op(op: Option<s::LogicOp>) {
+    match op {
+        Some(op) => {
+            gl::Enable(gl::COLOR_LOGIC_OP);
+            gl::LogicOp(map_logic_op(op));
+        },
+        None => gl::Disable(gl::COLOR_LOGIC_OP),
+    }
+}
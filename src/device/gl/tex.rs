@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use super::{gl, Surface, Texture, Sampler};
-use super::gl::types::{GLenum, GLuint, GLint, GLfloat, GLsizei, GLvoid};
+use super::gl::types::{GLbitfield, GLenum, GLuint, GLint, GLfloat, GLsizei, GLvoid};
 use Blob;
 
 /// A token produced by the `bind_texture` that allows following up
@@ -28,10 +28,12 @@ fn kind_to_gl(t: ::tex::TextureKind) -> GLenum {
         ::tex::Texture2DArray => gl::TEXTURE_2D_ARRAY,
         ::tex::TextureCube => gl::TEXTURE_CUBE_MAP,
         ::tex::Texture3D => gl::TEXTURE_3D,
+        ::tex::TextureRect => gl::TEXTURE_RECTANGLE,
+        ::tex::TextureBuffer => gl::TEXTURE_BUFFER,
     }
 }
 
-fn format_to_gl(t: ::tex::Format) -> Result<GLenum, ()> {
+pub fn format_to_gl(t: ::tex::Format) -> Result<GLenum, ()> {
     Ok(match t {
         // floating-point
         ::tex::Float(::tex::R,    ::attrib::F16) => gl::R16F,
@@ -44,10 +46,61 @@ fn format_to_gl(t: ::tex::Format) -> Result<GLenum, ()> {
         ::tex::Float(::tex::RGBA, ::attrib::F32) => gl::RGBA32F,
         ::tex::Float(_, ::attrib::F64) => return Err(()),
         // integer
-        ::tex::Integer(_, _, _) => unimplemented!(),
+        ::tex::Integer(c, bits, ::attrib::IntRaw) => match (c, bits) {
+            (::tex::R,     8) => gl::R8I,
+            (::tex::R,    16) => gl::R16I,
+            (::tex::R,    32) => gl::R32I,
+            (::tex::RG,    8) => gl::RG8I,
+            (::tex::RG,   16) => gl::RG16I,
+            (::tex::RG,   32) => gl::RG32I,
+            (::tex::RGB,   8) => gl::RGB8I,
+            (::tex::RGB,  16) => gl::RGB16I,
+            (::tex::RGB,  32) => gl::RGB32I,
+            (::tex::RGBA,  8) => gl::RGBA8I,
+            (::tex::RGBA, 16) => gl::RGBA16I,
+            (::tex::RGBA, 32) => gl::RGBA32I,
+            _ => return Err(()),
+        },
+        ::tex::Integer(c, bits, ::attrib::IntNormalized) => match (c, bits) {
+            (::tex::R,     8) => gl::R8_SNORM,
+            (::tex::R,    16) => gl::R16_SNORM,
+            (::tex::RG,    8) => gl::RG8_SNORM,
+            (::tex::RG,   16) => gl::RG16_SNORM,
+            (::tex::RGB,   8) => gl::RGB8_SNORM,
+            (::tex::RGB,  16) => gl::RGB16_SNORM,
+            (::tex::RGBA,  8) => gl::RGBA8_SNORM,
+            (::tex::RGBA, 16) => gl::RGBA16_SNORM,
+            _ => return Err(()),
+        },
+        ::tex::Integer(_, _, _) => return Err(()),
         // unsigned integer
-        ::tex::Unsigned(::tex::RGBA, 8, ::attrib::IntNormalized) => gl::RGBA8,
-        ::tex::Unsigned(_, _, _) => unimplemented!(),
+        ::tex::Unsigned(c, bits, ::attrib::IntRaw) => match (c, bits) {
+            (::tex::R,     8) => gl::R8UI,
+            (::tex::R,    16) => gl::R16UI,
+            (::tex::R,    32) => gl::R32UI,
+            (::tex::RG,    8) => gl::RG8UI,
+            (::tex::RG,   16) => gl::RG16UI,
+            (::tex::RG,   32) => gl::RG32UI,
+            (::tex::RGB,   8) => gl::RGB8UI,
+            (::tex::RGB,  16) => gl::RGB16UI,
+            (::tex::RGB,  32) => gl::RGB32UI,
+            (::tex::RGBA,  8) => gl::RGBA8UI,
+            (::tex::RGBA, 16) => gl::RGBA16UI,
+            (::tex::RGBA, 32) => gl::RGBA32UI,
+            _ => return Err(()),
+        },
+        ::tex::Unsigned(c, bits, ::attrib::IntNormalized) => match (c, bits) {
+            (::tex::R,     8) => gl::R8,
+            (::tex::R,    16) => gl::R16,
+            (::tex::RG,    8) => gl::RG8,
+            (::tex::RG,   16) => gl::RG16,
+            (::tex::RGB,   8) => gl::RGB8,
+            (::tex::RGB,  16) => gl::RGB16,
+            (::tex::RGBA,  8) => gl::RGBA8,
+            (::tex::RGBA, 16) => gl::RGBA16,
+            _ => return Err(()),
+        },
+        ::tex::Unsigned(_, _, _) => return Err(()),
         // special
         ::tex::R3G3B2       => gl::R3_G3_B2,
         ::tex::RGB5A1       => gl::RGB5_A1,
@@ -55,6 +108,11 @@ fn format_to_gl(t: ::tex::Format) -> Result<GLenum, ()> {
         ::tex::RGB10A2UI    => gl::RGB10_A2UI,
         ::tex::R11FG11FB10F => gl::R11F_G11F_B10F,
         ::tex::RGB9E5       => gl::RGB9_E5,
+        // depth
+        ::tex::DEPTH24         => gl::DEPTH_COMPONENT24,
+        ::tex::DEPTH32F        => gl::DEPTH_COMPONENT32F,
+        ::tex::DEPTH24STENCIL8 => gl::DEPTH24_STENCIL8,
+        ::tex::STENCIL8        => gl::STENCIL_INDEX8,
     })
 }
 
@@ -87,11 +145,16 @@ fn format_to_glpixel(t: ::tex::Format) -> GLenum {
         ::tex::RGB10A2UI    => gl::RGBA,
         ::tex::R11FG11FB10F => gl::RGB,
         ::tex::RGB9E5       => gl::RGB,
+        ::tex::DEPTH24         => gl::DEPTH_COMPONENT,
+        ::tex::DEPTH32F        => gl::DEPTH_COMPONENT,
+        ::tex::DEPTH24STENCIL8 => gl::DEPTH_STENCIL,
+        ::tex::STENCIL8        => gl::STENCIL_INDEX,
     }
 }
 
 fn format_to_gltype(t: ::tex::Format) -> Result<GLenum, ()> {
     match t {
+        ::tex::Float(_, ::attrib::F16) => Ok(gl::HALF_FLOAT),
         ::tex::Float(_, ::attrib::F32) => Ok(gl::FLOAT),
         ::tex::Integer(_, 8, _)   => Ok(gl::BYTE),
         ::tex::Unsigned(_, 8, _)  => Ok(gl::UNSIGNED_BYTE),
@@ -99,6 +162,10 @@ fn format_to_gltype(t: ::tex::Format) -> Result<GLenum, ()> {
         ::tex::Unsigned(_, 16, _) => Ok(gl::UNSIGNED_SHORT),
         ::tex::Integer(_, 32, _)  => Ok(gl::INT),
         ::tex::Unsigned(_, 32, _) => Ok(gl::UNSIGNED_INT),
+        ::tex::DEPTH24            => Ok(gl::UNSIGNED_INT),
+        ::tex::DEPTH32F           => Ok(gl::FLOAT),
+        ::tex::DEPTH24STENCIL8    => Ok(gl::UNSIGNED_INT_24_8),
+        ::tex::STENCIL8           => Ok(gl::UNSIGNED_BYTE),
         _ => Err(()),
     }
 }
@@ -116,12 +183,27 @@ fn format_to_size(t: ::tex::Format) -> uint {
         ::tex::RGB10A2UI    => 4,
         ::tex::R11FG11FB10F => 4,
         ::tex::RGB9E5       => 4,
+        ::tex::DEPTH24         => 4,
+        ::tex::DEPTH32F        => 4,
+        ::tex::DEPTH24STENCIL8 => 4,
+        ::tex::STENCIL8        => 1,
     }
 }
 
-fn set_mipmap_range(target: GLenum, (base, max): (u8, u8)) {
-    gl::TexParameteri(target, gl::TEXTURE_BASE_LEVEL, base as GLint);
-    gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, max as GLint);
+/// Get the texture parameters restricting sampling to a range of mipmap levels.
+pub fn mipmap_range_params((base, max): (u8, u8)) -> [(GLenum, GLint), ..2] {
+    [(gl::TEXTURE_BASE_LEVEL, base as GLint), (gl::TEXTURE_MAX_LEVEL, max as GLint)]
+}
+
+/// Get the texture parameters clamping the LOD used for sampling.
+pub fn lod_range_params((min, max): (f32, f32)) -> [(GLenum, GLfloat), ..2] {
+    [(gl::TEXTURE_MIN_LOD, min as GLfloat), (gl::TEXTURE_MAX_LOD, max as GLfloat)]
+}
+
+fn set_mipmap_range(target: GLenum, range: (u8, u8)) {
+    for &(pname, value) in mipmap_range_params(range).iter() {
+        gl::TexParameteri(target, pname, value);
+    }
 }
 
 /// Create a render surface.
@@ -190,7 +272,7 @@ pub fn make_without_storage(info: &::tex::TextureInfo) -> Result<Texture, ::Text
                     ::std::ptr::null(),
                 );
             },
-            ::tex::Texture2D => {
+            ::tex::Texture2D | ::tex::TextureRect => {
                 gl::TexImage2D(
                     target,
                     0,
@@ -204,6 +286,7 @@ pub fn make_without_storage(info: &::tex::TextureInfo) -> Result<Texture, ::Text
                 );
             },
             ::tex::TextureCube => unimplemented!(),
+            ::tex::TextureBuffer => return Err(::TextureBufferStorage),
             ::tex::Texture2DArray | ::tex::Texture3D => {
                 gl::TexImage3D(
                     target,
@@ -281,7 +364,17 @@ pub fn make_with_storage(info: &::tex::TextureInfo) -> Result<Texture, ::Texture
                 info.height as GLsizei,
             );
         },
+        ::tex::TextureRect => {
+            gl::TexStorage2D(
+                target,
+                1,
+                fmt,
+                info.width as GLsizei,
+                info.height as GLsizei,
+            );
+        },
         ::tex::TextureCube => unimplemented!(),
+        ::tex::TextureBuffer => return Err(::TextureBufferStorage),
         ::tex::Texture2DArray => {
             gl::TexStorage3D(
                 target,
@@ -339,15 +432,47 @@ pub fn bind_sampler(anchor: BindAnchor, info: &::tex::SamplerInfo) {
 
     gl::TexParameterf(target, gl::TEXTURE_LOD_BIAS, info.lod_bias);
 
-    let (min, max) = info.lod_range;
-    gl::TexParameterf(target, gl::TEXTURE_MIN_LOD, min);
-    gl::TexParameterf(target, gl::TEXTURE_MAX_LOD, max);
+    for &(pname, value) in lod_range_params(info.lod_range).iter() {
+        gl::TexParameterf(target, pname, value);
+    }
+
+    if info.uses_border() {
+        let ::target::Color(border) = info.border_color;
+        unsafe {
+            gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+        }
+    }
+}
+
+/// Get the largest pixel store alignment that tightly packed rows of the
+/// given size in bytes satisfy, so that no padding is expected between them.
+pub fn get_row_alignment(row_size: uint) -> GLint {
+    match row_size {
+        s if s % 8 == 0 => 8,
+        s if s % 4 == 0 => 4,
+        s if s % 2 == 0 => 2,
+        _ => 1,
+    }
+}
+
+/// Get the number of bytes the data of an image takes, when its rows are
+/// `row_length` texels apart in the source. The last row stops at the end of
+/// the image, so the source doesn't need to extend past it.
+pub fn get_image_size(img: &::tex::ImageInfo, row_length: Option<u16>) -> uint {
+    let rows = img.height as uint * img.depth as uint;
+    if rows == 0 {
+        return 0
+    }
+    let row = row_length.unwrap_or(img.width) as uint;
+    ((rows - 1) * row + img.width as uint) * format_to_size(img.format)
 }
 
+/// Upload the data into a region of the texture. The source rows are
+/// `row_length` texels apart if set, which allows uploading a crop of a
+/// larger image, or tightly packed otherwise.
 pub fn update_texture(kind: ::tex::TextureKind, name: Texture, img: &::tex::ImageInfo,
-                      data: &Blob) -> Result<(), ::TextureError> {
-    debug_assert!(img.width as uint * img.height as uint * img.depth as uint *
-        format_to_size(img.format) == data.get_size());
+                      data: &Blob, row_length: Option<u16>) -> Result<(), ::TextureError> {
+    debug_assert!(get_image_size(img, row_length) <= data.get_size());
 
     let data = data.get_address() as *const GLvoid;
     let pix = format_to_glpixel(img.format);
@@ -358,6 +483,12 @@ pub fn update_texture(kind: ::tex::TextureKind, name: Texture, img: &::tex::Imag
     let target = kind_to_gl(kind);
 
     gl::BindTexture(target, name);
+    let row = row_length.unwrap_or(img.width);
+    let row_size = row as uint * format_to_size(img.format);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, get_row_alignment(row_size));
+    if row_length.is_some() {
+        gl::PixelStorei(gl::UNPACK_ROW_LENGTH, row as GLint);
+    }
 
     unsafe {
         match kind {
@@ -372,7 +503,7 @@ pub fn update_texture(kind: ::tex::TextureKind, name: Texture, img: &::tex::Imag
                     data,
                 );
             },
-            ::tex::Texture1DArray | ::tex::Texture2D => {
+            ::tex::Texture1DArray | ::tex::Texture2D | ::tex::TextureRect => {
                 gl::TexSubImage2D(
                     target,
                     img.mipmap as GLint,
@@ -386,6 +517,7 @@ pub fn update_texture(kind: ::tex::TextureKind, name: Texture, img: &::tex::Imag
                 );
             },
             ::tex::TextureCube => unimplemented!(),
+            ::tex::TextureBuffer => return Err(::TextureBufferStorage),
             ::tex::Texture2DArray | ::tex::Texture3D => {
                 gl::TexSubImage3D(
                     target,
@@ -404,9 +536,118 @@ pub fn update_texture(kind: ::tex::TextureKind, name: Texture, img: &::tex::Imag
         }
     }
 
+    if row_length.is_some() {
+        // back to the length of the image rows
+        gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+    }
+
     Ok(())
 }
 
+/// Copy a region between two textures with `glCopyImageSubData`.
+pub fn copy_image(src_kind: ::tex::TextureKind, src: Texture, src_img: &::tex::ImageInfo,
+                  dst_kind: ::tex::TextureKind, dst: Texture, dst_img: &::tex::ImageInfo) {
+    gl::CopyImageSubData(
+        src, kind_to_gl(src_kind), src_img.mipmap as GLint,
+        src_img.xoffset as GLint, src_img.yoffset as GLint, src_img.zoffset as GLint,
+        dst, kind_to_gl(dst_kind), dst_img.mipmap as GLint,
+        dst_img.xoffset as GLint, dst_img.yoffset as GLint, dst_img.zoffset as GLint,
+        src_img.width as GLsizei, src_img.height as GLsizei, src_img.depth as GLsizei,
+    );
+}
+
+fn attach_layer(target: GLenum, att: GLenum, kind: ::tex::TextureKind, name: Texture,
+                level: u8, layer: u16) {
+    match kind {
+        ::tex::Texture1D =>
+            gl::FramebufferTexture1D(target, att, gl::TEXTURE_1D, name, level as GLint),
+        ::tex::Texture2D | ::tex::TextureRect =>
+            gl::FramebufferTexture2D(target, att, kind_to_gl(kind), name, level as GLint),
+        ::tex::Texture1DArray | ::tex::Texture2DArray | ::tex::Texture3D =>
+            gl::FramebufferTextureLayer(target, att, name, level as GLint, layer as GLint),
+        // the layers of a cube map are its faces, in the order of the enum
+        ::tex::TextureCube =>
+            gl::FramebufferTexture2D(target, att, gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer as GLenum,
+                                     name, level as GLint),
+        ::tex::TextureBuffer => error!("Buffer texture {} can't be attached", name),
+    }
+}
+
+/// Get the frame buffer attachment and the blit mask covering every
+/// component of the format, packed depth and stencil included.
+pub fn blit_attachment(format: ::tex::Format) -> (GLenum, GLbitfield) {
+    match (format.is_depth(), format.has_stencil()) {
+        (true, true) => (gl::DEPTH_STENCIL_ATTACHMENT,
+                         gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT),
+        (true, false) => (gl::DEPTH_ATTACHMENT, gl::DEPTH_BUFFER_BIT),
+        (false, true) => (gl::STENCIL_ATTACHMENT, gl::STENCIL_BUFFER_BIT),
+        (false, false) => (gl::COLOR_ATTACHMENT0, gl::COLOR_BUFFER_BIT),
+    }
+}
+
+/// Copy a region between two textures by blitting it one layer at a time
+/// between the `read` and `draw` frame buffers, for contexts without
+/// `glCopyImageSubData`. Leaves the default frame buffer bound afterwards.
+pub fn blit_image((read, draw): (super::FrameBuffer, super::FrameBuffer),
+                  src_kind: ::tex::TextureKind, src: Texture, src_img: &::tex::ImageInfo,
+                  dst_kind: ::tex::TextureKind, dst: Texture, dst_img: &::tex::ImageInfo) {
+    let (att, mask) = blit_attachment(src_img.format);
+    let (w, h) = (src_img.width as GLint, src_img.height as GLint);
+    let (sx, sy) = (src_img.xoffset as GLint, src_img.yoffset as GLint);
+    let (dx, dy) = (dst_img.xoffset as GLint, dst_img.yoffset as GLint);
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, read);
+    gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw);
+    for i in range(0, src_img.depth) {
+        attach_layer(gl::READ_FRAMEBUFFER, att, src_kind, src, src_img.mipmap,
+                     src_img.zoffset + i);
+        attach_layer(gl::DRAW_FRAMEBUFFER, att, dst_kind, dst, dst_img.mipmap,
+                     dst_img.zoffset + i);
+        gl::BlitFramebuffer(sx, sy, sx + w, sy + h, dx, dy, dx + w, dy + h,
+                            mask, gl::NEAREST);
+    }
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+    gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+}
+
+/// Create a view into the storage of `src`, starting at the given mipmap level
+/// and layer. The sizes of the view are taken from its info.
+pub fn make_view(src: Texture, info: &::tex::TextureInfo, level: u8, layer: u16)
+                 -> Result<Texture, ::TextureError> {
+    let fmt = match format_to_gl(info.format) {
+        Ok(f) => f,
+        Err(_) => return Err(::UnsupportedTextureFormat),
+    };
+    let (_, max_level) = info.mipmap_range;
+    let num_layers = info.get_layer_count();
+    let mut name = 0 as GLuint;
+    unsafe {
+        gl::GenTextures(1, &mut name);
+    }
+    // the view name must not be bound before this call
+    gl::TextureView(name, kind_to_gl(info.kind), src, fmt,
+                    level as GLuint, max_level as GLuint + 1,
+                    layer as GLuint, num_layers as GLuint);
+    Ok(name)
+}
+
+/// Create a buffer texture, reading the texels from the storage of `buffer`.
+pub fn make_buffer_texture(buffer: super::Buffer, format: ::tex::Format)
+                           -> Result<Texture, ::TextureError> {
+    if !format.is_buffer_compatible() {
+        return Err(::UnsupportedTextureFormat)
+    }
+    let fmt = match format_to_gl(format) {
+        Ok(f) => f,
+        Err(_) => return Err(::UnsupportedTextureFormat),
+    };
+    let name = make_texture(&::tex::TextureInfo {
+        kind: ::tex::TextureBuffer,
+        .. ::tex::TextureInfo::new()
+    });
+    gl::TexBuffer(gl::TEXTURE_BUFFER, fmt, buffer);
+    Ok(name)
+}
+
 /// Common texture creation routine, just creates and binds.
 fn make_texture(info: &::tex::TextureInfo) -> Texture {
     let mut name = 0 as GLuint;
@@ -425,6 +666,7 @@ fn wrap_to_gl(w: ::tex::WrapMode) -> GLenum {
         ::tex::Tile   => gl::REPEAT,
         ::tex::Mirror => gl::MIRRORED_REPEAT,
         ::tex::Clamp  => gl::CLAMP_TO_EDGE,
+        ::tex::Border => gl::CLAMP_TO_BORDER,
     }
 }
 
@@ -462,9 +704,16 @@ pub fn make_sampler(info: &::tex::SamplerInfo) -> Sampler {
 
     gl::SamplerParameterf(name, gl::TEXTURE_LOD_BIAS, info.lod_bias);
 
-    let (min, max) = info.lod_range;
-    gl::SamplerParameterf(name, gl::TEXTURE_MIN_LOD, min);
-    gl::SamplerParameterf(name, gl::TEXTURE_MAX_LOD, max);
+    for &(pname, value) in lod_range_params(info.lod_range).iter() {
+        gl::SamplerParameterf(name, pname, value);
+    }
+
+    if info.uses_border() {
+        let ::target::Color(border) = info.border_color;
+        unsafe {
+            gl::SamplerParameterfv(name, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+        }
+    }
 
     name
 }
@@ -32,22 +32,29 @@ extern crate libc;
 /* #[cfg(gl)] */ pub use gl::DrawList;
 // #[cfg(d3d11)] ... // TODO
 
+use std::default::Default;
 use std::fmt;
 use std::kinds::marker;
 use std::mem::size_of;
 
 pub mod attrib;
 pub mod draw;
+pub mod null;
+pub mod serial;
 pub mod shade;
+pub mod soft;
 pub mod state;
 pub mod target;
 pub mod tex;
+pub mod validate;
 /* #[cfg(gl)] */ mod gl;
 
 /// Draw vertex count.
 pub type VertexCount = u32;
 /// Draw index count.
 pub type IndexCount = u32;
+/// Draw number of instances.
+pub type InstanceCount = u32;
 /// Index of a uniform block.
 pub type UniformBlockIndex = u8;
 /// Slot for an attribute.
@@ -56,19 +63,39 @@ pub type AttributeSlot = u8;
 pub type UniformBufferSlot = u8;
 /// Slot a texture can be bound to.
 pub type TextureSlot = u8;
+/// Slot an image can be bound to, for load/store access in shaders.
+pub type ImageSlot = u8;
 
-/// A generic handle struct
+/// A generic handle struct, holding the API name of an object with its creation info.
+/// Cloning a handle is cheap: it doesn't duplicate the object, and all the
+/// clones refer to the same device resource. For the same reason, dropping a
+/// handle doesn't release the resource, which stays alive until it is passed
+/// to the matching `delete_*` method of the back-end.
 #[deriving(Clone, Show)]
 pub struct Handle<T, I>(T, I);
 
-#[deriving(Clone, Show)]
 impl<T: Copy, I> Handle<T, I> {
+    /// Create a handle from the name of an object and its info. Only meant
+    /// for the back-ends, which create the objects the names refer to.
+    pub fn new(name: T, info: I) -> Handle<T, I> {
+        Handle(name, info)
+    }
+
     /// Get the internal name
     pub fn get_name(&self) -> T {
         let Handle(name, _) = *self;
         name
     }
 
+    /// Get the raw API name of the object, as an escape hatch for passing it
+    /// to external libraries. With the GL back-end this is the GL object name.
+    /// The object still belongs to the back-end: changing its state behind
+    /// the back-end's back can break its assumptions, and it must not be
+    /// deleted externally.
+    pub fn raw_name(&self) -> T {
+        self.get_name()
+    }
+
     /// Get the info reference
     pub fn get_info(&self) -> &I {
         let Handle(_, ref info) = *self;
@@ -82,8 +109,17 @@ impl<T: Copy + PartialEq, I: PartialEq> PartialEq for Handle<T, I> {
     }
 }
 
+/// Creation info of a buffer. It holds no data, but gives buffer handles a
+/// type of their own, so that they can't be mixed with other handles.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BufferInfo;
+
+/// Creation info of a query, see `BufferInfo`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct QueryInfo;
+
 /// Buffer Handle
-pub type BufferHandle  = Handle<back::Buffer, ()>;
+pub type BufferHandle  = Handle<back::Buffer, BufferInfo>;
 /// Shader Handle
 pub type ShaderHandle  = Handle<back::Shader, shade::Stage>;
 /// Program Handle
@@ -94,11 +130,23 @@ pub type SurfaceHandle = Handle<back::Surface, tex::SurfaceInfo>;
 pub type TextureHandle = Handle<back::Texture, tex::TextureInfo>;
 /// Sampler Handle
 pub type SamplerHandle = Handle<back::Sampler, tex::SamplerInfo>;
+/// Query Handle
+pub type QueryHandle   = Handle<back::Query, QueryInfo>;
+
+/// A program being linked in the background, see `ApiBackEnd::link_program_async`.
+#[deriving(Show)]
+pub struct PendingProgram(pub back::Program);
 
 /// A helper method to test `#[vertex_format]` without GL context
 //#[cfg(test)]
 pub fn make_fake_buffer() -> BufferHandle {
-    Handle(0, ())
+    Handle(0, BufferInfo)
+}
+
+/// A helper method to test `#[shader_param]` without GL context
+//#[cfg(test)]
+pub fn make_fake_texture() -> TextureHandle {
+    Handle(0, std::default::Default::default())
 }
 
 /// Features that the device supports.
@@ -107,11 +155,108 @@ pub struct Capabilities {
     shader_model: shade::ShaderModel,
     max_draw_buffers : uint,
     max_texture_size : uint,
+    max_texture_units: uint,
     max_vertex_attributes: uint,
+    max_color_attachments: uint,
+    max_samples: uint,
+    max_uniform_block_size: uint,
+    uniform_buffer_offset_alignment: uint,
+    max_anisotropy: f32,
     uniform_block_supported: bool,
+    render_targets_supported: bool,
     array_buffer_supported: bool,
     sampler_objects_supported: bool,
     immutable_storage_supported: bool,
+    image_load_store_supported: bool,
+    draw_buffer_blend_supported: bool,
+    depth_clamp_supported: bool,
+    conditional_render_supported: bool,
+    debug_label_supported: bool,
+    copy_buffer_supported: bool,
+    copy_image_supported: bool,
+    clear_buffer_supported: bool,
+    float_color_buffer_supported: bool,
+    draw_base_vertex_supported: bool,
+    instance_draw_supported: bool,
+    instance_rate_supported: bool,
+    robustness_supported: bool,
+    parallel_compile_supported: bool,
+    texture_view_supported: bool,
+    buffer_storage_supported: bool,
+    indirect_count_supported: bool,
+    timer_query_supported: bool,
+    buffer_texture_supported: bool,
+}
+
+impl Default for Capabilities {
+    /// Capabilities of a device supporting nothing beyond the basics, as a
+    /// starting point for the back-ends.
+    fn default() -> Capabilities {
+        Capabilities {
+            shader_model: shade::ModelUnsupported,
+            max_draw_buffers: 1,
+            max_texture_size: 0,
+            max_texture_units: 0,
+            max_vertex_attributes: 0,
+            max_color_attachments: 0,
+            max_samples: 0,
+            max_uniform_block_size: 0,
+            uniform_buffer_offset_alignment: 0,
+            max_anisotropy: 1.0,
+            uniform_block_supported: false,
+            render_targets_supported: false,
+            array_buffer_supported: false,
+            sampler_objects_supported: false,
+            immutable_storage_supported: false,
+            image_load_store_supported: false,
+            draw_buffer_blend_supported: false,
+            depth_clamp_supported: false,
+            conditional_render_supported: false,
+            debug_label_supported: false,
+            copy_buffer_supported: false,
+            copy_image_supported: false,
+            clear_buffer_supported: false,
+            float_color_buffer_supported: false,
+            draw_base_vertex_supported: false,
+            instance_draw_supported: false,
+            instance_rate_supported: false,
+            robustness_supported: false,
+            parallel_compile_supported: false,
+            texture_view_supported: false,
+            buffer_storage_supported: false,
+            indirect_count_supported: false,
+            timer_query_supported: false,
+            buffer_texture_supported: false,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Maximum number of color targets a frame can draw to at once.
+    pub fn get_max_draw_buffers(&self) -> uint { self.max_draw_buffers }
+    /// Maximum width or height of a texture, in texels.
+    pub fn get_max_texture_size(&self) -> uint { self.max_texture_size }
+    /// Number of texture units available to a program.
+    pub fn get_max_texture_units(&self) -> uint { self.max_texture_units }
+    /// Set the number of texture units, for the back-ends implemented outside
+    /// of this crate.
+    pub fn set_max_texture_units(&mut self, units: uint) { self.max_texture_units = units }
+    /// Number of vertex attributes available to a program.
+    pub fn get_max_vertex_attributes(&self) -> uint { self.max_vertex_attributes }
+    /// Number of color planes that can be bound to a frame. 0 without
+    /// render targets.
+    pub fn get_max_color_attachments(&self) -> uint { self.max_color_attachments }
+    /// Maximum number of samples of a multisampled surface.
+    pub fn get_max_samples(&self) -> uint { self.max_samples }
+    /// Maximum size of a uniform block, in bytes. 0 without uniform blocks.
+    pub fn get_max_uniform_block_size(&self) -> uint { self.max_uniform_block_size }
+    /// Required alignment of the offset of a uniform buffer range, in bytes.
+    pub fn get_uniform_buffer_offset_alignment(&self) -> uint {
+        self.uniform_buffer_offset_alignment
+    }
+    /// Maximum anisotropy factor of the `Anisotropic` filter. 1 if anisotropic
+    /// filtering is not supported.
+    pub fn get_max_anisotropy(&self) -> f32 { self.max_anisotropy }
 }
 
 /// A trait that slice-like types implement.
@@ -131,6 +276,15 @@ impl<T: Send> Blob for Vec<T> {
     }
 }
 
+impl<'a, T> Blob for &'a [T] {
+    fn get_address(&self) -> uint {
+        self.as_ptr() as uint
+    }
+    fn get_size(&self) -> uint {
+        self.len() * size_of::<T>()
+    }
+}
+
 impl fmt::Show for Box<Blob + Send> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Blob({:#x}, {})", self.get_address(), self.get_size())
@@ -162,6 +316,20 @@ pub enum PrimitiveType {
     //Quad,
 }
 
+/// How a conditional render waits for the result of the occlusion query.
+#[deriving(Clone, PartialEq, Show)]
+#[repr(u8)]
+pub enum ConditionalMode {
+    /// Wait for the query result before drawing.
+    ConditionalWait,
+    /// Draw unconditionally if the query result is not available yet.
+    ConditionalNoWait,
+    /// Same as `ConditionalWait`, but the device may only wait for the local region.
+    ConditionalRegionWait,
+    /// Same as `ConditionalNoWait`, but the device may only wait for the local region.
+    ConditionalRegionNoWait,
+}
+
 /// A type of each index value in the mesh's index buffer
 pub type IndexType = attrib::IntSize;
 
@@ -182,6 +350,45 @@ pub enum BufferUsage {
     UsageStream,
 }
 
+/// How an immutable buffer storage can be accessed, see
+/// `ApiBackEnd::create_buffer_storage`.
+bitflags!(
+    #[allow(missing_doc)]
+    flags StorageFlags: u32 {
+        static StorageDynamic    = 0x01,
+        static StorageMapRead    = 0x02,
+        static StorageMapWrite   = 0x04,
+        static StoragePersistent = 0x08,
+        static StorageCoherent   = 0x10
+    }
+)
+
+impl fmt::Show for StorageFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StorageFlags({:#x})", self.bits())
+    }
+}
+
+/// Why the device was reset, losing all its resources.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ResetReason {
+    /// The reset was caused by this context.
+    ResetGuilty,
+    /// The reset was caused by another context.
+    ResetInnocent,
+    /// The cause of the reset is unknown.
+    ResetUnknown,
+}
+
+/// Buffer copy or storage error.
+#[deriving(Clone, PartialEq, Show)]
+pub enum BufferError {
+    /// The requested range does not fit into the buffer
+    BufferOutOfRange,
+    /// The operation is not supported by the device
+    UnsupportedBufferOperation,
+}
+
 /// Surface creation/update error.
 #[deriving(Clone, PartialEq, Show)]
 pub enum SurfaceError {
@@ -194,6 +401,21 @@ pub enum SurfaceError {
 pub enum TextureError {
     /// Failed to map a given format to the device
     UnsupportedTextureFormat,
+    /// The requested region does not fit into the texture
+    TextureOutOfRange,
+    /// The texture view format is not compatible with the texture format
+    TextureFormatMismatch,
+    /// Texture views are not supported by the device
+    UnsupportedTextureView,
+    /// Immutable texture storage is not supported by the device
+    UnsupportedTextureStorage,
+    /// Rectangle textures only have the base mipmap level
+    TextureRectMipmap,
+    /// Buffer textures are not supported by the device
+    UnsupportedTextureBuffer,
+    /// Buffer textures take their storage from a buffer, and can only be
+    /// created with `create_buffer_texture`
+    TextureBufferStorage,
 }
 
 /// Serialized device command.
@@ -206,7 +428,7 @@ pub enum Command {
     BindProgram(back::Program),
     BindArrayBuffer(back::ArrayBuffer),
     BindAttribute(AttributeSlot, back::Buffer, attrib::Count,
-        attrib::Type, attrib::Stride, attrib::Offset),
+        attrib::Type, attrib::Stride, attrib::Offset, attrib::InstanceRate),
     BindIndex(back::Buffer),
     BindFrameBuffer(back::FrameBuffer),
     /// Unbind any surface from the specified target slot
@@ -216,37 +438,151 @@ pub enum Command {
     /// Bind a level of the texture to the specified target slot
     BindTargetTexture(target::Target, back::Texture, target::Level, Option<target::Layer>),
     BindUniformBlock(back::Program, UniformBufferSlot, UniformBlockIndex, back::Buffer),
+    /// Bind `size` bytes of the buffer, starting at `offset`, to the uniform block
+    BindUniformBlockRange(back::Program, UniformBufferSlot, UniformBlockIndex, back::Buffer,
+                          uint, uint),
     BindUniform(shade::Location, shade::UniformValue),
     BindTexture(TextureSlot, tex::TextureKind, back::Texture, Option<SamplerHandle>),
+    /// Bind a level of the texture as an image for load/store access
+    BindImage(ImageSlot, back::Texture, target::Level, tex::ImageAccess, tex::Format),
     SetPrimitiveState(state::Primitive),
     SetViewport(target::Rect),
     SetScissor(Option<target::Rect>),
     SetDepthStencilState(Option<state::Depth>, Option<state::Stencil>, state::CullMode),
+    SetDepthRange(state::DepthRange),
+    SetClipPlanes(state::ClipPlaneMask),
+    /// Clamp the fragment depth to the range instead of clipping against the near and far planes
+    SetDepthClamp(bool),
     SetBlendState(Option<state::Blend>),
+    /// Override the blend state for a single color target
+    SetBlendTarget(target::ColorIndex, Option<state::Blend>),
     SetColorMask(state::ColorMask),
+    SetLogicOp(Option<state::LogicOp>),
+    /// Start counting the samples passing the depth and stencil tests
+    BeginOcclusionQuery(back::Query),
+    EndOcclusionQuery,
+    /// Record the GPU time once the previous commands have completed
+    QueryTimestamp(back::Query),
+    /// Skip the following draw calls if the query didn't get any samples
+    BeginConditional(back::Query, ConditionalMode),
+    EndConditional,
     UpdateBuffer(back::Buffer, Box<Blob + Send>),
     UpdateTexture(tex::TextureKind, back::Texture, tex::ImageInfo, Box<Blob + Send>),
     // drawing
     Clear(target::ClearData),
-    Draw(PrimitiveType, VertexCount, VertexCount),
-    DrawIndexed(PrimitiveType, IndexType, IndexCount, IndexCount),
+    /// Clear a color target with a signed integer format
+    ClearColorInt(target::ColorIndex, [i32, ..4]),
+    /// Clear a color target with an unsigned integer format
+    ClearColorUint(target::ColorIndex, [u32, ..4]),
+    Draw(PrimitiveType, VertexCount, VertexCount, Option<InstanceCount>),
+    DrawIndexed(PrimitiveType, IndexType, IndexCount, IndexCount, i32,
+                Option<InstanceCount>),
+    /// Issue the indexed draws found at the offset of the first buffer, their
+    /// number being read from the offset of the second one and capped at the
+    /// maximum, with the given stride between the draw parameters
+    DrawIndexedIndirectCount(PrimitiveType, IndexType, back::Buffer, uint, back::Buffer, uint,
+                             u32, u32),
+}
+
+/// Statistics about the submitted commands, accumulated by the back-end until reset.
+#[deriving(Clone, PartialEq, Show)]
+pub struct FrameStats {
+    /// Number of draw calls.
+    pub draw_count: uint,
+    /// Number of vertices, or indices for the indexed draw calls, counted
+    /// once for each instance.
+    pub vertex_count: uint,
+    /// Number of state changes and resource bindings.
+    pub state_change_count: uint,
 }
 
-/// An interface for performing draw calls using a specific graphics API
+impl FrameStats {
+    /// Create empty statistics.
+    pub fn new() -> FrameStats {
+        FrameStats {
+            draw_count: 0,
+            vertex_count: 0,
+            state_change_count: 0,
+        }
+    }
+
+    /// Account for a single command.
+    pub fn record(&mut self, command: &Command) {
+        match *command {
+            Draw(_, _, count, instances) | DrawIndexed(_, _, _, count, _, instances) => {
+                self.draw_count += 1;
+                self.vertex_count += count as uint * instances.unwrap_or(1) as uint;
+            },
+            // the number of draws and vertices is only known to the device
+            DrawIndexedIndirectCount(..) => self.draw_count += 1,
+            Clear(_) | ClearColorInt(..) | ClearColorUint(..) |
+            UpdateBuffer(..) | UpdateTexture(..) => (),
+            _ => self.state_change_count += 1,
+        }
+    }
+}
+
+/// An interface for performing draw calls using a specific graphics API.
+/// The front-end only talks to the device through it, so any back-end
+/// implementing it can be driven by the front-end, `GlBackEnd` being the one
+/// for OpenGL. `D` is the type of the draw lists the back-end can submit.
 #[allow(missing_doc)]
 pub trait ApiBackEnd<D> {
     /// Returns the capabilities available to the specific API implementation
     fn get_capabilities<'a>(&'a self) -> &'a Capabilities;
     // resource creation
     fn create_buffer(&mut self) -> BufferHandle;
+    /// Create a buffer with an immutable storage of `size` bytes. Its size
+    /// can't change afterwards, and `update_buffer` only accepts data that
+    /// fits if the storage is `StorageDynamic`.
+    fn create_buffer_storage(&mut self, size: uint, flags: StorageFlags)
+                             -> Result<BufferHandle, BufferError>;
     fn create_array_buffer(&mut self) -> Result<back::ArrayBuffer, ()>;
     fn create_shader(&mut self, stage: shade::Stage, code: shade::ShaderSource) ->
                      Result<ShaderHandle, shade::CreateShaderError>;
     fn create_program(&mut self, shaders: &[ShaderHandle]) -> Result<ProgramHandle, ()>;
+    /// Replace the shaders of a program and link it again, keeping the handle
+    /// valid. Fails if the new program has a different interface, in which
+    /// case the program needs to be created again.
+    fn relink_program(&mut self, &ProgramHandle, shaders: &[ShaderHandle]) -> Result<(), ()>;
+    /// Start linking a program without waiting for the result. The driver
+    /// links it in the background if it supports parallel compilation,
+    /// otherwise this is as slow as `create_program`.
+    fn link_program_async(&mut self, shaders: &[ShaderHandle]) -> PendingProgram;
+    /// Check if a pending program has finished linking, without blocking.
+    fn is_program_ready(&mut self, &PendingProgram) -> bool;
+    /// Get the linked program, waiting for it if it's not ready yet.
+    fn finish_program(&mut self, PendingProgram) -> Result<ProgramHandle, ()>;
     fn create_frame_buffer(&mut self) -> back::FrameBuffer;
     fn create_surface(&mut self, info: tex::SurfaceInfo) -> Result<SurfaceHandle, SurfaceError>;
+    /// Create a texture, with immutable storage if the device supports it.
     fn create_texture(&mut self, info: tex::TextureInfo) -> Result<TextureHandle, TextureError>;
+    /// Create a texture with immutable storage, failing if the device doesn't
+    /// support it. Its sizes and format can never change afterwards, which
+    /// saves the driver from validating them at each use.
+    fn create_texture_immutable(&mut self, info: tex::TextureInfo)
+                                -> Result<TextureHandle, TextureError>;
+    /// Create a texture sharing the storage of `src`, which reads its texels
+    /// with another format of the same size. The mipmap levels and layers of
+    /// the view are given as `(first, count)` ranges of those of `src`.
+    fn create_texture_view(&mut self, src: &TextureHandle, format: tex::Format,
+                           levels: (u8, u8), layers: (u16, u16))
+                           -> Result<TextureHandle, TextureError>;
+    /// Create a texture reading its texels from the storage of `buffer`,
+    /// sampled as a `samplerBuffer`. Updates of the buffer are seen by the
+    /// texture. Fails with `UnsupportedTextureFormat` unless the format
+    /// `is_buffer_compatible`.
+    fn create_buffer_texture(&mut self, buffer: &BufferHandle, format: tex::Format)
+                             -> Result<TextureHandle, TextureError>;
     fn create_sampler(&mut self, info: tex::SamplerInfo) -> SamplerHandle;
+    fn create_query(&mut self) -> QueryHandle;
+    /// Wrap a buffer created outside of the back-end. The back-end doesn't own
+    /// it: deleting the returned handle leaves the object alive.
+    fn import_buffer(&mut self, name: back::Buffer) -> BufferHandle;
+    /// Wrap a texture created outside of the back-end, described by `info`.
+    /// The back-end doesn't own it: deleting the returned handle leaves the
+    /// object alive.
+    fn import_texture(&mut self, name: back::Texture, info: tex::TextureInfo) -> TextureHandle;
     // resource deletion
     fn delete_buffer(&mut self, BufferHandle);
     fn delete_shader(&mut self, ShaderHandle);
@@ -254,13 +590,76 @@ pub trait ApiBackEnd<D> {
     fn delete_surface(&mut self, SurfaceHandle);
     fn delete_texture(&mut self, TextureHandle);
     fn delete_sampler(&mut self, SamplerHandle);
+    fn delete_query(&mut self, QueryHandle);
+    /// Release the objects passed to the `delete_*` methods right away, for
+    /// the back-ends deferring it to the end of the next `submit`.
+    fn flush_deletions(&mut self);
+    /// Attach a name to the buffer, shown by the debugging tools
+    fn set_buffer_label(&mut self, &BufferHandle, label: &str);
+    /// Attach a name to the texture, shown by the debugging tools
+    fn set_texture_label(&mut self, &TextureHandle, label: &str);
+    /// Attach a name to the program, shown by the debugging tools
+    fn set_program_label(&mut self, &ProgramHandle, label: &str);
     /// Update the information stored in a specific buffer
     fn update_buffer(&mut self, BufferHandle, &Blob, BufferUsage);
-    /// Update the information stored in a texture
+    /// Copy `size` bytes from one buffer into another on the device, without
+    /// reading the data back. Both ranges are checked against the buffer sizes.
+    fn copy_buffer(&mut self, src: BufferHandle, src_offset: uint,
+                   dst: BufferHandle, dst_offset: uint, size: uint)
+                   -> Result<(), BufferError>;
+    /// Fill the whole buffer with a repeated 32-bit value.
+    fn clear_buffer(&mut self, buf: BufferHandle, value: u32);
+    /// Write a value into the named member of a uniform block stored in the
+    /// buffer, only updating the bytes of that member.
+    fn set_block_member(&mut self, buf: &BufferHandle, block: &shade::BlockVar, name: &str,
+                        value: shade::UniformValue) -> Result<(), shade::BlockMemberError>;
+    /// Upload data into a region of the texture. The region has to fit into
+    /// the texture, whose storage is never specified again.
     fn update_texture(&mut self, &TextureHandle, &tex::ImageInfo, &Blob)
                       -> Result<(), TextureError>;
+    /// Upload a region like `update_texture`, taking the data from a larger
+    /// source image whose rows are `src_row_length` texels long, if set. This
+    /// allows uploading a crop of the image without copying it first.
+    fn update_texture_sub(&mut self, &TextureHandle, &tex::ImageInfo, &Blob,
+                          src_row_length: Option<u16>) -> Result<(), TextureError>;
+    /// Set the alignment in bytes of the rows of pixel data read by the
+    /// device, one of 1, 2, 4 or 8. `update_texture` sets it by itself, for
+    /// the data it uploads.
+    fn set_unpack_alignment(&mut self, alignment: u8);
+    /// Set the alignment in bytes of the rows of pixel data written back by
+    /// the device, one of 1, 2, 4 or 8.
+    fn set_pack_alignment(&mut self, alignment: u8);
+    /// Copy the region of `src` described by `src_img` into the `dst_level`
+    /// mipmap of `dst`, placing it at the `(x, y, z)` offset in `dst_offset`.
+    fn copy_texture(&mut self, src: &TextureHandle, src_img: &tex::ImageInfo,
+                    dst: &TextureHandle, dst_level: u8, dst_offset: (u16, u16, u16))
+                    -> Result<(), TextureError>;
     /// Submit a draw list. TODO: enforce `draw::DrawList` trait here
     fn submit(&mut self, list: &D);
+    /// Check if the device has been reset since the last call, for example
+    /// after a driver crash. All the resources have to be created again then.
+    /// Always `None` if the device can't detect resets, and resets are only
+    /// reported if the context was created with a reset notification
+    /// strategy (`LOSE_CONTEXT_ON_RESET` on GL).
+    fn check_device_reset(&mut self) -> Option<ResetReason>;
+    /// Get the time recorded by a `query_timestamp` command, in nanoseconds,
+    /// without waiting. `None` if the result is not available yet, or if
+    /// the device doesn't support timer queries.
+    fn get_timestamp(&mut self, &QueryHandle) -> Option<u64>;
+    /// Get the statistics of the commands submitted since the last reset.
+    fn get_frame_stats(&self) -> FrameStats;
+    /// Reset the statistics, typically at the start of a frame.
+    fn reset_frame_stats(&mut self);
+    /// Split the submitted lists into batches of at most `size` commands,
+    /// flushing after each batch, for the drivers with a watchdog killing
+    /// long command streams. `None`, the default, submits lists as a whole.
+    fn set_max_batch_size(&mut self, size: Option<uint>);
+    /// Make sure the submitted commands start executing in a finite time.
+    fn flush(&mut self);
+    /// Block until all the submitted commands have finished executing.
+    /// This is a hard CPU stall, only meant for synchronization with
+    /// external code and for benchmarking.
+    fn finish(&mut self);
 }
 
 /// A trait that OpenGL contexts implement.
@@ -275,4 +674,79 @@ pub trait GraphicsContext<T> {
 pub trait GlProvider {
     /// Load the GL command with the given name.
     fn get_proc_address(&self, function_name: &str) -> *const ::libc::c_void;
+    /// Check if the window system supports the given extension, such as
+    /// `WGL_EXT_swap_control`. Providers unable to tell report them all as
+    /// supported, leaving it to the loading of the commands.
+    fn is_extension_supported(&self, _extension: &str) -> bool {
+        true
+    }
+}
+
+/// Make `context` current on the calling thread and submit the list to the
+/// back-end, which lets a single back-end render into several windows.
+///
+/// The contexts have to share their objects with the one the back-end was
+/// created with, and use the same driver. Buffers, textures, samplers,
+/// shaders and programs are shared between them, but frame buffers, array
+/// buffers and queries belong to a single context: a front-end has to be
+/// created and used with the same context current.
+pub fn submit_to<L, T: ApiBackEnd<L>, C: GraphicsContext<T>>(context: &C, backend: &mut T,
+                                                             list: &L) {
+    context.make_current();
+    backend.submit(list);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::intrinsics::TypeId;
+    use super::{Blob, FrameStats, Handle, QueryInfo, make_fake_buffer, make_fake_texture};
+    use super::{BindProgram, Clear, Draw, DrawIndexed, SetScissor, TriangleList};
+    use super::attrib::U16;
+    use super::target::ClearData;
+
+    #[test]
+    fn test_blob_slice() {
+        let data = vec![1u16, 2, 3, 4];
+        let slice = data.as_slice();
+        assert_eq!(slice.get_address(), data.get_address());
+        assert_eq!(slice.get_size(), data.get_size());
+        assert_eq!(slice.slice_to(2).get_size(), 4);
+    }
+
+    fn info_type<T, I: 'static>(_: &Handle<T, I>) -> TypeId {
+        TypeId::of::<I>()
+    }
+
+    #[test]
+    fn test_handle_types() {
+        let buffer = make_fake_buffer();
+        let texture = make_fake_texture();
+        let query = Handle(0u32, QueryInfo);
+        // same name, but the handles can't be mixed
+        assert_eq!(buffer.get_name(), query.get_name());
+        assert!(info_type(&buffer) != info_type(&texture));
+        assert!(info_type(&buffer) != info_type(&query));
+        assert!(info_type(&texture) != info_type(&query));
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut stats = FrameStats::new();
+        let commands = [
+            Clear(ClearData { color: None, depth: Some(1.0), stencil: None }),
+            BindProgram(1),
+            SetScissor(None),
+            Draw(TriangleList, 0, 6, None),
+            DrawIndexed(TriangleList, U16, 0, 36, 0, None),
+            Draw(TriangleList, 0, 3, Some(4)),
+        ];
+        for com in commands.iter() {
+            stats.record(com);
+        }
+        assert_eq!(stats, FrameStats {
+            draw_count: 3,
+            vertex_count: 54,
+            state_change_count: 2,
+        });
+    }
 }
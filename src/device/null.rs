@@ -0,0 +1,291 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Null back-end, creating no actual objects.
+//!
+//! It is meant for testing the front-ends without a context: it only counts
+//! the commands, links and objects it is given. Every program it links has a
+//! `vec2` attribute `pos` at location 0 and a `vec3` attribute `color` at
+//! location 1, along with the uniforms and samplers set on the back-end
+//! beforehand.
+
+use std::default::Default;
+use {back, shade, tex};
+use {ApiBackEnd, Blob, Capabilities, FrameStats, Handle, PendingProgram, ResetReason};
+use {BufferHandle, ShaderHandle, ProgramHandle, SurfaceHandle, TextureHandle,
+     SamplerHandle, QueryHandle, BufferInfo, QueryInfo};
+use {BufferError, SurfaceError, TextureError, BufferUsage, StorageFlags};
+
+/// A back-end recording nothing but the number of commands and objects.
+pub struct NullBackEnd {
+    caps: Capabilities,
+    next_name: u32,
+    stats: FrameStats,
+    commands: uint,
+    links: uint,
+    shaders: uint,
+    programs: uint,
+    uniforms: Vec<shade::UniformVar>,
+    samplers: Vec<shade::SamplerVar>,
+}
+
+fn attribute(name: &str, location: uint, count: u8) -> shade::Attribute {
+    shade::Attribute {
+        name: name.to_string(),
+        location: location,
+        count: 1,
+        base_type: shade::BaseF32,
+        container: shade::Vector(count),
+    }
+}
+
+impl NullBackEnd {
+    /// Create a back-end with 16 texture units and no other capabilities.
+    pub fn new() -> NullBackEnd {
+        let mut caps: Capabilities = Default::default();
+        caps.set_max_texture_units(16);
+        NullBackEnd {
+            caps: caps,
+            next_name: 1,
+            stats: FrameStats::new(),
+            commands: 0,
+            links: 0,
+            shaders: 0,
+            programs: 0,
+            uniforms: Vec::new(),
+            samplers: Vec::new(),
+        }
+    }
+
+    /// Set the number of texture units reported by the capabilities.
+    pub fn set_max_texture_units(&mut self, units: uint) {
+        self.caps.set_max_texture_units(units);
+    }
+
+    /// Set the uniforms declared by the programs linked from now on.
+    pub fn set_uniforms(&mut self, uniforms: Vec<shade::UniformVar>) {
+        self.uniforms = uniforms;
+    }
+
+    /// Set the samplers declared by the programs linked from now on.
+    pub fn set_samplers(&mut self, samplers: Vec<shade::SamplerVar>) {
+        self.samplers = samplers;
+    }
+
+    /// Number of commands submitted so far.
+    pub fn get_command_count(&self) -> uint {
+        self.commands
+    }
+
+    /// Number of programs linked or relinked so far.
+    pub fn get_link_count(&self) -> uint {
+        self.links
+    }
+
+    /// Number of shaders created and not deleted yet.
+    pub fn get_shader_count(&self) -> uint {
+        self.shaders
+    }
+
+    /// Number of programs created and not deleted yet.
+    pub fn get_program_count(&self) -> uint {
+        self.programs
+    }
+
+    fn gen_name(&mut self) -> u32 {
+        self.next_name += 1;
+        self.next_name - 1
+    }
+
+    fn program_info(&self) -> shade::ProgramInfo {
+        shade::ProgramInfo {
+            attributes: vec![attribute("pos", 0, 2), attribute("color", 1, 3)],
+            uniforms: self.uniforms.clone(),
+            blocks: Vec::new(),
+            textures: self.samplers.clone(),
+            images: Vec::new(),
+        }
+    }
+}
+
+impl ApiBackEnd<::DrawList> for NullBackEnd {
+    fn get_capabilities<'a>(&'a self) -> &'a Capabilities {
+        &self.caps
+    }
+
+    fn create_buffer(&mut self) -> BufferHandle {
+        Handle(self.gen_name(), BufferInfo)
+    }
+
+    fn create_buffer_storage(&mut self, _: uint, _: StorageFlags)
+                             -> Result<BufferHandle, BufferError> {
+        Err(::UnsupportedBufferOperation)
+    }
+
+    fn create_array_buffer(&mut self) -> Result<back::ArrayBuffer, ()> {
+        Ok(0)
+    }
+
+    fn create_shader(&mut self, stage: shade::Stage, _: shade::ShaderSource)
+                     -> Result<ShaderHandle, shade::CreateShaderError> {
+        self.shaders += 1;
+        Ok(Handle(self.gen_name(), stage))
+    }
+
+    fn create_program(&mut self, _: &[ShaderHandle]) -> Result<ProgramHandle, ()> {
+        self.links += 1;
+        self.programs += 1;
+        let info = self.program_info();
+        Ok(Handle(self.gen_name(), info))
+    }
+
+    fn relink_program(&mut self, prog: &ProgramHandle, _: &[ShaderHandle]) -> Result<(), ()> {
+        self.links += 1;
+        if self.program_info() == *prog.get_info() { Ok(()) } else { Err(()) }
+    }
+
+    fn link_program_async(&mut self, _: &[ShaderHandle]) -> PendingProgram {
+        PendingProgram(self.gen_name())
+    }
+
+    fn is_program_ready(&mut self, _: &PendingProgram) -> bool {
+        true
+    }
+
+    fn finish_program(&mut self, _: PendingProgram) -> Result<ProgramHandle, ()> {
+        self.create_program(&[])
+    }
+
+    fn create_frame_buffer(&mut self) -> back::FrameBuffer {
+        self.gen_name()
+    }
+
+    fn create_surface(&mut self, info: tex::SurfaceInfo) -> Result<SurfaceHandle, SurfaceError> {
+        Ok(Handle(self.gen_name(), info))
+    }
+
+    fn create_texture(&mut self, info: tex::TextureInfo) -> Result<TextureHandle, TextureError> {
+        Ok(Handle(self.gen_name(), info))
+    }
+
+    fn create_texture_immutable(&mut self, info: tex::TextureInfo)
+                                -> Result<TextureHandle, TextureError> {
+        self.create_texture(info)
+    }
+
+    fn create_texture_view(&mut self, _: &TextureHandle, _: tex::Format, _: (u8, u8),
+                           _: (u16, u16)) -> Result<TextureHandle, TextureError> {
+        Err(::UnsupportedTextureView)
+    }
+
+    fn create_buffer_texture(&mut self, _: &BufferHandle, format: tex::Format)
+                             -> Result<TextureHandle, TextureError> {
+        Ok(Handle(self.gen_name(), tex::TextureInfo {
+            mipmap_range: (0, 0),
+            kind: tex::TextureBuffer,
+            format: format,
+            .. tex::TextureInfo::new()
+        }))
+    }
+
+    fn create_sampler(&mut self, info: tex::SamplerInfo) -> SamplerHandle {
+        Handle(0, info)
+    }
+
+    fn create_query(&mut self) -> QueryHandle {
+        Handle(self.gen_name(), QueryInfo)
+    }
+
+    fn import_buffer(&mut self, name: back::Buffer) -> BufferHandle {
+        Handle(name, BufferInfo)
+    }
+
+    fn import_texture(&mut self, name: back::Texture, info: tex::TextureInfo) -> TextureHandle {
+        Handle(name, info)
+    }
+
+    fn delete_buffer(&mut self, _: BufferHandle) {}
+    fn delete_shader(&mut self, _: ShaderHandle) {
+        self.shaders -= 1;
+    }
+    fn delete_program(&mut self, _: ProgramHandle) {
+        self.programs -= 1;
+    }
+    fn delete_surface(&mut self, _: SurfaceHandle) {}
+    fn delete_texture(&mut self, _: TextureHandle) {}
+    fn delete_sampler(&mut self, _: SamplerHandle) {}
+    fn delete_query(&mut self, _: QueryHandle) {}
+    fn flush_deletions(&mut self) {}
+    fn set_buffer_label(&mut self, _: &BufferHandle, _: &str) {}
+    fn set_texture_label(&mut self, _: &TextureHandle, _: &str) {}
+    fn set_program_label(&mut self, _: &ProgramHandle, _: &str) {}
+    fn update_buffer(&mut self, _: BufferHandle, _: &Blob, _: BufferUsage) {}
+
+    fn copy_buffer(&mut self, _: BufferHandle, _: uint, _: BufferHandle, _: uint, _: uint)
+                   -> Result<(), BufferError> {
+        Err(::UnsupportedBufferOperation)
+    }
+
+    fn clear_buffer(&mut self, _: BufferHandle, _: u32) {}
+
+    fn set_block_member(&mut self, _: &BufferHandle, _: &shade::BlockVar, _: &str,
+                        _: shade::UniformValue) -> Result<(), shade::BlockMemberError> {
+        Ok(())
+    }
+
+    fn update_texture(&mut self, _: &TextureHandle, _: &tex::ImageInfo, _: &Blob)
+                      -> Result<(), TextureError> {
+        Ok(())
+    }
+
+    fn update_texture_sub(&mut self, _: &TextureHandle, _: &tex::ImageInfo, _: &Blob,
+                          _: Option<u16>) -> Result<(), TextureError> {
+        Ok(())
+    }
+
+    fn set_unpack_alignment(&mut self, _: u8) {}
+    fn set_pack_alignment(&mut self, _: u8) {}
+
+    fn copy_texture(&mut self, _: &TextureHandle, _: &tex::ImageInfo, _: &TextureHandle,
+                    _: u8, _: (u16, u16, u16)) -> Result<(), TextureError> {
+        Ok(())
+    }
+
+    fn submit(&mut self, list: &::DrawList) {
+        for com in list.iter() {
+            self.stats.record(com);
+            self.commands += 1;
+        }
+    }
+
+    fn check_device_reset(&mut self) -> Option<ResetReason> {
+        None
+    }
+
+    fn get_timestamp(&mut self, _: &QueryHandle) -> Option<u64> {
+        None
+    }
+
+    fn get_frame_stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn reset_frame_stats(&mut self) {
+        self.stats = FrameStats::new();
+    }
+
+    fn set_max_batch_size(&mut self, _: Option<uint>) {}
+    fn flush(&mut self) {}
+    fn finish(&mut self) {}
+}
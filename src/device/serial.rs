@@ -0,0 +1,1262 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Binary encoding of the command stream, for recording and replaying it.
+//!
+//! Each command is written as a tag byte followed by its fields, with the
+//! integers and floats in little-endian order. The objects are referred to by
+//! their names, so replaying a stream requires the same objects to exist on
+//! the back-end. The tags of the commands and of the values they carry are
+//! fixed numbers, so reordering the variants of an enum doesn't change the
+//! encoding.
+
+use std::mem;
+use std::slice;
+use attrib;
+use shade;
+use state;
+use target;
+use tex;
+use {Blob, Command, Handle, SamplerHandle};
+
+/// Error decoding a command stream.
+#[deriving(Clone, PartialEq, Show)]
+pub enum SerialError {
+    /// The data ends in the middle of a command
+    ErrorTruncated,
+    /// A tag byte doesn't match any known command or value
+    ErrorInvalidTag(u8),
+}
+
+static TAG_BIND_PROGRAM: u8                 = 1;
+static TAG_BIND_ARRAY_BUFFER: u8            = 2;
+static TAG_BIND_ATTRIBUTE: u8               = 3;
+static TAG_BIND_INDEX: u8                   = 4;
+static TAG_BIND_FRAME_BUFFER: u8            = 5;
+static TAG_UNBIND_TARGET: u8                = 6;
+static TAG_BIND_TARGET_SURFACE: u8          = 7;
+static TAG_BIND_TARGET_TEXTURE: u8          = 8;
+static TAG_BIND_UNIFORM_BLOCK: u8           = 9;
+static TAG_SET_VIEWPORT: u8                 = 10;
+static TAG_SET_SCISSOR: u8                  = 11;
+static TAG_SET_DEPTH_CLAMP: u8              = 12;
+static TAG_UPDATE_BUFFER: u8                = 13;
+static TAG_CLEAR: u8                        = 14;
+static TAG_DRAW: u8                         = 15;
+static TAG_DRAW_INDEXED: u8                 = 16;
+static TAG_BIND_UNIFORM_BLOCK_RANGE: u8     = 17;
+static TAG_BIND_UNIFORM: u8                 = 18;
+static TAG_BIND_TEXTURE: u8                 = 19;
+static TAG_BIND_IMAGE: u8                   = 20;
+static TAG_SET_PRIMITIVE_STATE: u8          = 21;
+static TAG_SET_DEPTH_STENCIL_STATE: u8      = 22;
+static TAG_SET_DEPTH_RANGE: u8              = 23;
+static TAG_SET_CLIP_PLANES: u8              = 24;
+static TAG_SET_BLEND_STATE: u8              = 25;
+static TAG_SET_BLEND_TARGET: u8             = 26;
+static TAG_SET_COLOR_MASK: u8               = 27;
+static TAG_SET_LOGIC_OP: u8                 = 28;
+static TAG_BEGIN_OCCLUSION_QUERY: u8        = 29;
+static TAG_END_OCCLUSION_QUERY: u8          = 30;
+static TAG_QUERY_TIMESTAMP: u8              = 31;
+static TAG_BEGIN_CONDITIONAL: u8            = 32;
+static TAG_END_CONDITIONAL: u8              = 33;
+static TAG_UPDATE_TEXTURE: u8               = 34;
+static TAG_CLEAR_COLOR_INT: u8              = 35;
+static TAG_CLEAR_COLOR_UINT: u8             = 36;
+static TAG_DRAW_INDEXED_INDIRECT_COUNT: u8  = 37;
+
+struct Writer<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl<'a> Writer<'a> {
+    fn u8(&mut self, value: u8) {
+        self.out.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.u8(value as u8);
+        self.u8((value >> 8) as u8);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.u16(value as u16);
+        self.u16((value >> 16) as u16);
+    }
+
+    fn uint(&mut self, value: uint) {
+        let value = value as u64;
+        self.u32(value as u32);
+        self.u32((value >> 32) as u32);
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.u32(unsafe { mem::transmute(value) });
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(if value { 1 } else { 0 });
+    }
+
+    fn option<T>(&mut self, value: Option<T>, fun: |&mut Writer, T|) {
+        match value {
+            Some(v) => {
+                self.u8(1);
+                fun(self, v);
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn rect(&mut self, rect: target::Rect) {
+        self.u16(rect.x);
+        self.u16(rect.y);
+        self.u16(rect.w);
+        self.u16(rect.h);
+    }
+
+    fn color(&mut self, target::Color(c): target::Color) {
+        for &v in c.iter() {
+            self.f32(v);
+        }
+    }
+
+    fn target(&mut self, target: target::Target) {
+        match target {
+            target::TargetColor(index) => { self.u8(0); self.u8(index); },
+            target::TargetDepth => self.u8(1),
+            target::TargetStencil => self.u8(2),
+            target::TargetDepthStencil => self.u8(3),
+        }
+    }
+
+    fn int_sub_type(&mut self, sub: attrib::IntSubType) {
+        self.u8(match sub {
+            attrib::IntRaw => 0,
+            attrib::IntNormalized => 1,
+            attrib::IntAsFloat => 2,
+        });
+    }
+
+    fn int_size(&mut self, size: attrib::IntSize) {
+        self.u8(match size {
+            attrib::U8 => 0,
+            attrib::U16 => 1,
+            attrib::U32 => 2,
+        });
+    }
+
+    fn float_size(&mut self, size: attrib::FloatSize) {
+        self.u8(match size {
+            attrib::F16 => 0,
+            attrib::F32 => 1,
+            attrib::F64 => 2,
+        });
+    }
+
+    fn attrib_type(&mut self, atype: attrib::Type) {
+        match atype {
+            attrib::Int(sub, size, sign) => {
+                self.u8(0);
+                self.int_sub_type(sub);
+                self.int_size(size);
+                self.u8(match sign {
+                    attrib::Signed => 0,
+                    attrib::Unsigned => 1,
+                });
+            },
+            attrib::Float(sub, size) => {
+                self.u8(1);
+                self.u8(match sub {
+                    attrib::FloatDefault => 0,
+                    attrib::FloatPrecision => 1,
+                });
+                self.float_size(size);
+            },
+            attrib::Special => self.u8(2),
+        }
+    }
+
+    fn primitive(&mut self, prim: ::PrimitiveType) {
+        self.u8(match prim {
+            ::Point => 0,
+            ::Line => 1,
+            ::LineStrip => 2,
+            ::TriangleList => 3,
+            ::TriangleStrip => 4,
+            ::TriangleFan => 5,
+        });
+    }
+
+    fn uniform_value(&mut self, value: shade::UniformValue) {
+        match value {
+            shade::ValueI32(v) => { self.u8(0); self.u32(v as u32); },
+            shade::ValueF32(v) => { self.u8(1); self.f32(v); },
+            shade::ValueI32Vec(v) => {
+                self.u8(2);
+                for &x in v.iter() { self.u32(x as u32); }
+            },
+            shade::ValueF32Vec(v) => {
+                self.u8(3);
+                for &x in v.iter() { self.f32(x); }
+            },
+            shade::ValueF32Matrix(m) => {
+                self.u8(4);
+                for col in m.iter() {
+                    for &x in col.iter() { self.f32(x); }
+                }
+            },
+            shade::ValueF32MatrixTransposed(m) => {
+                self.u8(5);
+                for row in m.iter() {
+                    for &x in row.iter() { self.f32(x); }
+                }
+            },
+            shade::ValueF32Vec2(v) => {
+                self.u8(6);
+                for &x in v.iter() { self.f32(x); }
+            },
+            shade::ValueF32Vec3(v) => {
+                self.u8(7);
+                for &x in v.iter() { self.f32(x); }
+            },
+        }
+    }
+
+    fn texture_kind(&mut self, kind: tex::TextureKind) {
+        self.u8(match kind {
+            tex::Texture1D => 0,
+            tex::Texture1DArray => 1,
+            tex::Texture2D => 2,
+            tex::Texture2DArray => 3,
+            tex::TextureCube => 4,
+            tex::Texture3D => 5,
+            tex::TextureRect => 6,
+            tex::TextureBuffer => 7,
+        });
+    }
+
+    fn components(&mut self, comp: tex::Components) {
+        self.u8(match comp {
+            tex::R => 0,
+            tex::RG => 1,
+            tex::RGB => 2,
+            tex::RGBA => 3,
+        });
+    }
+
+    fn format(&mut self, format: tex::Format) {
+        match format {
+            tex::Float(comp, size) => {
+                self.u8(0);
+                self.components(comp);
+                self.float_size(size);
+            },
+            tex::Integer(comp, bits, sub) => {
+                self.u8(1);
+                self.components(comp);
+                self.u8(bits);
+                self.int_sub_type(sub);
+            },
+            tex::Unsigned(comp, bits, sub) => {
+                self.u8(2);
+                self.components(comp);
+                self.u8(bits);
+                self.int_sub_type(sub);
+            },
+            tex::R3G3B2 => self.u8(3),
+            tex::RGB5A1 => self.u8(4),
+            tex::RGB10A2 => self.u8(5),
+            tex::RGB10A2UI => self.u8(6),
+            tex::R11FG11FB10F => self.u8(7),
+            tex::RGB9E5 => self.u8(8),
+            tex::DEPTH24 => self.u8(9),
+            tex::DEPTH32F => self.u8(10),
+            tex::DEPTH24STENCIL8 => self.u8(11),
+            tex::STENCIL8 => self.u8(12),
+        }
+    }
+
+    fn image_info(&mut self, img: &tex::ImageInfo) {
+        self.u16(img.xoffset);
+        self.u16(img.yoffset);
+        self.u16(img.zoffset);
+        self.u16(img.width);
+        self.u16(img.height);
+        self.u16(img.depth);
+        self.format(img.format);
+        self.u8(img.mipmap);
+    }
+
+    fn wrap_mode(&mut self, wrap: tex::WrapMode) {
+        self.u8(match wrap {
+            tex::Tile => 0,
+            tex::Mirror => 1,
+            tex::Clamp => 2,
+            tex::Border => 3,
+        });
+    }
+
+    fn sampler(&mut self, sampler: &SamplerHandle) {
+        let info = sampler.get_info();
+        self.u32(sampler.get_name());
+        match info.filtering {
+            tex::Scale => self.u8(0),
+            tex::Mipmap => self.u8(1),
+            tex::Bilinear => self.u8(2),
+            tex::Trilinear => self.u8(3),
+            tex::Anisotropic(max) => { self.u8(4); self.u8(max); },
+        }
+        let (s, t, r) = info.wrap_mode;
+        self.wrap_mode(s);
+        self.wrap_mode(t);
+        self.wrap_mode(r);
+        self.f32(info.lod_bias);
+        self.f32(info.lod_range.val0());
+        self.f32(info.lod_range.val1());
+        self.color(info.border_color);
+    }
+
+    fn comparison(&mut self, fun: state::Comparison) {
+        self.u8(match fun {
+            state::Never => 0,
+            state::Less => 1,
+            state::LessEqual => 2,
+            state::Equal => 3,
+            state::GreaterEqual => 4,
+            state::Greater => 5,
+            state::NotEqual => 6,
+            state::Always => 7,
+        });
+    }
+
+    fn cull_mode(&mut self, cull: state::CullMode) {
+        self.u8(match cull {
+            state::CullNothing => 0,
+            state::CullFront => 1,
+            state::CullBack => 2,
+        });
+    }
+
+    fn primitive_state(&mut self, prim: &state::Primitive) {
+        self.u8(match prim.front_face {
+            state::Clockwise => 0,
+            state::CounterClockwise => 1,
+        });
+        match prim.method {
+            state::Point => self.u8(0),
+            state::Line(width) => { self.u8(1); self.f32(width); },
+            state::Fill(cull) => { self.u8(2); self.cull_mode(cull); },
+        }
+        match prim.offset {
+            state::NoOffset => self.u8(0),
+            state::Offset(factor, units) => {
+                self.u8(1);
+                self.f32(factor);
+                self.u32(units);
+            },
+        }
+    }
+
+    fn stencil_op(&mut self, op: state::StencilOp) {
+        self.u8(match op {
+            state::OpKeep => 0,
+            state::OpZero => 1,
+            state::OpReplace => 2,
+            state::OpIncrementClamp => 3,
+            state::OpIncrementWrap => 4,
+            state::OpDecrementClamp => 5,
+            state::OpDecrementWrap => 6,
+            state::OpInvert => 7,
+        });
+    }
+
+    fn stencil_side(&mut self, side: &state::StencilSide) {
+        self.comparison(side.fun);
+        self.u8(side.value);
+        self.u8(side.mask_read);
+        self.u8(side.mask_write);
+        self.stencil_op(side.op_fail);
+        self.stencil_op(side.op_depth_fail);
+        self.stencil_op(side.op_pass);
+    }
+
+    fn factor(&mut self, state::Factor(flag, value): state::Factor) {
+        self.u8(match flag {
+            state::Normal => 0,
+            state::Inverse => 1,
+        });
+        self.u8(match value {
+            state::Zero => 0,
+            state::SourceColor => 1,
+            state::SourceAlpha => 2,
+            state::SourceAlphaSaturated => 3,
+            state::DestColor => 4,
+            state::DestAlpha => 5,
+            state::ConstColor => 6,
+            state::ConstAlpha => 7,
+        });
+    }
+
+    fn blend_channel(&mut self, chan: &state::BlendChannel) {
+        self.u8(match chan.equation {
+            state::FuncAdd => 0,
+            state::FuncSub => 1,
+            state::FuncRevSub => 2,
+            state::FuncMin => 3,
+            state::FuncMax => 4,
+        });
+        self.factor(chan.source);
+        self.factor(chan.destination);
+    }
+
+    fn blend(&mut self, blend: Option<state::Blend>) {
+        self.option(blend, |w, b| {
+            w.blend_channel(&b.color);
+            w.blend_channel(&b.alpha);
+            w.color(b.value);
+        });
+    }
+
+    fn logic_op(&mut self, op: state::LogicOp) {
+        self.u8(match op {
+            state::LogicClear => 0,
+            state::LogicAnd => 1,
+            state::LogicAndReverse => 2,
+            state::LogicCopy => 3,
+            state::LogicAndInverted => 4,
+            state::LogicNoop => 5,
+            state::LogicXor => 6,
+            state::LogicOr => 7,
+            state::LogicNor => 8,
+            state::LogicEquiv => 9,
+            state::LogicInvert => 10,
+            state::LogicOrReverse => 11,
+            state::LogicCopyInverted => 12,
+            state::LogicOrInverted => 13,
+            state::LogicNand => 14,
+            state::LogicSet => 15,
+        });
+    }
+
+    fn blob(&mut self, data: &Blob) {
+        self.u32(data.get_size() as u32);
+        unsafe {
+            slice::raw::buf_as_slice(data.get_address() as *const u8, data.get_size(),
+                                     |bytes| self.out.push_all(bytes));
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, SerialError> {
+        if self.pos >= self.data.len() {
+            return Err(ErrorTruncated)
+        }
+        self.pos += 1;
+        Ok(self.data[self.pos - 1])
+    }
+
+    fn u16(&mut self) -> Result<u16, SerialError> {
+        let lo = try!(self.u8()) as u16;
+        let hi = try!(self.u8()) as u16;
+        Ok(lo | (hi << 8))
+    }
+
+    fn u32(&mut self) -> Result<u32, SerialError> {
+        let lo = try!(self.u16()) as u32;
+        let hi = try!(self.u16()) as u32;
+        Ok(lo | (hi << 16))
+    }
+
+    fn uint(&mut self) -> Result<uint, SerialError> {
+        let lo = try!(self.u32()) as u64;
+        let hi = try!(self.u32()) as u64;
+        Ok((lo | (hi << 32)) as uint)
+    }
+
+    fn f32(&mut self) -> Result<f32, SerialError> {
+        let bits = try!(self.u32());
+        Ok(unsafe { mem::transmute(bits) })
+    }
+
+    fn bool(&mut self) -> Result<bool, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn option<T>(&mut self, fun: |&mut Reader<'a>| -> Result<T, SerialError>)
+                 -> Result<Option<T>, SerialError> {
+        if try!(self.bool()) {
+            fun(self).map(|v| Some(v))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn rect(&mut self) -> Result<target::Rect, SerialError> {
+        Ok(target::Rect {
+            x: try!(self.u16()),
+            y: try!(self.u16()),
+            w: try!(self.u16()),
+            h: try!(self.u16()),
+        })
+    }
+
+    fn color(&mut self) -> Result<target::Color, SerialError> {
+        let mut c = [0.0f32, ..4];
+        for v in c.mut_iter() {
+            *v = try!(self.f32());
+        }
+        Ok(target::Color(c))
+    }
+
+    fn target(&mut self) -> Result<target::Target, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(target::TargetColor(try!(self.u8()))),
+            1 => Ok(target::TargetDepth),
+            2 => Ok(target::TargetStencil),
+            3 => Ok(target::TargetDepthStencil),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn int_sub_type(&mut self) -> Result<attrib::IntSubType, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(attrib::IntRaw),
+            1 => Ok(attrib::IntNormalized),
+            2 => Ok(attrib::IntAsFloat),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn int_size(&mut self) -> Result<attrib::IntSize, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(attrib::U8),
+            1 => Ok(attrib::U16),
+            2 => Ok(attrib::U32),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn float_size(&mut self) -> Result<attrib::FloatSize, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(attrib::F16),
+            1 => Ok(attrib::F32),
+            2 => Ok(attrib::F64),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn attrib_type(&mut self) -> Result<attrib::Type, SerialError> {
+        match try!(self.u8()) {
+            0 => {
+                let sub = try!(self.int_sub_type());
+                let size = try!(self.int_size());
+                let sign = match try!(self.u8()) {
+                    0 => attrib::Signed,
+                    1 => attrib::Unsigned,
+                    t => return Err(ErrorInvalidTag(t)),
+                };
+                Ok(attrib::Int(sub, size, sign))
+            },
+            1 => {
+                let sub = match try!(self.u8()) {
+                    0 => attrib::FloatDefault,
+                    1 => attrib::FloatPrecision,
+                    t => return Err(ErrorInvalidTag(t)),
+                };
+                Ok(attrib::Float(sub, try!(self.float_size())))
+            },
+            2 => Ok(attrib::Special),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn primitive(&mut self) -> Result<::PrimitiveType, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(::Point),
+            1 => Ok(::Line),
+            2 => Ok(::LineStrip),
+            3 => Ok(::TriangleList),
+            4 => Ok(::TriangleStrip),
+            5 => Ok(::TriangleFan),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn uniform_value(&mut self) -> Result<shade::UniformValue, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(shade::ValueI32(try!(self.u32()) as i32)),
+            1 => Ok(shade::ValueF32(try!(self.f32()))),
+            2 => {
+                let mut v = [0i32, ..4];
+                for x in v.mut_iter() { *x = try!(self.u32()) as i32; }
+                Ok(shade::ValueI32Vec(v))
+            },
+            3 => {
+                let mut v = [0.0f32, ..4];
+                for x in v.mut_iter() { *x = try!(self.f32()); }
+                Ok(shade::ValueF32Vec(v))
+            },
+            4 => Ok(shade::ValueF32Matrix(try!(self.matrix()))),
+            5 => Ok(shade::ValueF32MatrixTransposed(try!(self.matrix()))),
+            6 => {
+                let mut v = [0.0f32, ..2];
+                for x in v.mut_iter() { *x = try!(self.f32()); }
+                Ok(shade::ValueF32Vec2(v))
+            },
+            7 => {
+                let mut v = [0.0f32, ..3];
+                for x in v.mut_iter() { *x = try!(self.f32()); }
+                Ok(shade::ValueF32Vec3(v))
+            },
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn matrix(&mut self) -> Result<[[f32, ..4], ..4], SerialError> {
+        let mut m = [[0.0f32, ..4], ..4];
+        for vec in m.mut_iter() {
+            for x in vec.mut_iter() { *x = try!(self.f32()); }
+        }
+        Ok(m)
+    }
+
+    fn texture_kind(&mut self) -> Result<tex::TextureKind, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(tex::Texture1D),
+            1 => Ok(tex::Texture1DArray),
+            2 => Ok(tex::Texture2D),
+            3 => Ok(tex::Texture2DArray),
+            4 => Ok(tex::TextureCube),
+            5 => Ok(tex::Texture3D),
+            6 => Ok(tex::TextureRect),
+            7 => Ok(tex::TextureBuffer),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn components(&mut self) -> Result<tex::Components, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(tex::R),
+            1 => Ok(tex::RG),
+            2 => Ok(tex::RGB),
+            3 => Ok(tex::RGBA),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn format(&mut self) -> Result<tex::Format, SerialError> {
+        match try!(self.u8()) {
+            0 => {
+                let comp = try!(self.components());
+                Ok(tex::Float(comp, try!(self.float_size())))
+            },
+            1 => {
+                let comp = try!(self.components());
+                let bits = try!(self.u8());
+                Ok(tex::Integer(comp, bits, try!(self.int_sub_type())))
+            },
+            2 => {
+                let comp = try!(self.components());
+                let bits = try!(self.u8());
+                Ok(tex::Unsigned(comp, bits, try!(self.int_sub_type())))
+            },
+            3 => Ok(tex::R3G3B2),
+            4 => Ok(tex::RGB5A1),
+            5 => Ok(tex::RGB10A2),
+            6 => Ok(tex::RGB10A2UI),
+            7 => Ok(tex::R11FG11FB10F),
+            8 => Ok(tex::RGB9E5),
+            9 => Ok(tex::DEPTH24),
+            10 => Ok(tex::DEPTH32F),
+            11 => Ok(tex::DEPTH24STENCIL8),
+            12 => Ok(tex::STENCIL8),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn image_info(&mut self) -> Result<tex::ImageInfo, SerialError> {
+        Ok(tex::ImageInfo {
+            xoffset: try!(self.u16()),
+            yoffset: try!(self.u16()),
+            zoffset: try!(self.u16()),
+            width: try!(self.u16()),
+            height: try!(self.u16()),
+            depth: try!(self.u16()),
+            format: try!(self.format()),
+            mipmap: try!(self.u8()),
+        })
+    }
+
+    fn image_access(&mut self) -> Result<tex::ImageAccess, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(tex::ReadOnly),
+            1 => Ok(tex::WriteOnly),
+            2 => Ok(tex::ReadWrite),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn wrap_mode(&mut self) -> Result<tex::WrapMode, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(tex::Tile),
+            1 => Ok(tex::Mirror),
+            2 => Ok(tex::Clamp),
+            3 => Ok(tex::Border),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn sampler(&mut self) -> Result<SamplerHandle, SerialError> {
+        let name = try!(self.u32());
+        let filtering = match try!(self.u8()) {
+            0 => tex::Scale,
+            1 => tex::Mipmap,
+            2 => tex::Bilinear,
+            3 => tex::Trilinear,
+            4 => tex::Anisotropic(try!(self.u8())),
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        let s = try!(self.wrap_mode());
+        let t = try!(self.wrap_mode());
+        let r = try!(self.wrap_mode());
+        let lod_bias = try!(self.f32());
+        let lod_min = try!(self.f32());
+        let lod_max = try!(self.f32());
+        Ok(Handle::new(name, tex::SamplerInfo {
+            filtering: filtering,
+            wrap_mode: (s, t, r),
+            lod_bias: lod_bias,
+            lod_range: (lod_min, lod_max),
+            border_color: try!(self.color()),
+        }))
+    }
+
+    fn comparison(&mut self) -> Result<state::Comparison, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(state::Never),
+            1 => Ok(state::Less),
+            2 => Ok(state::LessEqual),
+            3 => Ok(state::Equal),
+            4 => Ok(state::GreaterEqual),
+            5 => Ok(state::Greater),
+            6 => Ok(state::NotEqual),
+            7 => Ok(state::Always),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn cull_mode(&mut self) -> Result<state::CullMode, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(state::CullNothing),
+            1 => Ok(state::CullFront),
+            2 => Ok(state::CullBack),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn primitive_state(&mut self) -> Result<state::Primitive, SerialError> {
+        let front_face = match try!(self.u8()) {
+            0 => state::Clockwise,
+            1 => state::CounterClockwise,
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        let method = match try!(self.u8()) {
+            0 => state::Point,
+            1 => state::Line(try!(self.f32())),
+            2 => state::Fill(try!(self.cull_mode())),
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        let offset = match try!(self.u8()) {
+            0 => state::NoOffset,
+            1 => {
+                let factor = try!(self.f32());
+                state::Offset(factor, try!(self.u32()))
+            },
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        Ok(state::Primitive {
+            front_face: front_face,
+            method: method,
+            offset: offset,
+        })
+    }
+
+    fn stencil_op(&mut self) -> Result<state::StencilOp, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(state::OpKeep),
+            1 => Ok(state::OpZero),
+            2 => Ok(state::OpReplace),
+            3 => Ok(state::OpIncrementClamp),
+            4 => Ok(state::OpIncrementWrap),
+            5 => Ok(state::OpDecrementClamp),
+            6 => Ok(state::OpDecrementWrap),
+            7 => Ok(state::OpInvert),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn stencil_side(&mut self) -> Result<state::StencilSide, SerialError> {
+        Ok(state::StencilSide {
+            fun: try!(self.comparison()),
+            value: try!(self.u8()),
+            mask_read: try!(self.u8()),
+            mask_write: try!(self.u8()),
+            op_fail: try!(self.stencil_op()),
+            op_depth_fail: try!(self.stencil_op()),
+            op_pass: try!(self.stencil_op()),
+        })
+    }
+
+    fn factor(&mut self) -> Result<state::Factor, SerialError> {
+        let flag = match try!(self.u8()) {
+            0 => state::Normal,
+            1 => state::Inverse,
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        let value = match try!(self.u8()) {
+            0 => state::Zero,
+            1 => state::SourceColor,
+            2 => state::SourceAlpha,
+            3 => state::SourceAlphaSaturated,
+            4 => state::DestColor,
+            5 => state::DestAlpha,
+            6 => state::ConstColor,
+            7 => state::ConstAlpha,
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        Ok(state::Factor(flag, value))
+    }
+
+    fn blend_channel(&mut self) -> Result<state::BlendChannel, SerialError> {
+        let equation = match try!(self.u8()) {
+            0 => state::FuncAdd,
+            1 => state::FuncSub,
+            2 => state::FuncRevSub,
+            3 => state::FuncMin,
+            4 => state::FuncMax,
+            t => return Err(ErrorInvalidTag(t)),
+        };
+        Ok(state::BlendChannel {
+            equation: equation,
+            source: try!(self.factor()),
+            destination: try!(self.factor()),
+        })
+    }
+
+    fn blend(&mut self) -> Result<Option<state::Blend>, SerialError> {
+        self.option(|r| Ok(state::Blend {
+            color: try!(r.blend_channel()),
+            alpha: try!(r.blend_channel()),
+            value: try!(r.color()),
+        }))
+    }
+
+    fn logic_op(&mut self) -> Result<state::LogicOp, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(state::LogicClear),
+            1 => Ok(state::LogicAnd),
+            2 => Ok(state::LogicAndReverse),
+            3 => Ok(state::LogicCopy),
+            4 => Ok(state::LogicAndInverted),
+            5 => Ok(state::LogicNoop),
+            6 => Ok(state::LogicXor),
+            7 => Ok(state::LogicOr),
+            8 => Ok(state::LogicNor),
+            9 => Ok(state::LogicEquiv),
+            10 => Ok(state::LogicInvert),
+            11 => Ok(state::LogicOrReverse),
+            12 => Ok(state::LogicCopyInverted),
+            13 => Ok(state::LogicOrInverted),
+            14 => Ok(state::LogicNand),
+            15 => Ok(state::LogicSet),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn conditional_mode(&mut self) -> Result<::ConditionalMode, SerialError> {
+        match try!(self.u8()) {
+            0 => Ok(::ConditionalWait),
+            1 => Ok(::ConditionalNoWait),
+            2 => Ok(::ConditionalRegionWait),
+            3 => Ok(::ConditionalRegionNoWait),
+            t => Err(ErrorInvalidTag(t)),
+        }
+    }
+
+    fn blob(&mut self) -> Result<Box<Blob + Send>, SerialError> {
+        let size = try!(self.u32()) as uint;
+        if size > self.data.len() - self.pos {
+            return Err(ErrorTruncated)
+        }
+        let bytes = self.data.slice(self.pos, self.pos + size).to_vec();
+        self.pos += size;
+        Ok(box bytes as Box<Blob + Send>)
+    }
+}
+
+/// Append the encoding of a command to `out`.
+pub fn encode(command: &Command, out: &mut Vec<u8>) {
+    let mut w = Writer { out: out };
+    match *command {
+        ::BindProgram(name) => { w.u8(TAG_BIND_PROGRAM); w.u32(name); },
+        ::BindArrayBuffer(name) => { w.u8(TAG_BIND_ARRAY_BUFFER); w.u32(name); },
+        ::BindAttribute(slot, buf, count, atype, stride, offset, rate) => {
+            w.u8(TAG_BIND_ATTRIBUTE);
+            w.u8(slot);
+            w.u32(buf);
+            w.u8(count);
+            w.attrib_type(atype);
+            w.u8(stride);
+            w.u32(offset);
+            w.u8(rate);
+        },
+        ::BindIndex(name) => { w.u8(TAG_BIND_INDEX); w.u32(name); },
+        ::BindFrameBuffer(name) => { w.u8(TAG_BIND_FRAME_BUFFER); w.u32(name); },
+        ::UnbindTarget(target) => { w.u8(TAG_UNBIND_TARGET); w.target(target); },
+        ::BindTargetSurface(target, name) => {
+            w.u8(TAG_BIND_TARGET_SURFACE);
+            w.target(target);
+            w.u32(name);
+        },
+        ::BindTargetTexture(target, name, level, layer) => {
+            w.u8(TAG_BIND_TARGET_TEXTURE);
+            w.target(target);
+            w.u32(name);
+            w.u8(level);
+            w.option(layer, |w, layer| w.u16(layer));
+        },
+        ::BindUniformBlock(program, slot, index, buf) => {
+            w.u8(TAG_BIND_UNIFORM_BLOCK);
+            w.u32(program);
+            w.u8(slot);
+            w.u8(index);
+            w.u32(buf);
+        },
+        ::BindUniformBlockRange(program, slot, index, buf, offset, size) => {
+            w.u8(TAG_BIND_UNIFORM_BLOCK_RANGE);
+            w.u32(program);
+            w.u8(slot);
+            w.u8(index);
+            w.u32(buf);
+            w.uint(offset);
+            w.uint(size);
+        },
+        ::BindUniform(location, value) => {
+            w.u8(TAG_BIND_UNIFORM);
+            w.uint(location);
+            w.uniform_value(value);
+        },
+        ::BindTexture(slot, kind, name, ref sampler) => {
+            w.u8(TAG_BIND_TEXTURE);
+            w.u8(slot);
+            w.texture_kind(kind);
+            w.u32(name);
+            w.option(sampler.as_ref(), |w, sampler| w.sampler(sampler));
+        },
+        ::BindImage(slot, name, level, access, format) => {
+            w.u8(TAG_BIND_IMAGE);
+            w.u8(slot);
+            w.u32(name);
+            w.u8(level);
+            w.u8(match access {
+                tex::ReadOnly => 0,
+                tex::WriteOnly => 1,
+                tex::ReadWrite => 2,
+            });
+            w.format(format);
+        },
+        ::SetPrimitiveState(ref prim) => {
+            w.u8(TAG_SET_PRIMITIVE_STATE);
+            w.primitive_state(prim);
+        },
+        ::SetViewport(rect) => { w.u8(TAG_SET_VIEWPORT); w.rect(rect); },
+        ::SetScissor(rect) => {
+            w.u8(TAG_SET_SCISSOR);
+            w.option(rect, |w, rect| w.rect(rect));
+        },
+        ::SetDepthStencilState(depth, ref stencil, cull) => {
+            w.u8(TAG_SET_DEPTH_STENCIL_STATE);
+            w.option(depth, |w, depth| {
+                w.comparison(depth.fun);
+                w.bool(depth.write);
+            });
+            w.option(stencil.as_ref(), |w, stencil| {
+                w.stencil_side(&stencil.front);
+                w.stencil_side(&stencil.back);
+            });
+            w.cull_mode(cull);
+        },
+        ::SetDepthRange(range) => {
+            w.u8(TAG_SET_DEPTH_RANGE);
+            w.f32(range.near);
+            w.f32(range.far);
+        },
+        ::SetClipPlanes(mask) => { w.u8(TAG_SET_CLIP_PLANES); w.u8(mask); },
+        ::SetDepthClamp(enable) => { w.u8(TAG_SET_DEPTH_CLAMP); w.bool(enable); },
+        ::SetBlendState(blend) => { w.u8(TAG_SET_BLEND_STATE); w.blend(blend); },
+        ::SetBlendTarget(index, blend) => {
+            w.u8(TAG_SET_BLEND_TARGET);
+            w.u8(index);
+            w.blend(blend);
+        },
+        ::SetColorMask(mask) => { w.u8(TAG_SET_COLOR_MASK); w.u32(mask.bits()); },
+        ::SetLogicOp(op) => {
+            w.u8(TAG_SET_LOGIC_OP);
+            w.option(op, |w, op| w.logic_op(op));
+        },
+        ::BeginOcclusionQuery(name) => { w.u8(TAG_BEGIN_OCCLUSION_QUERY); w.u32(name); },
+        ::EndOcclusionQuery => w.u8(TAG_END_OCCLUSION_QUERY),
+        ::QueryTimestamp(name) => { w.u8(TAG_QUERY_TIMESTAMP); w.u32(name); },
+        ::BeginConditional(name, mode) => {
+            w.u8(TAG_BEGIN_CONDITIONAL);
+            w.u32(name);
+            w.u8(match mode {
+                ::ConditionalWait => 0,
+                ::ConditionalNoWait => 1,
+                ::ConditionalRegionWait => 2,
+                ::ConditionalRegionNoWait => 3,
+            });
+        },
+        ::EndConditional => w.u8(TAG_END_CONDITIONAL),
+        ::UpdateBuffer(buf, ref data) => {
+            w.u8(TAG_UPDATE_BUFFER);
+            w.u32(buf);
+            w.blob(*data);
+        },
+        ::UpdateTexture(kind, name, ref img, ref data) => {
+            w.u8(TAG_UPDATE_TEXTURE);
+            w.texture_kind(kind);
+            w.u32(name);
+            w.image_info(img);
+            w.blob(*data);
+        },
+        ::Clear(ref data) => {
+            w.u8(TAG_CLEAR);
+            w.option(data.color, |w, color| w.color(color));
+            w.option(data.depth, |w, depth| w.f32(depth));
+            w.option(data.stencil, |w, stencil| w.u8(stencil));
+        },
+        ::ClearColorInt(index, color) => {
+            w.u8(TAG_CLEAR_COLOR_INT);
+            w.u8(index);
+            for &v in color.iter() { w.u32(v as u32); }
+        },
+        ::ClearColorUint(index, color) => {
+            w.u8(TAG_CLEAR_COLOR_UINT);
+            w.u8(index);
+            for &v in color.iter() { w.u32(v); }
+        },
+        ::Draw(prim, start, count, instances) => {
+            w.u8(TAG_DRAW);
+            w.primitive(prim);
+            w.u32(start);
+            w.u32(count);
+            w.option(instances, |w, num| w.u32(num));
+        },
+        ::DrawIndexed(prim, itype, start, count, base, instances) => {
+            w.u8(TAG_DRAW_INDEXED);
+            w.primitive(prim);
+            w.int_size(itype);
+            w.u32(start);
+            w.u32(count);
+            w.u32(base as u32);
+            w.option(instances, |w, num| w.u32(num));
+        },
+        ::DrawIndexedIndirectCount(prim, itype, buf, offset, count_buf, count_offset,
+                                   max_count, stride) => {
+            w.u8(TAG_DRAW_INDEXED_INDIRECT_COUNT);
+            w.primitive(prim);
+            w.int_size(itype);
+            w.u32(buf);
+            w.uint(offset);
+            w.u32(count_buf);
+            w.uint(count_offset);
+            w.u32(max_count);
+            w.u32(stride);
+        },
+    }
+}
+
+/// Decode a whole stream of commands, as produced by `encode`.
+pub fn decode(data: &[u8]) -> Result<Vec<Command>, SerialError> {
+    let mut r = Reader { data: data, pos: 0 };
+    let mut commands = Vec::new();
+    while r.pos < data.len() {
+        commands.push(try!(decode_one(&mut r)));
+    }
+    Ok(commands)
+}
+
+fn decode_one(r: &mut Reader) -> Result<Command, SerialError> {
+    let tag = try!(r.u8());
+    Ok(match tag {
+        TAG_BIND_PROGRAM => ::BindProgram(try!(r.u32())),
+        TAG_BIND_ARRAY_BUFFER => ::BindArrayBuffer(try!(r.u32())),
+        TAG_BIND_ATTRIBUTE => {
+            let slot = try!(r.u8());
+            let buf = try!(r.u32());
+            let count = try!(r.u8());
+            let atype = try!(r.attrib_type());
+            let stride = try!(r.u8());
+            let offset = try!(r.u32());
+            let rate = try!(r.u8());
+            ::BindAttribute(slot, buf, count, atype, stride, offset, rate)
+        },
+        TAG_BIND_INDEX => ::BindIndex(try!(r.u32())),
+        TAG_BIND_FRAME_BUFFER => ::BindFrameBuffer(try!(r.u32())),
+        TAG_UNBIND_TARGET => ::UnbindTarget(try!(r.target())),
+        TAG_BIND_TARGET_SURFACE => {
+            let target = try!(r.target());
+            ::BindTargetSurface(target, try!(r.u32()))
+        },
+        TAG_BIND_TARGET_TEXTURE => {
+            let target = try!(r.target());
+            let name = try!(r.u32());
+            let level = try!(r.u8());
+            ::BindTargetTexture(target, name, level, try!(r.option(|r| r.u16())))
+        },
+        TAG_BIND_UNIFORM_BLOCK => {
+            let program = try!(r.u32());
+            let slot = try!(r.u8());
+            let index = try!(r.u8());
+            ::BindUniformBlock(program, slot, index, try!(r.u32()))
+        },
+        TAG_BIND_UNIFORM_BLOCK_RANGE => {
+            let program = try!(r.u32());
+            let slot = try!(r.u8());
+            let index = try!(r.u8());
+            let buf = try!(r.u32());
+            let offset = try!(r.uint());
+            ::BindUniformBlockRange(program, slot, index, buf, offset, try!(r.uint()))
+        },
+        TAG_BIND_UNIFORM => {
+            let location = try!(r.uint());
+            ::BindUniform(location, try!(r.uniform_value()))
+        },
+        TAG_BIND_TEXTURE => {
+            let slot = try!(r.u8());
+            let kind = try!(r.texture_kind());
+            let name = try!(r.u32());
+            ::BindTexture(slot, kind, name, try!(r.option(|r| r.sampler())))
+        },
+        TAG_BIND_IMAGE => {
+            let slot = try!(r.u8());
+            let name = try!(r.u32());
+            let level = try!(r.u8());
+            let access = try!(r.image_access());
+            ::BindImage(slot, name, level, access, try!(r.format()))
+        },
+        TAG_SET_PRIMITIVE_STATE => ::SetPrimitiveState(try!(r.primitive_state())),
+        TAG_SET_VIEWPORT => ::SetViewport(try!(r.rect())),
+        TAG_SET_SCISSOR => ::SetScissor(try!(r.option(|r| r.rect()))),
+        TAG_SET_DEPTH_STENCIL_STATE => {
+            let depth = try!(r.option(|r| {
+                let fun = try!(r.comparison());
+                Ok(state::Depth { fun: fun, write: try!(r.bool()) })
+            }));
+            let stencil = try!(r.option(|r| {
+                let front = try!(r.stencil_side());
+                Ok(state::Stencil { front: front, back: try!(r.stencil_side()) })
+            }));
+            ::SetDepthStencilState(depth, stencil, try!(r.cull_mode()))
+        },
+        TAG_SET_DEPTH_RANGE => {
+            let near = try!(r.f32());
+            ::SetDepthRange(state::DepthRange { near: near, far: try!(r.f32()) })
+        },
+        TAG_SET_CLIP_PLANES => ::SetClipPlanes(try!(r.u8())),
+        TAG_SET_DEPTH_CLAMP => ::SetDepthClamp(try!(r.bool())),
+        TAG_SET_BLEND_STATE => ::SetBlendState(try!(r.blend())),
+        TAG_SET_BLEND_TARGET => {
+            let index = try!(r.u8());
+            ::SetBlendTarget(index, try!(r.blend()))
+        },
+        TAG_SET_COLOR_MASK => ::SetColorMask(state::ColorMask::from_bits_truncate(try!(r.u32()))),
+        TAG_SET_LOGIC_OP => ::SetLogicOp(try!(r.option(|r| r.logic_op()))),
+        TAG_BEGIN_OCCLUSION_QUERY => ::BeginOcclusionQuery(try!(r.u32())),
+        TAG_END_OCCLUSION_QUERY => ::EndOcclusionQuery,
+        TAG_QUERY_TIMESTAMP => ::QueryTimestamp(try!(r.u32())),
+        TAG_BEGIN_CONDITIONAL => {
+            let name = try!(r.u32());
+            ::BeginConditional(name, try!(r.conditional_mode()))
+        },
+        TAG_END_CONDITIONAL => ::EndConditional,
+        TAG_UPDATE_BUFFER => {
+            let buf = try!(r.u32());
+            ::UpdateBuffer(buf, try!(r.blob()))
+        },
+        TAG_UPDATE_TEXTURE => {
+            let kind = try!(r.texture_kind());
+            let name = try!(r.u32());
+            let img = try!(r.image_info());
+            ::UpdateTexture(kind, name, img, try!(r.blob()))
+        },
+        TAG_CLEAR => {
+            let color = try!(r.option(|r| r.color()));
+            let depth = try!(r.option(|r| r.f32()));
+            let stencil = try!(r.option(|r| r.u8()));
+            ::Clear(target::ClearData { color: color, depth: depth, stencil: stencil })
+        },
+        TAG_CLEAR_COLOR_INT => {
+            let index = try!(r.u8());
+            let mut color = [0i32, ..4];
+            for v in color.mut_iter() { *v = try!(r.u32()) as i32; }
+            ::ClearColorInt(index, color)
+        },
+        TAG_CLEAR_COLOR_UINT => {
+            let index = try!(r.u8());
+            let mut color = [0u32, ..4];
+            for v in color.mut_iter() { *v = try!(r.u32()); }
+            ::ClearColorUint(index, color)
+        },
+        TAG_DRAW => {
+            let prim = try!(r.primitive());
+            let start = try!(r.u32());
+            let count = try!(r.u32());
+            ::Draw(prim, start, count, try!(r.option(|r| r.u32())))
+        },
+        TAG_DRAW_INDEXED => {
+            let prim = try!(r.primitive());
+            let itype = try!(r.int_size());
+            let start = try!(r.u32());
+            let count = try!(r.u32());
+            let base = try!(r.u32()) as i32;
+            ::DrawIndexed(prim, itype, start, count, base, try!(r.option(|r| r.u32())))
+        },
+        TAG_DRAW_INDEXED_INDIRECT_COUNT => {
+            let prim = try!(r.primitive());
+            let itype = try!(r.int_size());
+            let buf = try!(r.u32());
+            let offset = try!(r.uint());
+            let count_buf = try!(r.u32());
+            let count_offset = try!(r.uint());
+            let max_count = try!(r.u32());
+            ::DrawIndexedIndirectCount(prim, itype, buf, offset, count_buf, count_offset,
+                                       max_count, try!(r.u32()))
+        },
+        t => return Err(ErrorInvalidTag(t)),
+    })
+}
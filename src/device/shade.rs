@@ -18,6 +18,9 @@
 
 use std::cell::Cell;
 use std::fmt;
+use std::mem;
+use std::collections::{HashMap, HashSet};
+use std::str;
 
 // Describing shader parameters
 // TOOD: Remove GL-isms, especially in the documentation.
@@ -111,7 +114,13 @@ pub enum UniformValue {
     ValueF32(f32),
     ValueI32Vec([i32, ..4]),
     ValueF32Vec([f32, ..4]),
+    /// A vector of 2 floats, for `vec2` uniforms
+    ValueF32Vec2([f32, ..2]),
+    /// A vector of 3 floats, for `vec3` uniforms
+    ValueF32Vec3([f32, ..3]),
     ValueF32Matrix([[f32, ..4], ..4]),
+    /// A matrix stored row by row, transposed by the device when uploaded
+    ValueF32MatrixTransposed([[f32, ..4], ..4]),
 }
 
 impl UniformValue {
@@ -122,7 +131,10 @@ impl UniformValue {
             (ValueF32(_), ValueF32(_)) => true,
             (ValueI32Vec(_), ValueI32Vec(_)) => true,
             (ValueF32Vec(_), ValueF32Vec(_)) => true,
+            (ValueF32Vec2(_), ValueF32Vec2(_)) => true,
+            (ValueF32Vec3(_), ValueF32Vec3(_)) => true,
             (ValueF32Matrix(_), ValueF32Matrix(_)) => true,
+            (ValueF32MatrixTransposed(_), ValueF32MatrixTransposed(_)) => true,
             _ => false,
         }
     }
@@ -135,12 +147,20 @@ impl Clone for UniformValue {
             ValueF32(val)       => ValueF32(val),
             ValueI32Vec(v)      => ValueI32Vec([v[0], v[1], v[2], v[3]]),
             ValueF32Vec(v)      => ValueF32Vec([v[0], v[1], v[2], v[3]]),
+            ValueF32Vec2(v)     => ValueF32Vec2([v[0], v[1]]),
+            ValueF32Vec3(v)     => ValueF32Vec3([v[0], v[1], v[2]]),
             ValueF32Matrix(v)   => ValueF32Matrix([
                 [v[0][0], v[0][1], v[0][2], v[0][3]],
                 [v[1][0], v[1][1], v[1][2], v[1][3]],
                 [v[2][0], v[2][1], v[2][2], v[2][3]],
                 [v[3][0], v[3][1], v[3][2], v[3][3]],
             ]),
+            ValueF32MatrixTransposed(v) => ValueF32MatrixTransposed([
+                [v[0][0], v[0][1], v[0][2], v[0][3]],
+                [v[1][0], v[1][1], v[1][2], v[1][3]],
+                [v[2][0], v[2][1], v[2][2], v[2][3]],
+                [v[3][0], v[3][1], v[3][2], v[3][3]],
+            ]),
         }
     }
 }
@@ -152,6 +172,8 @@ impl fmt::Show for UniformValue {
             ValueF32(x)           => write!(f, "ValueF32({})", x),
             ValueI32Vec(ref v)    => write!(f, "ValueI32Vec({})", v.as_slice()),
             ValueF32Vec(ref v)    => write!(f, "ValueF32Vec({})", v.as_slice()),
+            ValueF32Vec2(ref v)   => write!(f, "ValueF32Vec2({})", v.as_slice()),
+            ValueF32Vec3(ref v)   => write!(f, "ValueF32Vec3({})", v.as_slice()),
             ValueF32Matrix(ref m) => {
                 try!(write!(f, "ValueF32Matrix("));
                 for v in m.iter() {
@@ -159,12 +181,19 @@ impl fmt::Show for UniformValue {
                 }
                 write!(f, ")")
             },
+            ValueF32MatrixTransposed(ref m) => {
+                try!(write!(f, "ValueF32MatrixTransposed("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
         }
     }
 }
 
 /// Vertex information that a shader takes as input.
-#[deriving(Clone, Show)]
+#[deriving(Clone, PartialEq, Show)]
 pub struct Attribute {
     /// Name of this attribute.
     pub name: String,
@@ -179,7 +208,7 @@ pub struct Attribute {
 }
 
 /// Uniform, a type of shader parameter representing data passed to the program.
-#[deriving(Clone, Show)]
+#[deriving(Clone, PartialEq, Show)]
 pub struct UniformVar {
     /// Name of this uniform.
     pub name: String,
@@ -194,7 +223,7 @@ pub struct UniformVar {
 }
 
 /// A uniform block.
-#[deriving(Clone, Show)]
+#[deriving(Clone, PartialEq, Show)]
 pub struct BlockVar {
     /// Name of this uniform block.
     pub name: String,
@@ -202,10 +231,149 @@ pub struct BlockVar {
     pub size: uint,
     /// What program stage this uniform block can be used in, as a bitflag.
     pub usage: u8,
+    /// Layout of the active members of the block.
+    pub members: Vec<BlockMember>,
+}
+
+/// A member of a uniform block, placed by the driver.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BlockMember {
+    /// Name of this member, qualified with the block name if it has an instance name.
+    pub name: String,
+    /// Number of elements this member represents.
+    pub count: uint,
+    /// Type that this member is composed of.
+    pub base_type: BaseType,
+    /// "Scalarness" of this member, with the layout of the matrices.
+    pub container: ContainerType,
+    /// Offset (in bytes) of this member from the start of the block.
+    pub offset: uint,
+    /// Distance (in bytes) between the array elements, 0 if not an array.
+    pub array_stride: uint,
+    /// Distance (in bytes) between the matrix columns, 0 if not a matrix.
+    pub matrix_stride: uint,
+}
+
+/// Error writing a member of a uniform block.
+#[deriving(Clone, PartialEq, Show)]
+pub enum BlockMemberError {
+    /// The block has no active member with this name
+    ErrorMemberMissing,
+    /// The value doesn't fit in the block data
+    ErrorMemberOutOfRange,
+    /// The value doesn't match the type of the member
+    ErrorMemberType,
+}
+
+fn write_word(data: &mut [u8], offset: uint, word: [u8, ..4]) {
+    for (i, &byte) in word.iter().enumerate() {
+        data[offset + i] = byte;
+    }
+}
+
+/// Get the offset of a matrix component from the start of the matrix.
+fn matrix_offset(format: MatrixFormat, stride: uint, column: uint, row: uint) -> uint {
+    match format {
+        ColumnMajor => column * stride + row * 4,
+        RowMajor => row * stride + column * 4,
+    }
+}
+
+impl BlockVar {
+    /// Find a member of the block by name.
+    pub fn find_member<'a>(&'a self, name: &str) -> Option<&'a BlockMember> {
+        self.members.iter().find(|m| m.name.as_slice() == name)
+    }
+
+    /// Encode a value for the named member, returning the offset of the
+    /// member in the block along with the bytes to write there. Only the
+    /// components of the member are encoded, so that the members packed
+    /// right after it are left alone: a `vec3` member takes a `ValueF32Vec3`,
+    /// a `mat3` the upper-left part of a matrix.
+    /// Matrices are written column by column, or row by row for the
+    /// `row_major` ones, the padding in between being zeroed. Fails with
+    /// `ErrorMemberType` if the value doesn't match the member, or if the
+    /// member is an array.
+    pub fn encode_member(&self, name: &str, value: UniformValue)
+                         -> Result<(uint, Vec<u8>), BlockMemberError> {
+        let member = match self.find_member(name) {
+            Some(m) => m,
+            None => return Err(ErrorMemberMissing),
+        };
+        if member.count != 1 {
+            return Err(ErrorMemberType)
+        }
+        let stride = member.matrix_stride;
+        let mut words: Vec<(uint, [u8, ..4])> = Vec::new();
+        match (member.base_type, member.container, value) {
+            (BaseI32, Single, ValueI32(v)) => words.push((0, unsafe { mem::transmute(v) })),
+            (BaseF32, Single, ValueF32(v)) => words.push((0, unsafe { mem::transmute(v) })),
+            (BaseI32, Vector(4), ValueI32Vec(v)) => {
+                for (i, &x) in v.iter().enumerate() {
+                    words.push((i * 4, unsafe { mem::transmute(x) }));
+                }
+            },
+            (BaseF32, Vector(4), ValueF32Vec(v)) => {
+                for (i, &x) in v.iter().enumerate() {
+                    words.push((i * 4, unsafe { mem::transmute(x) }));
+                }
+            },
+            (BaseF32, Vector(2), ValueF32Vec2(v)) => {
+                for (i, &x) in v.iter().enumerate() {
+                    words.push((i * 4, unsafe { mem::transmute(x) }));
+                }
+            },
+            (BaseF32, Vector(3), ValueF32Vec3(v)) => {
+                for (i, &x) in v.iter().enumerate() {
+                    words.push((i * 4, unsafe { mem::transmute(x) }));
+                }
+            },
+            (BaseF32, Matrix(format, c, r), ValueF32Matrix(m)) if c <= 4 && r <= 4 => {
+                for col in range(0, c as uint) {
+                    for row in range(0, r as uint) {
+                        let offset = matrix_offset(format, stride, col, row);
+                        words.push((offset, unsafe { mem::transmute(m[col][row]) }));
+                    }
+                }
+            },
+            (BaseF32, Matrix(format, c, r), ValueF32MatrixTransposed(m)) if c <= 4 && r <= 4 => {
+                for col in range(0, c as uint) {
+                    for row in range(0, r as uint) {
+                        let offset = matrix_offset(format, stride, col, row);
+                        words.push((offset, unsafe { mem::transmute(m[row][col]) }));
+                    }
+                }
+            },
+            _ => return Err(ErrorMemberType),
+        }
+        let size = words.iter().map(|&(offset, _)| offset + 4).max().unwrap_or(0);
+        if member.offset + size > self.size {
+            return Err(ErrorMemberOutOfRange)
+        }
+        let mut data = Vec::from_elem(size, 0u8);
+        for &(offset, word) in words.iter() {
+            write_word(data.as_mut_slice(), offset, word);
+        }
+        Ok((member.offset, data))
+    }
+
+    /// Write a value into the data of this block, at the place of the named
+    /// member, see `encode_member`.
+    pub fn set_member(&self, data: &mut [u8], name: &str, value: UniformValue)
+                      -> Result<(), BlockMemberError> {
+        let (offset, bytes) = try!(self.encode_member(name, value));
+        if offset + bytes.len() > data.len() {
+            return Err(ErrorMemberOutOfRange)
+        }
+        for (i, &byte) in bytes.iter().enumerate() {
+            data[offset + i] = byte;
+        }
+        Ok(())
+    }
 }
 
 /// Sampler, a type of shader parameter representing a texture that can be sampled.
-#[deriving(Clone, Show)]
+#[deriving(Clone, PartialEq, Show)]
 pub struct SamplerVar {
     /// Name of this sampler variable.
     pub name: String,
@@ -217,8 +385,21 @@ pub struct SamplerVar {
     pub sampler_type: SamplerType,
 }
 
+/// Image, a type of shader parameter representing a texture level accessed with load/store.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ImageVar {
+    /// Name of this image variable.
+    pub name: String,
+    /// Location of this image in the program.
+    pub location: Location,
+    /// Base type for the image.
+    pub base_type: BaseType,
+    /// Shape of this image, described as the matching sampler type.
+    pub image_type: SamplerType,
+}
+
 /// Metadata about a program.
-#[deriving(Clone, Show)]
+#[deriving(Clone, PartialEq, Show)]
 pub struct ProgramInfo {
     /// Attributes in the program.
     pub attributes: Vec<Attribute>,
@@ -228,6 +409,52 @@ pub struct ProgramInfo {
     pub blocks: Vec<BlockVar>,
     /// Samplers in the program
     pub textures: Vec<SamplerVar>,
+    /// Images in the program, accessed with load/store
+    pub images: Vec<ImageVar>,
+}
+
+impl ProgramInfo {
+    /// Get the vertex attributes the program expects, as reported when linking.
+    pub fn get_attributes<'a>(&'a self) -> &'a [Attribute] {
+        self.attributes.as_slice()
+    }
+
+    /// Find a vertex attribute of the program by name.
+    pub fn find_attribute<'a>(&'a self, name: &str) -> Option<&'a Attribute> {
+        self.attributes.iter().find(|a| a.name.as_slice() == name)
+    }
+
+    /// Whether the program uses a uniform of this name, outside of the blocks.
+    /// The uniforms unused by the shaders are removed by the driver.
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniforms.iter().any(|u| u.name.as_slice() == name)
+    }
+
+    /// Whether the program uses a uniform block of this name.
+    pub fn has_block(&self, name: &str) -> bool {
+        self.blocks.iter().any(|b| b.name.as_slice() == name)
+    }
+
+    /// Whether the program uses a sampler of this name.
+    pub fn has_texture(&self, name: &str) -> bool {
+        self.textures.iter().any(|t| t.name.as_slice() == name)
+    }
+
+    /// Find a uniform name declared more than once, which happens when the
+    /// shader stages disagree on its type. Samplers and images share the
+    /// namespace of the uniforms.
+    pub fn find_duplicate_uniform<'a>(&'a self) -> Option<&'a str> {
+        let mut names = HashSet::new();
+        let all = self.uniforms.iter().map(|u| u.name.as_slice())
+            .chain(self.textures.iter().map(|t| t.name.as_slice()))
+            .chain(self.images.iter().map(|i| i.name.as_slice()));
+        for name in all {
+            if !names.insert(name) {
+                return Some(name)
+            }
+        }
+        None
+    }
 }
 
 /// Error type for trying to store a UniformValue in a UniformVar.
@@ -254,18 +481,70 @@ impl UniformVar {
             (BaseF32, Single, ValueF32(_)) => Ok(()),
             (BaseF32, Vector(4), ValueF32Vec(_)) => Ok(()),
             (BaseF32, Vector(_), ValueF32Vec(_)) => Err(ErrorContainer),
+            (BaseF32, Vector(2), ValueF32Vec2(_)) => Ok(()),
+            (BaseF32, Vector(_), ValueF32Vec2(_)) => Err(ErrorContainer),
+            (BaseF32, Vector(3), ValueF32Vec3(_)) => Ok(()),
+            (BaseF32, Vector(_), ValueF32Vec3(_)) => Err(ErrorContainer),
             (BaseI32, Vector(4), ValueI32Vec(_)) => Ok(()),
             (BaseI32, Vector(_), ValueI32Vec(_)) => Err(ErrorContainer),
             (BaseF32, Matrix(_, 4,4), ValueF32Matrix(_)) => Ok(()),
             (BaseF32, Matrix(_, _,_), ValueF32Matrix(_)) => Err(ErrorContainer),
+            (BaseF32, Matrix(_, 4,4), ValueF32MatrixTransposed(_)) => Ok(()),
+            (BaseF32, Matrix(_, _,_), ValueF32MatrixTransposed(_)) => Err(ErrorContainer),
             _ => Err(ErrorBaseType)
         }
     }
+
+    /// Get the name of the GLSL type of this variable, e.g. `float`, `vec3` or `mat4`.
+    pub fn get_glsl_type(&self) -> String {
+        let prefix = match self.base_type {
+            BaseF32  => "",
+            BaseF64  => "d",
+            BaseI32  => "i",
+            BaseU32  => "u",
+            BaseBool => "b",
+        };
+        match self.container {
+            Single => match self.base_type {
+                BaseF32  => "float",
+                BaseF64  => "double",
+                BaseI32  => "int",
+                BaseU32  => "uint",
+                BaseBool => "bool",
+            }.to_string(),
+            Vector(d) => format!("{}vec{}", prefix, d),
+            Matrix(_, c, r) if c == r => format!("{}mat{}", prefix, c),
+            Matrix(_, c, r) => format!("{}mat{}x{}", prefix, c, r),
+        }
+    }
+}
+
+impl SamplerVar {
+    /// Get the name of the GLSL type of this sampler, e.g. `sampler2D` or `isampler2DArray`.
+    pub fn get_glsl_type(&self) -> String {
+        let prefix = match self.base_type {
+            BaseI32 => "i",
+            BaseU32 => "u",
+            _ => "",
+        };
+        let array = |a: IsArray| if a == Array { "Array" } else { "" };
+        let shadow = |s: IsShadow| if s == Shadow { "Shadow" } else { "" };
+        let kind = match self.sampler_type {
+            SamplerBuffer => "Buffer".to_string(),
+            Sampler1D(a, s) => format!("1D{}{}", array(a), shadow(s)),
+            Sampler2D(a, _, MultiSample, _) => format!("2DMS{}", array(a)),
+            Sampler2D(_, s, _, Rect) => format!("2DRect{}", shadow(s)),
+            Sampler2D(a, s, _, _) => format!("2D{}{}", array(a), shadow(s)),
+            Sampler3D => "3D".to_string(),
+            SamplerCube(s) => format!("Cube{}", shadow(s)),
+        };
+        format!("{}sampler{}", prefix, kind)
+    }
 }
 
 /// Like `MaybeOwned` but for u8.
 #[allow(missing_doc)]
-#[deriving(Show, PartialEq, Clone)]
+#[deriving(Show, PartialEq, Eq, Hash, Clone)]
 pub enum Bytes {
     StaticBytes(&'static [u8]),
     OwnedBytes(Vec<u8>),
@@ -283,13 +562,123 @@ impl Bytes {
 
 /// A type storing shader source for different graphics APIs and versions.
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct ShaderSource {
     pub glsl_120: Option<Bytes>,
     pub glsl_150: Option<Bytes>,
     // TODO: hlsl_sm_N...
 }
 
+/// An error produced by the shader source preprocessing.
+#[deriving(Clone, PartialEq, Show)]
+pub enum PreprocessError {
+    /// The source is not valid UTF-8.
+    ErrorEncoding,
+    /// The named include is not in the registry.
+    ErrorIncludeMissing(String),
+    /// The named include ends up including itself.
+    ErrorIncludeCycle(String),
+}
+
+/// Find the line of the `#version` directive, which can only be preceded by
+/// blank lines and comments.
+fn find_version_line(lines: &[&str]) -> Option<uint> {
+    let mut in_comment = false;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if in_comment || line.starts_with("/*") {
+            in_comment = !line.contains("*/");
+        } else if line.starts_with("#version") {
+            return Some(i)
+        } else if !line.is_empty() && !line.starts_with("//") {
+            return None
+        }
+    }
+    None
+}
+
+fn resolve_includes(source: &str, registry: &HashMap<String, String>,
+                    stack: &mut Vec<String>, output: &mut String)
+                    -> Result<(), PreprocessError> {
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if !trimmed.starts_with("#include") {
+            output.push_str(line);
+            output.push_char('\n');
+            continue
+        }
+        let name = trimmed.slice_from("#include".len()).trim().trim_chars('"');
+        if stack.iter().any(|n| n.as_slice() == name) {
+            return Err(ErrorIncludeCycle(name.to_string()))
+        }
+        match registry.find_equiv(&name) {
+            Some(text) => {
+                stack.push(name.to_string());
+                try!(resolve_includes(text.as_slice(), registry, stack, output));
+                stack.pop();
+            },
+            None => return Err(ErrorIncludeMissing(name.to_string())),
+        }
+    }
+    Ok(())
+}
+
+fn map_bytes(bytes: &Option<Bytes>, fun: &mut |&str| -> Result<String, PreprocessError>)
+             -> Result<Option<Bytes>, PreprocessError> {
+    match *bytes {
+        Some(ref b) => match str::from_utf8(b.as_slice()) {
+            Some(text) => (*fun)(text).map(|s| Some(OwnedBytes(s.into_bytes()))),
+            None => Err(ErrorEncoding),
+        },
+        None => Ok(None),
+    }
+}
+
+impl ShaderSource {
+    /// Apply a transformation to the text of every provided source.
+    fn map_text(&self, mut fun: |&str| -> Result<String, PreprocessError>)
+                -> Result<ShaderSource, PreprocessError> {
+        Ok(ShaderSource {
+            glsl_120: try!(map_bytes(&self.glsl_120, &mut fun)),
+            glsl_150: try!(map_bytes(&self.glsl_150, &mut fun)),
+        })
+    }
+
+    /// Replace every `#include "name"` line with the source registered under
+    /// that name, recursively.
+    pub fn with_includes(&self, registry: &HashMap<String, String>)
+                         -> Result<ShaderSource, PreprocessError> {
+        self.map_text(|text| {
+            let mut output = String::new();
+            let mut stack = Vec::new();
+            resolve_includes(text, registry, &mut stack, &mut output).map(|_| output)
+        })
+    }
+
+    /// Insert a `#define name value` line for each pair, right after the
+    /// `#version` directive if there is one, or at the start otherwise.
+    pub fn with_defines(&self, defines: &[(&str, &str)])
+                        -> Result<ShaderSource, PreprocessError> {
+        self.map_text(|text| {
+            let lines: Vec<&str> = text.lines().collect();
+            let split = find_version_line(lines.as_slice()).map_or(0, |i| i + 1);
+            let mut output = String::new();
+            for line in lines.slice_to(split).iter() {
+                output.push_str(*line);
+                output.push_char('\n');
+            }
+            for &(name, value) in defines.iter() {
+                output.push_str(format!("#define {} {}\n", name, value).as_slice());
+            }
+            for line in lines.slice_from(split).iter() {
+                output.push_str(*line);
+                output.push_char('\n');
+            }
+            Ok(output)
+        })
+    }
+}
+
 /// An error type for creating programs.
 #[deriving(Clone, PartialEq, Show)]
 pub enum CreateShaderError {
@@ -324,3 +713,250 @@ impl ShaderModel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{ProgramInfo, Attribute, UniformVar, SamplerVar, ShaderSource, StaticBytes};
+    use super::{ErrorIncludeCycle, ErrorIncludeMissing};
+    use super::{BaseF32, BaseI32, Single, Vector, Matrix, ColumnMajor};
+    use super::{Sampler2D, Array, NoArray, Shadow, NoShadow, NoMultiSample, NoRect};
+
+    #[test]
+    fn test_glsl_type() {
+        let var = |base, container| UniformVar {
+            name: "u".to_string(),
+            location: 0,
+            count: 1,
+            base_type: base,
+            container: container,
+        };
+        assert_eq!(var(BaseF32, Single).get_glsl_type(), "float".to_string());
+        assert_eq!(var(BaseF32, Vector(3)).get_glsl_type(), "vec3".to_string());
+        assert_eq!(var(BaseI32, Vector(2)).get_glsl_type(), "ivec2".to_string());
+        assert_eq!(var(BaseF32, Matrix(ColumnMajor, 4, 4)).get_glsl_type(), "mat4".to_string());
+        assert_eq!(var(BaseF32, Matrix(ColumnMajor, 2, 3)).get_glsl_type(), "mat2x3".to_string());
+        let sampler = |base, ty| SamplerVar {
+            name: "t".to_string(),
+            location: 0,
+            base_type: base,
+            sampler_type: ty,
+        };
+        assert_eq!(sampler(BaseF32, Sampler2D(NoArray, NoShadow, NoMultiSample, NoRect))
+                   .get_glsl_type(), "sampler2D".to_string());
+        assert_eq!(sampler(BaseI32, Sampler2D(Array, Shadow, NoMultiSample, NoRect))
+                   .get_glsl_type(), "isampler2DArrayShadow".to_string());
+    }
+
+    #[test]
+    fn test_duplicate_uniform() {
+        let var = |name: &str, container| UniformVar {
+            name: name.to_string(),
+            location: 0,
+            count: 1,
+            base_type: BaseF32,
+            container: container,
+        };
+        let mut info = ProgramInfo {
+            attributes: Vec::new(),
+            uniforms: vec![var("color", Vector(4)), var("scale", Single)],
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+        };
+        assert_eq!(info.find_duplicate_uniform(), None);
+        info.uniforms.push(var("color", Vector(3)));
+        assert_eq!(info.find_duplicate_uniform(), Some("color"));
+    }
+
+    #[test]
+    fn test_has_parameter() {
+        let info = ProgramInfo {
+            attributes: Vec::new(),
+            uniforms: vec![UniformVar {
+                name: "u_Color".to_string(),
+                location: 0,
+                count: 1,
+                base_type: BaseF32,
+                container: Vector(4),
+            }],
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+        };
+        assert!(info.has_uniform("u_Color"));
+        assert!(!info.has_uniform("u_Shadows"));
+        assert!(!info.has_block("u_Color"));
+        assert!(!info.has_texture("u_Color"));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let attrib = |name: &str, location, count| Attribute {
+            name: name.to_string(),
+            location: location,
+            count: 1,
+            base_type: BaseF32,
+            container: Vector(count),
+        };
+        let info = ProgramInfo {
+            attributes: vec![attrib("pos", 0, 2), attrib("color", 1, 3)],
+            uniforms: Vec::new(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+        };
+        let names: Vec<&str> = info.get_attributes().iter()
+            .map(|a| a.name.as_slice()).collect();
+        assert_eq!(names, vec!["pos", "color"]);
+        assert_eq!(info.find_attribute("color").map(|a| a.location), Some(1));
+        assert_eq!(info.find_attribute("normal"), None);
+    }
+
+    #[test]
+    fn test_includes() {
+        let mut registry = HashMap::new();
+        registry.insert("light".to_string(), "float light() { return 1.0; }".to_string());
+        registry.insert("loop".to_string(), "#include \"loop\"".to_string());
+        let source = |text: &'static str| ShaderSource {
+            glsl_120: None,
+            glsl_150: Some(StaticBytes(text.as_bytes())),
+        };
+        let result = source("#version 150\n#include \"light\"\nvoid main() {}")
+            .with_includes(&registry).unwrap();
+        assert_eq!(result.glsl_150.unwrap().as_slice(),
+                   "#version 150\nfloat light() { return 1.0; }\nvoid main() {}\n".as_bytes());
+        assert!(result.glsl_120.is_none());
+        assert_eq!(source("#include \"loop\"").with_includes(&registry),
+                   Err(ErrorIncludeCycle("loop".to_string())));
+        assert_eq!(source("#include \"none\"").with_includes(&registry),
+                   Err(ErrorIncludeMissing("none".to_string())));
+    }
+
+    #[test]
+    fn test_defines() {
+        let source = ShaderSource {
+            glsl_120: Some(StaticBytes("#version 120\nvoid main() {}".as_bytes())),
+            glsl_150: None,
+        };
+        let plain = source.with_defines([]).unwrap();
+        let shadows = source.with_defines([("SHADOWS", "1")]).unwrap();
+        assert_eq!(plain.glsl_120.unwrap().as_slice(),
+                   "#version 120\nvoid main() {}\n".as_bytes());
+        assert_eq!(shadows.glsl_120.unwrap().as_slice(),
+                   "#version 120\n#define SHADOWS 1\nvoid main() {}\n".as_bytes());
+        // the directive can follow comments and blank lines
+        let commented = ShaderSource {
+            glsl_120: Some(StaticBytes("// shadows\n/* a\n b */\n\n#version 120\nvoid main() {}"
+                                       .as_bytes())),
+            glsl_150: None,
+        };
+        assert_eq!(commented.with_defines([("SHADOWS", "1")]).unwrap().glsl_120.unwrap().as_slice(),
+                   "// shadows\n/* a\n b */\n\n#version 120\n#define SHADOWS 1\nvoid main() {}\n"
+                   .as_bytes());
+        // other code can't precede it, so there is none to skip
+        let unversioned = ShaderSource {
+            glsl_120: Some(StaticBytes("void main() {}\n#version 120".as_bytes())),
+            glsl_150: None,
+        };
+        assert_eq!(unversioned.with_defines([("SHADOWS", "1")]).unwrap().glsl_120.unwrap()
+                   .as_slice(), "#define SHADOWS 1\nvoid main() {}\n#version 120\n".as_bytes());
+    }
+
+    #[test]
+    fn test_block_members() {
+        use super::{BlockVar, BlockMember, ValueF32, ValueF32Vec, ValueF32Vec2, ValueF32Vec3,
+                    ErrorMemberMissing, ErrorMemberType, ContainerType, BaseF32, Single, Vector};
+        let member = |name: &str, container: ContainerType, offset| BlockMember {
+            name: name.to_string(),
+            count: 1,
+            base_type: BaseF32,
+            container: container,
+            offset: offset,
+            array_stride: 0,
+            matrix_stride: 0,
+        };
+        let block = BlockVar {
+            name: "Light".to_string(),
+            size: 32,
+            usage: 1,
+            members: vec![member("intensity", Single, 0), member("color", Vector(4), 16)],
+        };
+        assert_eq!(block.find_member("color").map(|m| m.offset), Some(16));
+        let mut data = [0u8, ..32];
+        block.set_member(data.as_mut_slice(), "intensity", ValueF32(1.0)).unwrap();
+        block.set_member(data.as_mut_slice(), "color", ValueF32Vec([0.0, 0.0, 0.0, 1.0])).unwrap();
+        // 1.0 as a little-endian float
+        assert_eq!(data.slice(0, 4), [0u8, 0, 0x80, 0x3f].as_slice());
+        assert_eq!(data.slice(28, 32), [0u8, 0, 0x80, 0x3f].as_slice());
+        assert_eq!(data.slice(4, 28), [0u8, ..24].as_slice());
+        assert_eq!(block.set_member(data.as_mut_slice(), "radius", ValueF32(2.0)),
+                   Err(ErrorMemberMissing));
+        assert_eq!(block.set_member(data.as_mut_slice(), "color", ValueF32(2.0)),
+                   Err(ErrorMemberType));
+
+        // std140 packs a float right after a vec3
+        let packed = BlockVar {
+            name: "Light".to_string(),
+            size: 16,
+            usage: 1,
+            members: vec![member("color", Vector(3), 0), member("intensity", Single, 12)],
+        };
+        let mut data = [0u8, ..16];
+        packed.set_member(data.as_mut_slice(), "intensity", ValueF32(1.0)).unwrap();
+        packed.set_member(data.as_mut_slice(), "color", ValueF32Vec3([1.0, ..3])).unwrap();
+        assert_eq!(packed.encode_member("color", ValueF32Vec3([0.0, ..3])).unwrap().val1().len(),
+                   12);
+        // a vec4 value would spill over the next member
+        assert_eq!(packed.set_member(data.as_mut_slice(), "color", ValueF32Vec([0.0, ..4])),
+                   Err(ErrorMemberType));
+        assert_eq!(data.slice(8, 16), [0u8, 0, 0x80, 0x3f, 0, 0, 0x80, 0x3f].as_slice());
+        packed.set_member(data.as_mut_slice(), "color", ValueF32Vec3([0.0, ..3])).unwrap();
+        assert_eq!(data.slice(0, 16), [0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0x3f]
+                                          .as_slice());
+        assert_eq!(packed.set_member(data.as_mut_slice(), "color", ValueF32Vec2([0.0, ..2])),
+                   Err(ErrorMemberType));
+    }
+
+    #[test]
+    fn test_matrix_member() {
+        use super::{BlockVar, BlockMember, ValueF32Matrix, ValueF32MatrixTransposed};
+        use super::{BaseF32, Matrix, ColumnMajor, RowMajor, MatrixFormat};
+        let block = |format: MatrixFormat, columns, rows| BlockVar {
+            name: "Locals".to_string(),
+            size: 64,
+            usage: 1,
+            members: vec![BlockMember {
+                name: "transform".to_string(),
+                count: 1,
+                base_type: BaseF32,
+                container: Matrix(format, columns, rows),
+                offset: 0,
+                array_stride: 0,
+                matrix_stride: 16,
+            }],
+        };
+        // a translation, stored row by row
+        let rows = [[1.0, 0.0, 0.0, 5.0],
+                    [0.0, 1.0, 0.0, 6.0],
+                    [0.0, 0.0, 1.0, 7.0],
+                    [0.0, 0.0, 0.0, 1.0]];
+        let columns = [[1.0, 0.0, 0.0, 0.0],
+                       [0.0, 1.0, 0.0, 0.0],
+                       [0.0, 0.0, 1.0, 0.0],
+                       [5.0, 6.0, 7.0, 1.0]];
+        let mat4 = block(ColumnMajor, 4, 4);
+        assert_eq!(mat4.encode_member("transform", ValueF32MatrixTransposed(rows)),
+                   mat4.encode_member("transform", ValueF32Matrix(columns)));
+        // a row-major block member is laid out like the transposed value
+        let (_, row_major) = block(RowMajor, 4, 4)
+            .encode_member("transform", ValueF32Matrix(columns)).unwrap();
+        let (_, column_major) = mat4.encode_member("transform", ValueF32Matrix(rows)).unwrap();
+        assert_eq!(row_major, column_major);
+        // a mat3 only takes 3 components of its 3 columns
+        let (_, data) = block(ColumnMajor, 3, 3)
+            .encode_member("transform", ValueF32Matrix(columns)).unwrap();
+        assert_eq!(data.len(), 2 * 16 + 3 * 4);
+        assert_eq!(data.slice(12, 16), [0u8, ..4].as_slice());
+    }
+}
@@ -0,0 +1,856 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Software back-end, executing the command stream on the CPU.
+//!
+//! It is meant for testing the rendering logic without a GPU, and only runs
+//! a small subset of the commands: color clears, and non-indexed triangle
+//! lists. The programs are not executed. Instead, the position of each vertex
+//! is read from the attribute bound to slot 0, as 2 floats in normalized
+//! device coordinates, and its color from the attribute bound to slot 1, as
+//! 3 or 4 floats, which is interpolated across the triangle. Timestamps count
+//! the commands processed so far, there being no clock to read. The other
+//! commands are ignored.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::default::Default;
+use std::mem;
+use std::slice;
+use attrib;
+use target::Color;
+use {back, shade, tex};
+use {ApiBackEnd, Blob, Capabilities, FrameStats, Handle, PendingProgram, ResetReason};
+use {BufferHandle, ShaderHandle, ProgramHandle, SurfaceHandle, TextureHandle,
+     SamplerHandle, QueryHandle, BufferInfo, QueryInfo};
+use {BufferError, SurfaceError, TextureError, BufferUsage, StorageFlags};
+
+/// A vertex attribute bound to a slot, made of 32-bit floats.
+struct Stream {
+    buffer: back::Buffer,
+    count: attrib::Count,
+    offset: attrib::Offset,
+    stride: attrib::Stride,
+}
+
+/// A back-end rasterizing into a color buffer in memory.
+pub struct SoftBackEnd {
+    caps: Capabilities,
+    width: u16,
+    height: u16,
+    pixels: Vec<Color>,
+    buffers: HashMap<back::Buffer, Vec<u8>>,
+    streams: HashMap<::AttributeSlot, Stream>,
+    next_name: u32,
+    stats: FrameStats,
+    ticks: u64,
+    timestamps: HashMap<back::Query, u64>,
+    max_batch_size: Option<uint>,
+    flush_count: uint,
+    finish_count: uint,
+    labels: HashMap<u32, String>,
+    programs: HashMap<back::Program, shade::ProgramInfo>,
+    validation: bool,
+}
+
+fn copy_blob(data: &Blob) -> Vec<u8> {
+    unsafe {
+        slice::raw::buf_as_slice(data.get_address() as *const u8, data.get_size(),
+                                 |bytes| bytes.to_vec())
+    }
+}
+
+/// Compute twice the signed area of the triangle `(a, b, c)`.
+fn edge((ax, ay): (f32, f32), (bx, by): (f32, f32), (cx, cy): (f32, f32)) -> f32 {
+    (bx - ax) * (cy - ay) - (by - ay) * (cx - ax)
+}
+
+impl SoftBackEnd {
+    /// Create a back-end with a color buffer of the given size, cleared to
+    /// black.
+    pub fn new(width: u16, height: u16) -> SoftBackEnd {
+        SoftBackEnd {
+            caps: Default::default(),
+            width: width,
+            height: height,
+            pixels: Vec::from_elem(width as uint * height as uint, Color::new()),
+            buffers: HashMap::new(),
+            streams: HashMap::new(),
+            next_name: 1,
+            stats: FrameStats::new(),
+            ticks: 0,
+            timestamps: HashMap::new(),
+            max_batch_size: None,
+            flush_count: 0,
+            finish_count: 0,
+            labels: HashMap::new(),
+            programs: HashMap::new(),
+            validation: false,
+        }
+    }
+
+    /// Enable or disable the validation of the draw lists on `submit`, as
+    /// with the GL back-end. The lists failing it are skipped. Disabled by
+    /// default.
+    pub fn set_validation(&mut self, enable: bool) {
+        self.validation = enable;
+    }
+
+    /// Get the color buffer, row by row starting from the bottom one, as
+    /// with `glReadPixels`.
+    pub fn read_pixels<'a>(&'a self) -> &'a [Color] {
+        self.pixels.as_slice()
+    }
+
+    /// Get the color of a single pixel, `y` going up from the bottom row.
+    pub fn get_pixel(&self, x: u16, y: u16) -> Color {
+        self.pixels.as_slice()[y as uint * self.width as uint + x as uint]
+    }
+
+    /// Number of flushes so far, either requested or splitting the lists into
+    /// batches.
+    pub fn get_flush_count(&self) -> uint {
+        self.flush_count
+    }
+
+    /// Number of times the back-end was asked to wait for the commands to complete.
+    pub fn get_finish_count(&self) -> uint {
+        self.finish_count
+    }
+
+    /// Get the label attached to the buffer, texture or program of the given name.
+    pub fn get_label<'a>(&'a self, name: u32) -> Option<&'a str> {
+        self.labels.find(&name).map(|label| label.as_slice())
+    }
+
+    /// Get the data last uploaded into a buffer.
+    pub fn read_buffer<'a>(&'a self, buffer: &BufferHandle) -> Option<&'a [u8]> {
+        self.buffers.find(&buffer.get_name()).map(|data| data.as_slice())
+    }
+
+    fn gen_name(&mut self) -> u32 {
+        self.next_name += 1;
+        self.next_name - 1
+    }
+
+    /// Read the floats of a vertex attribute, or `None` if nothing usable
+    /// is bound to the slot.
+    fn read_attribute(&self, slot: ::AttributeSlot, vertex: uint) -> Option<[f32, ..4]> {
+        let stream = match self.streams.find(&slot) {
+            Some(s) => s,
+            None => return None,
+        };
+        let data = match self.buffers.find(&stream.buffer) {
+            Some(d) => d,
+            None => return None,
+        };
+        let size = stream.count as uint * 4;
+        let stride = if stream.stride == 0 { size } else { stream.stride as uint };
+        let start = stream.offset as uint + vertex * stride;
+        if start + size > data.len() {
+            return None
+        }
+        let mut values = [0.0f32, 0.0, 0.0, 1.0];
+        for i in range(0, ::std::cmp::min(stream.count as uint, 4)) {
+            // the attribute may not be aligned within the buffer
+            let mut bytes = [0u8, ..4];
+            let offset = start + i * 4;
+            slice::bytes::copy_memory(bytes.as_mut_slice(), data.slice(offset, offset + 4));
+            values[i] = unsafe { mem::transmute(bytes) };
+        }
+        Some(values)
+    }
+
+    fn draw_triangles(&mut self, start: ::VertexCount, count: ::VertexCount) {
+        let (w, h) = (self.width as f32, self.height as f32);
+        for tri in range(0, count as uint / 3) {
+            let mut pos = [(0.0f32, 0.0f32), ..3];
+            let mut col = [[1.0f32, 1.0, 1.0, 1.0], ..3];
+            for k in range(0u, 3) {
+                let vertex = start as uint + tri * 3 + k;
+                match self.read_attribute(0, vertex) {
+                    Some(p) => pos[k] = ((p[0] + 1.0) * 0.5 * w, (p[1] + 1.0) * 0.5 * h),
+                    None => {
+                        error!("Ignored a triangle without a position");
+                        return
+                    },
+                }
+                self.read_attribute(1, vertex).map(|c| col[k] = c);
+            }
+            let area = edge(pos[0], pos[1], pos[2]);
+            if area == 0.0 {
+                continue
+            }
+            for y in range(0, self.height) {
+                for x in range(0, self.width) {
+                    let p = (x as f32 + 0.5, y as f32 + 0.5);
+                    // barycentric weights, positive inside for either winding
+                    let b0 = edge(pos[1], pos[2], p) / area;
+                    let b1 = edge(pos[2], pos[0], p) / area;
+                    let b2 = edge(pos[0], pos[1], p) / area;
+                    if b0 < 0.0 || b1 < 0.0 || b2 < 0.0 {
+                        continue
+                    }
+                    let mut color = [0.0f32, ..4];
+                    for i in range(0u, 4) {
+                        color[i] = b0 * col[0][i] + b1 * col[1][i] + b2 * col[2][i];
+                    }
+                    *self.pixels.get_mut(y as uint * self.width as uint + x as uint) =
+                        Color(color);
+                }
+            }
+        }
+    }
+
+    fn process(&mut self, cmd: &::Command) {
+        self.ticks += 1;
+        match *cmd {
+            ::Clear(ref data) => {
+                data.color.map(|color| {
+                    for pixel in self.pixels.mut_iter() {
+                        *pixel = color;
+                    }
+                });
+            },
+            ::BindAttribute(slot, buffer, count, atype, stride, offset, _) => {
+                match atype {
+                    attrib::Float(attrib::FloatDefault, attrib::F32) => {
+                        self.streams.insert(slot, Stream {
+                            buffer: buffer,
+                            count: count,
+                            offset: offset,
+                            stride: stride,
+                        });
+                    },
+                    _ => error!("Ignored attribute {}, only 32-bit floats are supported", slot),
+                }
+            },
+            ::QueryTimestamp(query) => {
+                self.timestamps.insert(query, self.ticks);
+            },
+            ::UpdateBuffer(buffer, ref data) => {
+                self.buffers.insert(buffer, copy_blob(*data));
+            },
+            ::Draw(::TriangleList, start, count, _) => {
+                self.draw_triangles(start, count);
+            },
+            ::Draw(..) | ::DrawIndexed(..) | ::DrawIndexedIndirectCount(..) => {
+                error!("Ignored a draw call, only non-indexed triangle lists are supported");
+            },
+            _ => (),
+        }
+    }
+}
+
+impl ApiBackEnd<::DrawList> for SoftBackEnd {
+    fn get_capabilities<'a>(&'a self) -> &'a Capabilities {
+        &self.caps
+    }
+
+    fn create_buffer(&mut self) -> BufferHandle {
+        let name = self.gen_name();
+        self.buffers.insert(name, Vec::new());
+        Handle(name, BufferInfo)
+    }
+
+    fn create_buffer_storage(&mut self, _: uint, _: StorageFlags)
+                             -> Result<BufferHandle, BufferError> {
+        Err(::UnsupportedBufferOperation)
+    }
+
+    fn create_array_buffer(&mut self) -> Result<back::ArrayBuffer, ()> {
+        Ok(0)
+    }
+
+    fn create_shader(&mut self, stage: shade::Stage, _: shade::ShaderSource)
+                     -> Result<ShaderHandle, shade::CreateShaderError> {
+        Ok(Handle(self.gen_name(), stage))
+    }
+
+    fn create_program(&mut self, _: &[ShaderHandle]) -> Result<ProgramHandle, ()> {
+        let name = self.gen_name();
+        let info = shade::ProgramInfo {
+            attributes: Vec::new(),
+            uniforms: Vec::new(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+        };
+        self.programs.insert(name, info.clone());
+        Ok(Handle(name, info))
+    }
+
+    fn relink_program(&mut self, _: &ProgramHandle, _: &[ShaderHandle]) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn link_program_async(&mut self, _: &[ShaderHandle]) -> PendingProgram {
+        PendingProgram(0)
+    }
+
+    fn is_program_ready(&mut self, _: &PendingProgram) -> bool {
+        true
+    }
+
+    fn finish_program(&mut self, _: PendingProgram) -> Result<ProgramHandle, ()> {
+        self.create_program(&[])
+    }
+
+    fn create_frame_buffer(&mut self) -> back::FrameBuffer {
+        0
+    }
+
+    fn create_surface(&mut self, info: tex::SurfaceInfo) -> Result<SurfaceHandle, SurfaceError> {
+        Ok(Handle(self.gen_name(), info))
+    }
+
+    fn create_texture(&mut self, info: tex::TextureInfo) -> Result<TextureHandle, TextureError> {
+        try!(info.check_kind());
+        Ok(Handle(self.gen_name(), info))
+    }
+
+    fn create_texture_immutable(&mut self, info: tex::TextureInfo)
+                                -> Result<TextureHandle, TextureError> {
+        self.create_texture(info)
+    }
+
+    fn create_texture_view(&mut self, _: &TextureHandle, _: tex::Format, _: (u8, u8),
+                           _: (u16, u16)) -> Result<TextureHandle, TextureError> {
+        Err(::UnsupportedTextureView)
+    }
+
+    fn create_buffer_texture(&mut self, _: &BufferHandle, format: tex::Format)
+                             -> Result<TextureHandle, TextureError> {
+        if !format.is_buffer_compatible() {
+            return Err(::UnsupportedTextureFormat)
+        }
+        Ok(Handle(self.gen_name(), tex::TextureInfo {
+            mipmap_range: (0, 0),
+            kind: tex::TextureBuffer,
+            format: format,
+            .. tex::TextureInfo::new()
+        }))
+    }
+
+    fn create_sampler(&mut self, info: tex::SamplerInfo) -> SamplerHandle {
+        Handle(0, info)
+    }
+
+    fn create_query(&mut self) -> QueryHandle {
+        Handle(self.gen_name(), QueryInfo)
+    }
+
+    fn import_buffer(&mut self, name: back::Buffer) -> BufferHandle {
+        Handle(name, BufferInfo)
+    }
+
+    fn import_texture(&mut self, name: back::Texture, info: tex::TextureInfo) -> TextureHandle {
+        Handle(name, info)
+    }
+
+    fn delete_buffer(&mut self, handle: BufferHandle) {
+        self.buffers.remove(&handle.get_name());
+    }
+
+    fn delete_shader(&mut self, _: ShaderHandle) {}
+    fn delete_program(&mut self, handle: ProgramHandle) {
+        self.programs.remove(&handle.get_name());
+    }
+    fn delete_surface(&mut self, _: SurfaceHandle) {}
+    fn delete_texture(&mut self, _: TextureHandle) {}
+    fn delete_sampler(&mut self, _: SamplerHandle) {}
+    fn delete_query(&mut self, query: QueryHandle) {
+        self.timestamps.remove(&query.get_name());
+    }
+    fn flush_deletions(&mut self) {}
+    fn set_buffer_label(&mut self, handle: &BufferHandle, label: &str) {
+        self.labels.insert(handle.get_name(), label.to_string());
+    }
+    fn set_texture_label(&mut self, handle: &TextureHandle, label: &str) {
+        self.labels.insert(handle.get_name(), label.to_string());
+    }
+    fn set_program_label(&mut self, handle: &ProgramHandle, label: &str) {
+        self.labels.insert(handle.get_name(), label.to_string());
+    }
+
+    fn update_buffer(&mut self, buffer: BufferHandle, data: &Blob, _: BufferUsage) {
+        self.buffers.insert(buffer.get_name(), copy_blob(data));
+    }
+
+    fn copy_buffer(&mut self, src: BufferHandle, src_offset: uint,
+                   dst: BufferHandle, dst_offset: uint, size: uint)
+                   -> Result<(), BufferError> {
+        let fits = |len: uint, offset: uint| offset.checked_add(&size).map_or(false, |end| end <= len);
+        let data = match self.buffers.find(&src.get_name()) {
+            Some(data) if fits(data.len(), src_offset) =>
+                data.slice(src_offset, src_offset + size).to_vec(),
+            _ => return Err(::BufferOutOfRange),
+        };
+        match self.buffers.find_mut(&dst.get_name()) {
+            Some(target) if fits(target.len(), dst_offset) => {
+                let range = target.mut_slice(dst_offset, dst_offset + size);
+                for (to, from) in range.mut_iter().zip(data.iter()) {
+                    *to = *from;
+                }
+                Ok(())
+            },
+            _ => Err(::BufferOutOfRange),
+        }
+    }
+
+    fn set_block_member(&mut self, buffer: &BufferHandle, block: &shade::BlockVar, name: &str,
+                        value: shade::UniformValue) -> Result<(), shade::BlockMemberError> {
+        match self.buffers.find_mut(&buffer.get_name()) {
+            Some(data) => block.set_member(data.as_mut_slice(), name, value),
+            None => Err(shade::ErrorMemberOutOfRange),
+        }
+    }
+
+    fn clear_buffer(&mut self, buffer: BufferHandle, value: u32) {
+        let data = Vec::from_elem(self.buffers.find(&buffer.get_name())
+                                      .map_or(0, |d| d.len() / 4), value);
+        self.update_buffer(buffer, &data, ::UsageStatic);
+    }
+
+    fn update_texture(&mut self, texture: &TextureHandle, img: &tex::ImageInfo, _: &Blob)
+                      -> Result<(), TextureError> {
+        texture.get_info().check_update(img)
+    }
+
+    fn update_texture_sub(&mut self, texture: &TextureHandle, img: &tex::ImageInfo, _: &Blob,
+                          _: Option<u16>) -> Result<(), TextureError> {
+        texture.get_info().check_update(img)
+    }
+
+    fn set_unpack_alignment(&mut self, _: u8) {}
+    fn set_pack_alignment(&mut self, _: u8) {}
+
+    fn copy_texture(&mut self, src: &TextureHandle, src_img: &tex::ImageInfo,
+                    dst: &TextureHandle, dst_level: u8, (x, y, z): (u16, u16, u16))
+                    -> Result<(), TextureError> {
+        let dst_img = tex::ImageInfo {
+            xoffset: x,
+            yoffset: y,
+            zoffset: z,
+            mipmap: dst_level,
+            .. *src_img
+        };
+        if src.get_info().contains(src_img) && dst.get_info().contains(&dst_img) {
+            Ok(())
+        } else {
+            Err(::TextureOutOfRange)
+        }
+    }
+
+    fn submit(&mut self, list: &::DrawList) {
+        if self.validation {
+            match ::validate::validate(list.iter(), &self.programs) {
+                Ok(()) => (),
+                Err(e) => {
+                    error!("Invalid draw list, skipped: {}", e);
+                    return
+                },
+            }
+        }
+        for (i, com) in list.iter().enumerate() {
+            match self.max_batch_size {
+                Some(size) if i > 0 && i % size == 0 => self.flush(),
+                _ => (),
+            }
+            self.stats.record(com);
+            self.process(com);
+        }
+    }
+
+    fn check_device_reset(&mut self) -> Option<ResetReason> {
+        None
+    }
+
+    fn get_timestamp(&mut self, query: &QueryHandle) -> Option<u64> {
+        self.timestamps.find_copy(&query.get_name())
+    }
+
+    fn get_frame_stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn reset_frame_stats(&mut self) {
+        self.stats = FrameStats::new();
+    }
+
+    fn set_max_batch_size(&mut self, size: Option<uint>) {
+        self.max_batch_size = size.map(|s| cmp::max(s, 1));
+    }
+
+    fn flush(&mut self) {
+        self.flush_count += 1;
+    }
+    fn finish(&mut self) {
+        self.finish_count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use super::SoftBackEnd;
+    use {ApiBackEnd, DrawList, TriangleList, UsageStatic};
+    use ListTrait = draw::DrawList;
+    use attrib::{Float, FloatDefault, F32};
+    use target::{ClearData, Color};
+    use tex;
+
+    #[test]
+    fn test_triangle() {
+        let mut backend = SoftBackEnd::new(16, 16);
+        // interleaved positions and colors
+        let data = vec![
+            -1.0f32, -1.0, 1.0, 0.0, 0.0,
+            1.0, -1.0, 0.0, 1.0, 0.0,
+            0.0, 1.0, 0.0, 0.0, 1.0,
+        ];
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &data, UsageStatic);
+        let mut list = DrawList::new();
+        let grey = Color([0.5, 0.5, 0.5, 1.0]);
+        list.call_clear(ClearData { color: Some(grey), depth: None, stencil: None });
+        list.bind_attribute(0, buf.get_name(), 2, Float(FloatDefault, F32), 20, 0, 0);
+        list.bind_attribute(1, buf.get_name(), 3, Float(FloatDefault, F32), 20, 8, 0);
+        list.call_draw(TriangleList, 0, 3, None);
+        backend.submit(&list);
+
+        let Color(center) = backend.get_pixel(8, 8);
+        let expected = [0.2, 0.27, 0.53, 1.0];
+        for (value, exp) in center.iter().zip(expected.iter()) {
+            assert!((*value - *exp).abs() < 0.01, "{} is not {}",
+                    center.as_slice(), expected.as_slice());
+        }
+        assert_eq!(backend.get_pixel(0, 15), grey);
+        assert_eq!(backend.get_frame_stats().draw_count, 1);
+    }
+
+    #[test]
+    fn test_unaligned_attribute() {
+        let mut backend = SoftBackEnd::new(4, 4);
+        // a single byte of padding before the positions
+        let mut data = vec![0u8];
+        for value in [2.0f32, 3.0].iter() {
+            let bytes: [u8, ..4] = unsafe { mem::transmute(*value) };
+            data.push_all(bytes);
+        }
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &data, UsageStatic);
+        let mut list = DrawList::new();
+        list.bind_attribute(0, buf.get_name(), 2, Float(FloatDefault, F32), 0, 1, 0);
+        backend.submit(&list);
+        assert_eq!(backend.read_attribute(0, 0), Some([2.0, 3.0, 0.0, 1.0]));
+        assert_eq!(backend.read_attribute(0, 1), None);
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut backend = SoftBackEnd::new(8, 8);
+        let data = vec![
+            -1.0f32, -1.0, 1.0, 0.0, 0.0,
+            3.0, -1.0, 1.0, 0.0, 0.0,
+            -1.0, 3.0, 1.0, 0.0, 0.0,
+        ];
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &data, UsageStatic);
+        let mut list = DrawList::new();
+        list.call_clear(ClearData { color: Some(Color([0.0, 0.0, 1.0, 1.0])), depth: None,
+                                    stencil: None });
+        list.bind_attribute(0, buf.get_name(), 2, Float(FloatDefault, F32), 20, 0, 0);
+        list.bind_attribute(1, buf.get_name(), 3, Float(FloatDefault, F32), 20, 8, 0);
+        list.call_draw(TriangleList, 0, 3, None);
+        backend.submit(&list);
+        let expected = backend.read_pixels().to_vec();
+
+        let data = list.serialize();
+        let replay = DrawList::deserialize(data.as_slice()).unwrap();
+        assert_eq!(replay.serialize(), data);
+        let mut other = DrawList::new();
+        other.call_clear(ClearData { color: Some(Color::new()), depth: None, stencil: None });
+        backend.submit(&other);
+        backend.submit(&replay);
+        assert_eq!(backend.read_pixels(), expected.as_slice());
+        assert_eq!(backend.get_frame_stats().draw_count, 2);
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut backend = SoftBackEnd::new(4, 4);
+        backend.set_validation(true);
+        let red = Color([1.0, 0.0, 0.0, 1.0]);
+        let mut list = DrawList::new();
+        list.call_clear(ClearData { color: Some(red), depth: None, stencil: None });
+        list.call_draw(TriangleList, 0, 0, None);
+        // no program is bound for the draw call
+        backend.submit(&list);
+        assert_eq!(backend.get_pixel(0, 0), Color::new());
+        let program = backend.create_program(&[]).unwrap();
+        list.clear();
+        list.bind_program(program.get_name());
+        list.call_clear(ClearData { color: Some(red), depth: None, stencil: None });
+        list.call_draw(TriangleList, 0, 0, None);
+        backend.submit(&list);
+        assert_eq!(backend.get_pixel(0, 0), red);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let mut backend = SoftBackEnd::new(4, 4);
+        let (start, end) = (backend.create_query(), backend.create_query());
+        assert_eq!(backend.get_timestamp(&start), None);
+        let mut list = DrawList::new();
+        list.query_timestamp(start.get_name());
+        list.call_clear(ClearData { color: Some(Color::new()), depth: None, stencil: None });
+        list.call_draw(TriangleList, 0, 0, None);
+        list.query_timestamp(end.get_name());
+        backend.submit(&list);
+        let t0 = backend.get_timestamp(&start).unwrap();
+        let t1 = backend.get_timestamp(&end).unwrap();
+        assert_eq!(t1 - t0, 3);
+    }
+
+    #[test]
+    fn test_block_member() {
+        use shade::{BlockVar, BlockMember, ContainerType, ValueF32Matrix};
+        use shade::{BaseF32, Single, Matrix, ColumnMajor};
+        let member = |name: &str, container: ContainerType, offset| BlockMember {
+            name: name.to_string(),
+            count: 1,
+            base_type: BaseF32,
+            container: container,
+            offset: offset,
+            array_stride: 0,
+            matrix_stride: 16,
+        };
+        let block = BlockVar {
+            name: "Locals".to_string(),
+            size: 80,
+            usage: 1,
+            members: vec![member("scale", Single, 0),
+                          member("model", Matrix(ColumnMajor, 4, 4), 16)],
+        };
+        let mut backend = SoftBackEnd::new(1, 1);
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &Vec::from_elem(96, 0xffu8), UsageStatic);
+        let identity = [[1.0f32, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0],
+                        [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        backend.set_block_member(&buf, &block, "model", ValueF32Matrix(identity)).unwrap();
+
+        let data = backend.read_buffer(&buf).unwrap();
+        assert!(data.slice_to(16).iter().all(|&b| b == 0xff));
+        assert!(data.slice_from(80).iter().all(|&b| b == 0xff));
+        // the diagonal of ones, as little-endian floats
+        let mut expected = [0u8, ..64];
+        for c in range(0u, 4) {
+            expected[c * 20 + 2] = 0x80;
+            expected[c * 20 + 3] = 0x3f;
+        }
+        assert_eq!(data.slice(16, 80), expected.as_slice());
+    }
+
+    #[test]
+    fn test_packed_block_member() {
+        use shade::{BlockVar, BlockMember, ContainerType, ValueF32Vec3, ValueF32Matrix};
+        use shade::{BaseF32, Single, Vector, Matrix, ColumnMajor};
+        let member = |name: &str, container: ContainerType, offset| BlockMember {
+            name: name.to_string(),
+            count: 1,
+            base_type: BaseF32,
+            container: container,
+            offset: offset,
+            array_stride: 0,
+            matrix_stride: 16,
+        };
+        // std140 layout of `mat3 normal; float scale; vec3 color; float intensity;`
+        let block = BlockVar {
+            name: "Locals".to_string(),
+            size: 80,
+            usage: 1,
+            members: vec![member("normal", Matrix(ColumnMajor, 3, 3), 0),
+                          member("scale", Single, 48),
+                          member("color", Vector(3), 64),
+                          member("intensity", Single, 76)],
+        };
+        let mut backend = SoftBackEnd::new(1, 1);
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &Vec::from_elem(80, 0xffu8), UsageStatic);
+        let identity = [[1.0f32, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0],
+                        [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        backend.set_block_member(&buf, &block, "normal", ValueF32Matrix(identity)).unwrap();
+        backend.set_block_member(&buf, &block, "color", ValueF32Vec3([0.0, ..3])).unwrap();
+
+        let data = backend.read_buffer(&buf).unwrap();
+        // the last column stops at its third component
+        assert!(data.slice(44, 64).iter().all(|&b| b == 0xff));
+        assert!(data.slice(64, 76).iter().all(|&b| b == 0));
+        assert!(data.slice_from(76).iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn test_copy_buffer() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let src = backend.create_buffer();
+        backend.update_buffer(src, &vec![1u8, 2, 3, 4, 5, 6, 7, 8], UsageStatic);
+        let dst = backend.create_buffer();
+        backend.update_buffer(dst, &Vec::from_elem(6, 0u8), UsageStatic);
+        backend.copy_buffer(src, 2, dst, 1, 4).unwrap();
+        assert_eq!(backend.read_buffer(&dst).unwrap(), [0u8, 3, 4, 5, 6, 0].as_slice());
+        // neither range may go past the end of its buffer
+        assert_eq!(backend.copy_buffer(src, 6, dst, 0, 4), Err(::BufferOutOfRange));
+        assert_eq!(backend.copy_buffer(src, 0, dst, 4, 4), Err(::BufferOutOfRange));
+        assert_eq!(backend.read_buffer(&dst).unwrap(), [0u8, 3, 4, 5, 6, 0].as_slice());
+    }
+
+    #[test]
+    fn test_clear_buffer() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &Vec::from_elem(12, 0u8), UsageStatic);
+        backend.clear_buffer(buf, 0x12345678);
+        // the value is repeated over the whole buffer, as little-endian words
+        assert_eq!(backend.read_buffer(&buf).unwrap(),
+                   [0x78u8, 0x56, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12,
+                    0x78, 0x56, 0x34, 0x12].as_slice());
+    }
+
+    #[test]
+    fn test_batches() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let mut list = DrawList::new();
+        for _ in range(0u, 10) {
+            list.call_clear(ClearData { color: Some(Color::new()), depth: None, stencil: None });
+        }
+        backend.submit(&list);
+        assert_eq!(backend.get_flush_count(), 0);
+        backend.set_max_batch_size(Some(4));
+        backend.submit(&list);
+        // flushed after the 4th and the 8th commands
+        assert_eq!(backend.get_flush_count(), 2);
+    }
+
+    #[test]
+    fn test_flush_finish() {
+        let mut backend = SoftBackEnd::new(8, 8);
+        backend.flush();
+        assert_eq!((backend.get_flush_count(), backend.get_finish_count()), (1, 0));
+        backend.finish();
+        assert_eq!((backend.get_flush_count(), backend.get_finish_count()), (1, 1));
+    }
+
+    #[test]
+    fn test_raw_name() {
+        let mut backend = SoftBackEnd::new(8, 8);
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+        let buffer = backend.create_buffer();
+        let program = backend.create_program(&[]).unwrap();
+        assert!(texture.raw_name() != 0);
+        assert!(buffer.raw_name() != 0);
+        assert!(program.raw_name() != 0);
+        assert_eq!(texture.raw_name(), texture.get_name());
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut backend = SoftBackEnd::new(8, 8);
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+        let buffer = backend.create_buffer();
+        assert_eq!(backend.get_label(texture.get_name()), None);
+        backend.set_texture_label(&texture, "player_texture");
+        backend.set_buffer_label(&buffer, "player_mesh");
+        assert_eq!(backend.get_label(texture.get_name()), Some("player_texture"));
+        assert_eq!(backend.get_label(buffer.get_name()), Some("player_mesh"));
+    }
+
+    #[test]
+    fn test_rect_texture() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let info = tex::TextureInfo { width: 640, height: 480, kind: tex::TextureRect,
+                                      mipmap_range: (0, 0), .. tex::TextureInfo::new() };
+        let texture = backend.create_texture(info).unwrap();
+        let data = Vec::from_elem(320 * 240 * 4, 0u8);
+        let img = tex::ImageInfo { width: 320, height: 240, .. texture.get_info().to_image_info() };
+        assert_eq!(backend.update_texture(&texture, &img, &data), Ok(()));
+        let level = tex::ImageInfo { mipmap: 1, .. img };
+        assert_eq!(backend.update_texture(&texture, &level, &data), Err(::TextureOutOfRange));
+        for &range in [(2, 4), (0, 4), (0, -1)].iter() {
+            assert_eq!(backend.create_texture(tex::TextureInfo { mipmap_range: range, .. info })
+                              .err(), Some(::TextureRectMipmap));
+        }
+    }
+
+    #[test]
+    fn test_buffer_texture() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let data = vec![0.0f32, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.25];
+        let buf = backend.create_buffer();
+        backend.update_buffer(buf, &data, UsageStatic);
+        assert_eq!(backend.create_buffer_texture(&buf, tex::RGB10A2).err(),
+                   Some(::UnsupportedTextureFormat));
+        let texture = backend.create_buffer_texture(&buf, tex::RGBA32F).unwrap();
+        assert_eq!(texture.get_info().kind, tex::TextureBuffer);
+        assert_eq!(backend.create_texture(texture.get_info().clone()).err(),
+                   Some(::TextureBufferStorage));
+        let mut list = DrawList::new();
+        list.bind_texture(0, tex::TextureBuffer, texture.get_name(), None);
+        list.call_draw(TriangleList, 0, 3, None);
+        backend.submit(&list);
+        assert_eq!(backend.get_frame_stats().draw_count, 1);
+    }
+
+    #[test]
+    fn test_copy_texture() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let src = backend.create_texture(tex::TextureInfo {
+            width: 64, height: 64, .. tex::TextureInfo::new()
+        }).unwrap();
+        let dst = backend.create_texture(tex::TextureInfo {
+            width: 32, height: 32, .. tex::TextureInfo::new()
+        }).unwrap();
+        let region = tex::ImageInfo {
+            xoffset: 16, yoffset: 16, width: 16, height: 16, .. tex::ImageInfo::new()
+        };
+        assert_eq!(backend.copy_texture(&src, &region, &dst, 0, (16, 0, 0)), Ok(()));
+        // the region ends past the destination
+        assert_eq!(backend.copy_texture(&src, &region, &dst, 0, (24, 0, 0)),
+                   Err(::TextureOutOfRange));
+        // the region starts past the source
+        let outside = tex::ImageInfo { xoffset: 56, .. region };
+        assert_eq!(backend.copy_texture(&src, &outside, &dst, 0, (0, 0, 0)),
+                   Err(::TextureOutOfRange));
+    }
+
+    #[test]
+    fn test_update_immutable_texture() {
+        let mut backend = SoftBackEnd::new(1, 1);
+        let texture = backend.create_texture_immutable(tex::TextureInfo {
+            width: 32, height: 32, mipmap_range: (0, 1), .. tex::TextureInfo::new()
+        }).unwrap();
+        let data = Vec::from_elem(64 * 64 * 4, 0u8);
+        let img = tex::ImageInfo { width: 16, height: 16, .. tex::ImageInfo::new() };
+        assert_eq!(backend.update_texture(&texture, &img, &data), Ok(()));
+        // a larger image would specify the storage again
+        let larger = tex::ImageInfo { width: 64, height: 64, .. img };
+        assert_eq!(backend.update_texture(&texture, &larger, &data), Err(::TextureOutOfRange));
+        // so would a mipmap level past the storage
+        let level = tex::ImageInfo { mipmap: 2, .. img };
+        assert_eq!(backend.update_texture(&texture, &level, &data), Err(::TextureOutOfRange));
+        // the data format is converted on upload
+        let converted = tex::ImageInfo { format: tex::RGBA32F, .. img };
+        assert_eq!(backend.update_texture(&texture, &converted, &data), Ok(()));
+    }
+}
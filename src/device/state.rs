@@ -17,11 +17,11 @@
 //! Configures the primitive assembly (PA), rasterizer, and output merger (OM) blocks.
 
 use std::default::Default;
-use std::fmt;
+use std::{fmt, hash, mem};
 use StencilValue = super::target::Stencil;
 
 /// The winding order of a set of vertices.
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum WindingOrder {
     /// Clockwise winding order.
     Clockwise,
@@ -38,15 +38,45 @@ pub type OffsetUnits = u32;
 
 /// How to offset vertices in screen space, if at all.
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, Show)]
 pub enum OffsetType {
     NoOffset,
     Offset(OffsetFactor, OffsetUnits),
 }
 
+/// Returns the bit pattern of a float, used for exact comparison and hashing.
+fn float_bits(value: f32) -> u32 {
+    unsafe { mem::transmute(value) }
+}
+
+impl PartialEq for OffsetType {
+    fn eq(&self, other: &OffsetType) -> bool {
+        match (*self, *other) {
+            (NoOffset, NoOffset) => true,
+            (Offset(fa, ua), Offset(fb, ub)) => float_bits(fa) == float_bits(fb) && ua == ub,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for OffsetType {}
+
+impl<S: hash::Writer> hash::Hash<S> for OffsetType {
+    fn hash(&self, state: &mut S) {
+        match *self {
+            NoOffset => 0u8.hash(state),
+            Offset(factor, units) => {
+                1u8.hash(state);
+                float_bits(factor).hash(state);
+                units.hash(state);
+            },
+        }
+    }
+}
+
 /// Which face, if any, to cull.
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum CullMode {
     CullNothing,
     CullFront,
@@ -54,7 +84,7 @@ pub enum CullMode {
 }
 
 /// How to rasterize a primitive.
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, Show)]
 pub enum RasterMethod {
     /// Rasterize as a point.
     Point,
@@ -64,9 +94,38 @@ pub enum RasterMethod {
     Fill(CullMode),
 }
 
+impl PartialEq for RasterMethod {
+    fn eq(&self, other: &RasterMethod) -> bool {
+        match (*self, *other) {
+            (Point, Point) => true,
+            (Line(a), Line(b)) => float_bits(a) == float_bits(b),
+            (Fill(a), Fill(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for RasterMethod {}
+
+impl<S: hash::Writer> hash::Hash<S> for RasterMethod {
+    fn hash(&self, state: &mut S) {
+        match *self {
+            Point => 0u8.hash(state),
+            Line(width) => {
+                1u8.hash(state);
+                float_bits(width).hash(state);
+            },
+            Fill(cull) => {
+                2u8.hash(state);
+                cull.hash(state);
+            },
+        }
+    }
+}
+
 /// Primitive rasterization state. Note that GL allows different raster
 /// method to be used for front and back, while this abstraction does not.
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct Primitive {
     /// Which vertex winding is considered to be the front face for culling.
     pub front_face: WindingOrder,
@@ -97,7 +156,7 @@ impl Default for Primitive {
 }
 
 /// A pixel-wise comparison function.
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum Comparison {
     /// `false`
     Never,
@@ -119,7 +178,7 @@ pub enum Comparison {
 
 /// Stencil mask operation.
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum StencilOp {
     /// Keep the current value in the stencil buffer (no change).
     OpKeep,
@@ -140,7 +199,7 @@ pub enum StencilOp {
 }
 
 /// Complete stencil state for a given side of a face.
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct StencilSide {
     /// Comparison function to use to determine if the stencil test passes.
     pub fun: Comparison,
@@ -175,14 +234,14 @@ impl Default for StencilSide {
 
 /// Complete stencil state, specifying how to handle the front and back side of a face.
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct Stencil {
     pub front: StencilSide,
     pub back: StencilSide,
 }
 
 /// Depth test state.
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct Depth {
     /// Comparison function to use.
     pub fun: Comparison,
@@ -199,8 +258,46 @@ impl Default for Depth {
     }
 }
 
+/// A set of user clip planes, bit `i` enabling the clip distance `i` written by the shader.
+pub type ClipPlaneMask = u8;
+
+/// Mapping of the normalized device depth into the window depth.
+/// Swapping `near` and `far` gives a reversed depth buffer.
+#[deriving(Clone, Show)]
+pub struct DepthRange {
+    /// Window depth the near clipping plane maps to.
+    pub near: f32,
+    /// Window depth the far clipping plane maps to.
+    pub far: f32,
+}
+
+impl PartialEq for DepthRange {
+    fn eq(&self, other: &DepthRange) -> bool {
+        float_bits(self.near) == float_bits(other.near) &&
+        float_bits(self.far) == float_bits(other.far)
+    }
+}
+
+impl Eq for DepthRange {}
+
+impl<S: hash::Writer> hash::Hash<S> for DepthRange {
+    fn hash(&self, state: &mut S) {
+        float_bits(self.near).hash(state);
+        float_bits(self.far).hash(state);
+    }
+}
+
+impl Default for DepthRange {
+    fn default() -> DepthRange {
+        DepthRange {
+            near: 0.0,
+            far: 1.0,
+        }
+    }
+}
+
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum Equation {
     FuncAdd,
     FuncSub,
@@ -210,14 +307,14 @@ pub enum Equation {
 }
 
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum InverseFlag {
     Normal,
     Inverse,
 }
 
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum BlendValue {
     Zero,
     SourceColor,
@@ -230,11 +327,21 @@ pub enum BlendValue {
 }
 
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct Factor(pub InverseFlag, pub BlendValue);
 
+impl Factor {
+    /// Whether this factor refers to the constant blend color.
+    pub fn is_constant(&self) -> bool {
+        match *self {
+            Factor(_, ConstColor) | Factor(_, ConstAlpha) => true,
+            _ => false,
+        }
+    }
+}
+
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct BlendChannel {
     pub equation: Equation,
     pub source: Factor,
@@ -252,13 +359,21 @@ impl Default for BlendChannel {
 }
 
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct Blend {
     pub color: BlendChannel,
     pub alpha: BlendChannel,
     pub value: super::target::Color,
 }
 
+impl Blend {
+    /// Whether any of the blend factors use the constant blend color `value`.
+    pub fn uses_constant(&self) -> bool {
+        self.color.source.is_constant() || self.color.destination.is_constant() ||
+        self.alpha.source.is_constant() || self.alpha.destination.is_constant()
+    }
+}
+
 impl Default for Blend {
     fn default() -> Blend {
         Blend {
@@ -269,6 +384,44 @@ impl Default for Blend {
     }
 }
 
+/// A bitwise operation combining the source color (`s`) with the destination one (`d`).
+/// Only applies to integer and normalized color targets.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub enum LogicOp {
+    /// `0`
+    LogicClear,
+    /// `s & d`
+    LogicAnd,
+    /// `s & !d`
+    LogicAndReverse,
+    /// `s`
+    LogicCopy,
+    /// `!s & d`
+    LogicAndInverted,
+    /// `d`
+    LogicNoop,
+    /// `s ^ d`
+    LogicXor,
+    /// `s | d`
+    LogicOr,
+    /// `!(s | d)`
+    LogicNor,
+    /// `!(s ^ d)`
+    LogicEquiv,
+    /// `!d`
+    LogicInvert,
+    /// `s | !d`
+    LogicOrReverse,
+    /// `!s`
+    LogicCopyInverted,
+    /// `!s | d`
+    LogicOrInverted,
+    /// `!(s & d)`
+    LogicNand,
+    /// `1`
+    LogicSet,
+}
+
 #[deriving(Clone, PartialEq)]
 bitflags!(
     #[allow(missing_doc)]
@@ -283,6 +436,6 @@ bitflags!(
 
 impl fmt::Show for ColorMask {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ColorMask({})", *self)
+        write!(f, "ColorMask({:#x})", self.bits())
     }
 }
@@ -14,7 +14,7 @@
 
 //! Render target specification.
 
-use std::{default, fmt};
+use std::{cmp, default, fmt, hash, mem};
 
 // TODO: Really tighten up the terminology here.
 
@@ -26,10 +26,12 @@ pub type Level = u8;
 pub type Depth = f32;
 /// A single value from a stencil stencstencil buffer.
 pub type Stencil = u8;
+/// Index of a color target.
+pub type ColorIndex = u8;
 
-/// A screen space rectangle
+/// A screen space rectangle, used for the viewport and the scissor test
 #[allow(missing_doc)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub struct Rect {
     pub x: u16,
     pub y: u16,
@@ -37,6 +39,18 @@ pub struct Rect {
     pub h: u16,
 }
 
+impl Rect {
+    /// Create a rectangle from its origin and size.
+    pub fn new(x: u16, y: u16, w: u16, h: u16) -> Rect {
+        Rect {
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+        }
+    }
+}
+
 /// A color with floating-point components. Used for `ClearData`.
 pub struct Color(pub [f32, ..4]);
 
@@ -49,6 +63,55 @@ impl Color {
     }
 }
 
+impl Color {
+    /// Returns the color with the RGB components multiplied by the alpha.
+    pub fn premultiply(&self) -> Color {
+        let Color([r, g, b, a]) = *self;
+        Color([r * a, g * a, b * a, a])
+    }
+
+    /// Returns the color with the RGB components divided by the alpha, which
+    /// reverses `premultiply`. A fully transparent color is returned as is.
+    pub fn unpremultiply(&self) -> Color {
+        let Color([r, g, b, a]) = *self;
+        if a == 0.0 {
+            Color([r, g, b, a])
+        } else {
+            Color([r / a, g / a, b / a, a])
+        }
+    }
+
+    /// Converts the RGB components from the sRGB space into the linear one.
+    /// The alpha is left untouched.
+    pub fn to_linear(&self) -> Color {
+        let Color([r, g, b, a]) = *self;
+        Color([srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a])
+    }
+
+    /// Converts the RGB components from the linear space into the sRGB one.
+    /// The alpha is left untouched.
+    pub fn to_srgb(&self) -> Color {
+        let Color([r, g, b, a]) = *self;
+        Color([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a])
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 impl Clone for Color {
     fn clone(&self) -> Color {
         let Color(ref x) = *self;
@@ -56,11 +119,37 @@ impl Clone for Color {
     }
 }
 
+impl Color {
+    /// Returns the bit patterns of the components, used for exact comparison and hashing.
+    fn to_bits(&self) -> [u32, ..4] {
+        let Color(x) = *self;
+        unsafe { mem::transmute(x) }
+    }
+}
+
 impl PartialEq for Color {
+    /// Compares the bit patterns, so that `Color` can be used as a key.
     fn eq(&self, other: &Color) -> bool {
+        let x = self.to_bits();
+        let y = other.to_bits();
+        x[0] == y[0] && x[1] == y[1] && x[2] == y[2] && x[3] == y[3]
+    }
+}
+
+impl Eq for Color {}
+
+impl PartialOrd for Color {
+    /// Compares the components in order, like the arrays they are made of.
+    fn partial_cmp(&self, other: &Color) -> Option<cmp::Ordering> {
         let Color(ref x) = *self;
         let Color(ref y) = *other;
-        x[0] == y[0] && x[1] == y[1] && x[2] == y[2] && x[3] == y[3]
+        x.as_slice().partial_cmp(&y.as_slice())
+    }
+}
+
+impl<S: hash::Writer> hash::Hash<S> for Color {
+    fn hash(&self, state: &mut S) {
+        self.to_bits().as_slice().hash(state)
     }
 }
 
@@ -83,8 +172,10 @@ pub struct ClearData {
     /// If set, the color buffer of the frame will be cleared to this.
     pub color: Option<Color>,
     /// If set, the depth buffer of the frame will be cleared to this.
+    /// The depth writes are enabled for the clear.
     pub depth: Option<Depth>,
     /// If set, the stencil buffer of the frame will be cleared to this.
+    /// All the stencil bits are written, regardless of the stencil mask.
     pub stencil: Option<Stencil>,
 }
 
@@ -97,7 +188,7 @@ pub enum Target {
     /// # Portability Note
     ///
     /// The device is only required to expose one color target.
-    TargetColor(u8),
+    TargetColor(ColorIndex),
     /// Depth data.
     TargetDepth,
     /// Stencil data.
@@ -105,3 +196,41 @@ pub enum Target {
     /// A target for both depth and stencil data at once.
     TargetDepthStencil,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, Rect};
+
+    #[test]
+    fn test_rect() {
+        let rect = Rect::new(1, 2, 30, 40);
+        assert_eq!(rect, Rect { x: 1, y: 2, w: 30, h: 40 });
+        assert!(rect != Rect::new(1, 2, 40, 30));
+    }
+
+    #[test]
+    fn test_premultiply() {
+        let color = Color([1.0, 0.5, 0.25, 0.5]);
+        assert_eq!(color.premultiply(), Color([0.5, 0.25, 0.125, 0.5]));
+        assert_eq!(color.premultiply().unpremultiply(), color);
+        let clear = Color([0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(clear.unpremultiply(), clear);
+    }
+
+    #[test]
+    fn test_srgb() {
+        let Color([r, g, b, a]) = Color([0.5, 0.0, 1.0, 0.5]).to_linear();
+        assert!((r - 0.214).abs() < 0.001);
+        assert_eq!((g, b, a), (0.0, 1.0, 0.5));
+        let Color([r, _, _, _]) = Color([0.5, 0.0, 0.0, 1.0]).to_linear().to_srgb();
+        assert!((r - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_color_order() {
+        let red = Color([1.0, 0.0, 0.0, 1.0]);
+        let green = Color([0.0, 1.0, 0.0, 1.0]);
+        assert!(green < red);
+        assert!(!(red < red));
+    }
+}
@@ -22,6 +22,7 @@
 //! texels.
 
 use std::default::Default;
+use target::Color;
 
 /// Number of bits per component
 pub type Bits = u8;
@@ -62,11 +63,117 @@ pub enum Format {
     /// This s an RGB format of type floating-point. The 3 color values have
     /// 9 bits of precision, and they share a single exponent.
     RGB9E5,
+    /// 24-bit normalized depth.
+    DEPTH24,
+    /// 32-bit floating-point depth.
+    DEPTH32F,
+    /// 24-bit normalized depth, packed together with 8 bits of stencil.
+    DEPTH24STENCIL8,
+    /// 8-bit stencil only.
+    STENCIL8,
     // TODO: sRGB, compression
 }
 
 /// A commonly used RGBA8 format
 pub static RGBA8: Format = Unsigned(RGBA, 8, ::attrib::IntNormalized);
+/// A half-float RGBA format, commonly used for HDR render targets
+pub static RGBA16F: Format = Float(RGBA, ::attrib::F16);
+/// A full-float RGBA format
+pub static RGBA32F: Format = Float(RGBA, ::attrib::F32);
+/// An RGBA format of raw 8-bit unsigned integers
+pub static RGBA8UI: Format = Unsigned(RGBA, 8, ::attrib::IntRaw);
+
+fn count_components(c: Components) -> uint {
+    match c {
+        R => 1,
+        RG => 2,
+        RGB => 3,
+        RGBA => 4,
+    }
+}
+
+impl Format {
+    /// Check if the format stores floating-point values, which requires the
+    /// device to support float color buffers in order to be rendered to.
+    pub fn is_float(&self) -> bool {
+        match *self {
+            Float(_, _) | R11FG11FB10F | RGB9E5 => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the format stores raw signed integers, which a shader reads
+    /// as `ivec4`, and which have to be cleared with integer values.
+    pub fn is_signed_integer(&self) -> bool {
+        match *self {
+            Integer(_, _, ::attrib::IntRaw) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the format stores raw unsigned integers, read as `uvec4`.
+    pub fn is_unsigned_integer(&self) -> bool {
+        match *self {
+            Unsigned(_, _, ::attrib::IntRaw) | RGB10A2UI => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the format has a depth component, in which case it can only be
+    /// bound to the depth target.
+    pub fn is_depth(&self) -> bool {
+        match *self {
+            DEPTH24 | DEPTH32F | DEPTH24STENCIL8 => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the format has a stencil component.
+    pub fn has_stencil(&self) -> bool {
+        match *self {
+            DEPTH24STENCIL8 | STENCIL8 => true,
+            _ => false,
+        }
+    }
+
+    /// Get the size of a texel in bits, which is what decides if a texture
+    /// view can reinterpret it with another format. Returns `None` for the
+    /// formats that can only be viewed as themselves.
+    fn get_view_bits(&self) -> Option<uint> {
+        match *self {
+            Float(c, ::attrib::F16) => Some(16 * count_components(c)),
+            Float(c, ::attrib::F32) => Some(32 * count_components(c)),
+            Integer(c, bits, _) | Unsigned(c, bits, _) => Some(bits as uint * count_components(c)),
+            RGB10A2 | RGB10A2UI | R11FG11FB10F | RGB9E5 => Some(32),
+            _ => None,
+        }
+    }
+
+    /// Check if a buffer texture can read its texels in this format, which is
+    /// limited to the sized R, RG and RGBA formats of 8, 16 or 32 bits per
+    /// component, or RGB with 32 bits. Normalized integers have to be
+    /// unsigned.
+    pub fn is_buffer_compatible(&self) -> bool {
+        match *self {
+            Float(RGB, size) => size == ::attrib::F32,
+            Float(_, size) => size == ::attrib::F16 || size == ::attrib::F32,
+            Integer(RGB, bits, ::attrib::IntRaw) |
+            Unsigned(RGB, bits, ::attrib::IntRaw) => bits == 32,
+            Integer(_, bits, ::attrib::IntRaw) |
+            Unsigned(_, bits, ::attrib::IntRaw) => bits == 8 || bits == 16 || bits == 32,
+            Unsigned(c, bits, ::attrib::IntNormalized) => c != RGB && (bits == 8 || bits == 16),
+            _ => false,
+        }
+    }
+
+    /// Check if a texture view can interpret the texels of this format as `other`.
+    pub fn is_view_compatible(&self, other: &Format) -> bool {
+        *self == *other || match (self.get_view_bits(), other.get_view_bits()) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
 
 /// Describes the storage of a surface
 #[allow(missing_doc)]
@@ -128,9 +235,27 @@ pub enum TextureKind {
     TextureCube,
     /// A volume texture, with each 2D layer arranged contiguously.
     Texture3D,
+    /// A 2D texture sampled with unnormalized texel coordinates, bound as
+    /// `sampler2DRect`. It only has the base mipmap level, so its mipmap
+    /// range has to be `(0, 0)`, and can't use the repeating wrap modes.
+    TextureRect,
+    /// A row of texels stored in a buffer, bound as `samplerBuffer`. It has
+    /// no mipmap levels and ignores the sampler state.
+    TextureBuffer,
     // TODO: Multisampling?
 }
 
+/// How a shader is allowed to access an image bound for load/store.
+#[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
+pub enum ImageAccess {
+    /// The image can only be read from.
+    ReadOnly,
+    /// The image can only be written to.
+    WriteOnly,
+    /// The image can be both read from and written to.
+    ReadWrite,
+}
+
 /// Describes the storage of a texture.
 ///
 /// # Portability note
@@ -147,6 +272,7 @@ pub struct TextureInfo {
     /// this texture. Defaults to `(0, -1)`, that is, every mipmap level
     /// available. 0 is the base mipmap level, with the full-sized texture,
     /// and every level after that shrinks each dimension by a factor of 2.
+    /// These are the base and max levels of the texture.
     pub mipmap_range: (u8, u8),
     pub kind: TextureKind,
     pub format: Format,
@@ -217,13 +343,78 @@ impl TextureInfo {
         }
     }
 
+    /// Get the number of layers of the texture, as addressed by a view.
+    pub fn get_layer_count(&self) -> u16 {
+        match self.kind {
+            Texture1DArray => self.height,
+            Texture2DArray => self.depth,
+            TextureCube => 6,
+            _ => 1,
+        }
+    }
+
+    /// Get the info of a view into this texture, with the given format and
+    /// ranges of mipmap levels and layers, each as a `(first, count)` pair.
+    pub fn to_view_info(&self, format: Format, (level, num_levels): (u8, u8),
+                        (layer, num_layers): (u16, u16)) -> Result<TextureInfo, ::TextureError> {
+        use std::cmp::max;
+        if !self.format.is_view_compatible(&format) {
+            return Err(::TextureFormatMismatch)
+        }
+        let (lo, hi) = self.mipmap_range;
+        if num_levels == 0 || level < lo ||
+                level as uint + num_levels as uint > hi as uint + 1 ||
+                num_layers == 0 ||
+                layer as uint + num_layers as uint > self.get_layer_count() as uint {
+            return Err(::TextureOutOfRange)
+        }
+        let shrink = |size: u16| max(size >> level as uint, 1);
+        Ok(TextureInfo {
+            width: shrink(self.width),
+            height: match self.kind {
+                Texture1D => 1,
+                Texture1DArray => num_layers,
+                _ => shrink(self.height),
+            },
+            depth: match self.kind {
+                Texture2DArray => num_layers,
+                Texture3D => shrink(self.depth),
+                _ => 1,
+            },
+            mipmap_range: (0, num_levels - 1),
+            kind: self.kind,
+            format: format,
+        })
+    }
+
+    /// Check that the texture kind supports the requested mipmap range.
+    pub fn check_kind(&self) -> Result<(), ::TextureError> {
+        match self.kind {
+            TextureRect if self.mipmap_range != (0, 0) => Err(::TextureRectMipmap),
+            TextureBuffer => Err(::TextureBufferStorage),
+            _ => Ok(()),
+        }
+    }
+
     /// Check if given ImageInfo is a part of the texture
     pub fn contains(&self, img: &ImageInfo) -> bool {
-        self.width <= img.xoffset + img.width &&
-        self.height <= img.yoffset + img.height &&
-        self.depth <= img.zoffset + img.depth &&
+        img.xoffset as uint + img.width as uint <= self.width as uint &&
+        img.yoffset as uint + img.height as uint <= self.height as uint &&
+        img.zoffset as uint + img.depth as uint <= self.depth as uint &&
         self.format == img.format &&
-        self.mipmap_range.val0() <= img.mipmap && img.mipmap < self.mipmap_range.val1()
+        self.mipmap_range.val0() <= img.mipmap && img.mipmap <= self.mipmap_range.val1()
+    }
+
+    /// Check that the data described by `img` can be uploaded into the
+    /// texture, whose storage is never specified again. The format of the
+    /// data may differ, it is converted on upload.
+    pub fn check_update(&self, img: &ImageInfo) -> Result<(), ::TextureError> {
+        let region = ImageInfo { format: self.format, .. *img };
+        if self.contains(&region) {
+            Ok(())
+        } else {
+            Err(::TextureOutOfRange)
+        }
     }
 }
 
@@ -242,6 +433,8 @@ pub enum WrapMode {
     Mirror,
     /// Clamp the texture to the value at `0.0` or `1.0` respectively.
     Clamp,
+    /// Use the border color of the sampler outside of the `[0, 1]` range.
+    Border,
 }
 
 /// Specifies how to sample from a texture.
@@ -257,8 +450,15 @@ pub struct SamplerInfo {
     /// example, if it would select mipmap level 2 and lod_bias is 1, it will
     /// use mipmap level 3.
     pub lod_bias: f32,
-    /// This range is used to clamp LOD level used for sampling
+    /// This range is used to clamp LOD level used for sampling, as the
+    /// `(min, max)` LOD. Together with the `mipmap_range` of the texture, it
+    /// controls which mipmap levels can be sampled. Raising the min LOD shows
+    /// a coarser level, for example while the detailed ones are still being
+    /// uploaded.
     pub lod_range: (f32, f32),
+    /// Color sampled outside of the texture by the axes using the `Border`
+    /// wrap mode. Ignored if none of them does.
+    pub border_color: Color,
     // TODO: comparison mode
 }
 
@@ -271,6 +471,97 @@ impl SamplerInfo {
             wrap_mode: (wrap, wrap, wrap),
             lod_bias: 0.0,
             lod_range: (-1000.0, 1000.0),
+            border_color: Color::new(),
+        }
+    }
+
+    /// Check if any of the axes samples the border color.
+    pub fn uses_border(&self) -> bool {
+        let (s, t, r) = self.wrap_mode;
+        s == Border || t == Border || r == Border
+    }
+
+    /// Check if the sampler can be used with a `TextureRect`, which allows
+    /// neither mipmapped filtering nor the repeating wrap modes.
+    pub fn is_rect_compatible(&self) -> bool {
+        let (s, t, r) = self.wrap_mode;
+        let no_repeat = |w: WrapMode| w == Clamp || w == Border;
+        match self.filtering {
+            Scale | Bilinear => no_repeat(s) && no_repeat(t) && no_repeat(r),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use target::Color;
+    use super::{TextureInfo, ImageInfo, SamplerInfo, Bilinear, Trilinear, Border, Clamp, Tile};
+    use super::{RGBA8, RGBA8UI, RGBA16F, RGBA32F, Texture2DArray, TextureRect};
+    use super::{Float, Integer, Unsigned, R, RGB, RGB10A2, DEPTH24};
+
+    #[test]
+    fn test_contains() {
+        let tinfo = TextureInfo { width: 64, height: 32, .. TextureInfo::new() };
+        let inside = ImageInfo { xoffset: 16, yoffset: 8, width: 48, height: 24, .. ImageInfo::new() };
+        let outside = ImageInfo { xoffset: 32, width: 48, .. inside };
+        assert!(tinfo.contains(&tinfo.to_image_info()));
+        assert!(tinfo.contains(&inside));
+        assert!(!tinfo.contains(&outside));
+    }
+
+    #[test]
+    fn test_border() {
+        let mut info = SamplerInfo::new(Bilinear, Border);
+        info.border_color = Color([1.0, 0.0, 0.0, 1.0]);
+        assert!(info.uses_border());
+        assert_eq!(info.border_color, Color([1.0, 0.0, 0.0, 1.0]));
+        assert!(!SamplerInfo::new(Bilinear, Clamp).uses_border());
+    }
+
+    #[test]
+    fn test_view_info() {
+        let tinfo = TextureInfo { width: 64, height: 32, depth: 4, kind: Texture2DArray,
+                                  mipmap_range: (0, 6), .. TextureInfo::new() };
+        assert!(RGBA8.is_view_compatible(&RGBA8UI));
+        assert!(!RGBA8.is_view_compatible(&RGBA16F));
+        let view = tinfo.to_view_info(RGBA8UI, (1, 2), (2, 2)).unwrap();
+        assert_eq!((view.width, view.height, view.depth), (32, 16, 2));
+        assert_eq!(view.mipmap_range, (0, 1));
+        assert_eq!(view.format, RGBA8UI);
+        assert_eq!(tinfo.to_view_info(RGBA16F, (0, 1), (0, 1)), Err(::TextureFormatMismatch));
+        assert_eq!(tinfo.to_view_info(RGBA8UI, (6, 2), (0, 1)), Err(::TextureOutOfRange));
+        assert_eq!(tinfo.to_view_info(RGBA8UI, (0, 1), (3, 2)), Err(::TextureOutOfRange));
+    }
+
+    #[test]
+    fn test_rect() {
+        let tinfo = TextureInfo { width: 640, height: 480, kind: TextureRect,
+                                  mipmap_range: (0, 0), .. TextureInfo::new() };
+        assert_eq!(tinfo.check_kind(), Ok(()));
+        assert!(tinfo.contains(&ImageInfo { width: 640, height: 480, .. ImageInfo::new() }));
+        assert!(!tinfo.contains(&ImageInfo { width: 320, height: 240, mipmap: 1,
+                                             .. ImageInfo::new() }));
+        for &range in [(1, 4), (0, 4), (0, -1)].iter() {
+            let mipmapped = TextureInfo { mipmap_range: range, .. tinfo };
+            assert_eq!(mipmapped.check_kind(), Err(::TextureRectMipmap));
+        }
+        assert!(SamplerInfo::new(Bilinear, Clamp).is_rect_compatible());
+        assert!(!SamplerInfo::new(Bilinear, Tile).is_rect_compatible());
+        assert!(!SamplerInfo::new(Trilinear, Clamp).is_rect_compatible());
+    }
+
+    #[test]
+    fn test_buffer_formats() {
+        use attrib::{F16, F32, IntRaw, IntNormalized};
+        for format in [RGBA8, RGBA8UI, RGBA16F, RGBA32F, Float(RGB, F32), Integer(R, 16, IntRaw),
+                       Unsigned(RGB, 32, IntRaw)].iter() {
+            assert!(format.is_buffer_compatible(), "{} is rejected", format);
+        }
+        for format in [Float(RGB, F16), Unsigned(RGB, 8, IntNormalized),
+                       Unsigned(R, 32, IntNormalized), Integer(R, 8, IntNormalized),
+                       RGB10A2, DEPTH24].iter() {
+            assert!(!format.is_buffer_compatible(), "{} is accepted", format);
         }
     }
 }
@@ -0,0 +1,168 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of the command stream before it's submitted.
+//!
+//! Each draw call is checked against the reflection of the bound program:
+//! all of its attributes, uniforms, blocks and textures have to be bound by
+//! the previous commands of the same list. Programs without a known
+//! `ProgramInfo` are not checked.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use {back, shade};
+use Command;
+
+/// What a draw call is missing.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Missing {
+    /// No program is bound
+    MissingProgram,
+    /// The named vertex attribute has no buffer bound
+    MissingAttribute(String),
+    /// The named uniform was never set
+    MissingUniform(String),
+    /// The named uniform block has no buffer bound
+    MissingBlock(String),
+    /// The named sampler has no texture bound to its unit
+    MissingTexture(String),
+}
+
+/// A draw call that would read unbound resources.
+#[deriving(Clone, PartialEq)]
+pub struct ValidationError {
+    /// Index of the draw command in the list
+    pub command: uint,
+    /// The first missing resource
+    pub missing: Missing,
+}
+
+impl fmt::Show for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Draw command {}: {}", self.command, self.missing)
+    }
+}
+
+/// Check that each draw call of the commands has all the resources of its
+/// program bound, reporting the first missing one.
+pub fn validate<'a, I: Iterator<&'a Command>>(commands: I,
+                programs: &HashMap<back::Program, shade::ProgramInfo>)
+                -> Result<(), ValidationError> {
+    let mut program = None;
+    let mut attributes = HashSet::new();
+    let mut uniforms = HashMap::new();
+    let mut blocks = HashSet::new();
+    let mut units = HashSet::new();
+    for (index, command) in commands.enumerate() {
+        match *command {
+            ::BindProgram(name) => program = Some(name),
+            ::BindArrayBuffer(_) => attributes.clear(),
+            ::BindAttribute(slot, _, _, _, _, _, _) => { attributes.insert(slot); },
+            ::BindUniform(loc, value) => match program {
+                Some(name) => {
+                    let unit = match value {
+                        shade::ValueI32(v) => Some(v),
+                        _ => None,
+                    };
+                    uniforms.insert((name, loc), unit);
+                },
+                None => (),
+            },
+            ::BindUniformBlock(name, _, block, _) |
+            ::BindUniformBlockRange(name, _, block, _, _, _) => { blocks.insert((name, block)); },
+            ::BindTexture(slot, _, _, _) => { units.insert(slot as i32); },
+            ::Draw(..) | ::DrawIndexed(..) | ::DrawIndexedIndirectCount(..) => {
+                let name = match program {
+                    Some(name) => name,
+                    None => return Err(ValidationError {
+                        command: index,
+                        missing: MissingProgram,
+                    }),
+                };
+                let info = match programs.find(&name) {
+                    Some(info) => info,
+                    None => continue,
+                };
+                let missing = info.attributes.iter()
+                    .find(|a| !attributes.contains(&(a.location as ::AttributeSlot)))
+                    .map(|a| MissingAttribute(a.name.clone()))
+                    .or_else(|| info.uniforms.iter()
+                        .find(|u| !uniforms.contains_key(&(name, u.location)))
+                        .map(|u| MissingUniform(u.name.clone())))
+                    .or_else(|| info.blocks.iter().enumerate()
+                        .find(|&(i, _)| !blocks.contains(&(name, i as ::UniformBlockIndex)))
+                        .map(|(_, b)| MissingBlock(b.name.clone())))
+                    .or_else(|| info.textures.iter()
+                        .find(|t| match uniforms.find(&(name, t.location)) {
+                            Some(&Some(unit)) => !units.contains(&unit),
+                            _ => true,
+                        })
+                        .map(|t| MissingTexture(t.name.clone())));
+                match missing {
+                    Some(m) => return Err(ValidationError {
+                        command: index,
+                        missing: m,
+                    }),
+                    None => (),
+                }
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{validate, ValidationError, MissingBlock, MissingProgram};
+    use shade::{ProgramInfo, BlockVar};
+    use {DrawList, TriangleList};
+    use ListTrait = draw::DrawList;
+
+    #[test]
+    fn test_missing_block() {
+        let mut programs = HashMap::new();
+        programs.insert(1, ProgramInfo {
+            attributes: Vec::new(),
+            uniforms: Vec::new(),
+            blocks: vec![BlockVar {
+                name: "Locals".to_string(),
+                size: 64,
+                usage: 1,
+                members: Vec::new(),
+            }],
+            textures: Vec::new(),
+            images: Vec::new(),
+        });
+        let mut list = DrawList::new();
+        list.call_draw(TriangleList, 0, 3, None);
+        assert_eq!(validate(list.iter(), &programs), Err(ValidationError {
+            command: 0,
+            missing: MissingProgram,
+        }));
+        list.clear();
+        list.bind_program(1);
+        list.call_draw(TriangleList, 0, 3, None);
+        assert_eq!(validate(list.iter(), &programs), Err(ValidationError {
+            command: 1,
+            missing: MissingBlock("Locals".to_string()),
+        }));
+        list.clear();
+        list.bind_program(1);
+        list.bind_uniform_block(1, 0, 0, 5);
+        list.call_draw(TriangleList, 0, 3, None);
+        assert_eq!(validate(list.iter(), &programs), Ok(()));
+    }
+}
@@ -31,6 +31,7 @@ impl<'a> device::GlProvider for Provider<'a> {
 //----------------------------------------
 // Cube associated data
 
+#[repr(C)]
 #[vertex_format]
 struct Vertex {
     #[as_float]
@@ -176,7 +177,7 @@ fn main() {
 
         let buf = backend.create_buffer();
         backend.update_buffer(buf, &index_data, device::UsageStatic);
-        gfx::IndexSlice(buf, gfx::attrib::U8, 0, 36)
+        gfx::IndexSlice(buf, gfx::attrib::U8, 0, 36, 0)
     };
 
     let tinfo = gfx::tex::TextureInfo {
@@ -204,7 +205,7 @@ fn main() {
                 [0.0, 0.0, 1.0, 0.0],
                 [0.0, 0.0, 0.0, 1.0],
             ],
-            t_Color: (texture, Some(sampler)),
+            t_Color: gfx::shade::sampled_texture_param(texture, sampler),
         };
         backend.link_program(data, VERTEX_SRC.clone(), FRAGMENT_SRC.clone())
                .unwrap()
@@ -22,6 +22,7 @@ impl<'a> device::GlProvider for Provider<'a> {
     }
 }
 
+#[repr(C)]
 #[vertex_format]
 struct Vertex {
     pos: [f32, ..2],
@@ -32,14 +32,23 @@ extern crate render;
 pub use render::front;
 pub use render::front::{BackEndHelper, FrontEnd, DrawList};
 pub use render::mesh::{Attribute, Mesh, VertexFormat, Slice, VertexSlice, IndexSlice};
-pub use render::state::{DrawState, BlendAdditive, BlendAlpha};
+pub use render::mesh::{Vec3, Hit, calculate_bounds, describe, merge};
+pub use render::state::{DrawState, BlendAdditive, BlendAlpha, BlendPremultiplied};
 pub use render::shade;
-pub use render::target::{Frame, Plane, PlaneEmpty, PlaneSurface, PlaneTexture};
+pub use render::target::{Frame, FrameKind, DefaultFrame, CustomFrame};
+pub use render::target::{Plane, PlaneEmpty, PlaneSurface, PlaneTexture};
+pub use render::target::{FrameError, ErrorColorFormat, ErrorDepthFormat, ErrorStencilFormat,
+                         ErrorStencilPacked, ErrorClearDepth, ErrorClearStencil,
+                         ErrorClearInteger};
 pub use device::{attrib, state, tex};
 pub use device::{BufferHandle, ShaderHandle, SurfaceHandle, TextureHandle, SurfaceHandle};
-pub use device::{VertexCount, IndexCount};
+pub use device::PendingProgram;
+pub use device::{QueryHandle, ConditionalMode, ConditionalWait, ConditionalNoWait,
+                 ConditionalRegionWait, ConditionalRegionNoWait};
+pub use device::{VertexCount, IndexCount, InstanceCount};
 pub use device::{Point, Line, LineStrip, TriangleList, TriangleStrip, TriangleFan};
-pub use device::{Blob, GlBackEnd, GlProvider, GraphicsContext};
-pub use device::shade::{UniformValue, ValueI32, ValueF32, ValueI32Vec, ValueF32Vec, ValueF32Matrix};
-pub use device::shade::{ShaderSource, StaticBytes};
+pub use device::{Blob, GlBackEnd, GlProvider, GraphicsContext, submit_to};
+pub use device::shade::{UniformValue, ValueI32, ValueF32, ValueI32Vec, ValueF32Vec, ValueF32Vec2,
+                        ValueF32Vec3, ValueF32Matrix, ValueF32MatrixTransposed};
+pub use device::shade::{ShaderSource, StaticBytes, PreprocessError};
 pub use device::target::{Color, ClearData, Layer, Level};
@@ -48,6 +48,28 @@ fn classify(node: &ast::Ty_) -> Result<ParamType, ParamError> {
     }
 }
 
+/// Classify a field, using the `#[block]` or `#[texture]` attribute if there is one,
+/// and the type of the field otherwise
+fn classify_field(field: &ast::StructField) -> Result<ParamType, ParamError> {
+    let annotated = field.node.attrs.iter().filter_map(|attribute| {
+        match attribute.node.value.node {
+            ast::MetaWord(ref word) if word.get() == "block" => {
+                attr::mark_used(attribute);
+                Some(ParamBlock)
+            },
+            ast::MetaWord(ref word) if word.get() == "texture" => {
+                attr::mark_used(attribute);
+                Some(ParamTexture)
+            },
+            _ => None,
+        }
+    }).last();
+    match annotated {
+        Some(param) => Ok(param),
+        None => classify(&field.node.ty.node),
+    }
+}
+
 /// Scan through the field's attributes and find out if it needs to be linked
 /// with the shader.
 /// #[unused] attribute prevents that
@@ -63,6 +85,41 @@ fn is_field_used(field: &ast::StructField) -> bool {
     }).is_none()
 }
 
+/// Check if the field is marked as `#[optional]`, in which case the link
+/// doesn't fail if the program has no matching variable
+fn is_optional(field: &ast::StructField) -> bool {
+    field.node.attrs.iter().any(|attribute| {
+        match attribute.node.value.node {
+            ast::MetaWord(ref word) if word.get() == "optional" => {
+                attr::mark_used(attribute);
+                true
+            },
+            _ => false,
+        }
+    })
+}
+
+/// Find the name of the shader variable the field is linked to,
+/// given by the `#[name = "..."]` attribute. Defaults to the field name.
+fn find_name(cx: &mut ext::base::ExtCtxt, span: codemap::Span, field: &ast::StructField,
+             default: token::InternedString) -> token::InternedString {
+    field.node.attrs.iter().fold(default, |name, attribute| {
+        match attribute.node.value.node {
+            ast::MetaNameValue(ref key, ref value) if key.get() == "name" => {
+                attr::mark_used(attribute);
+                match value.node {
+                    ast::LitStr(ref new_name, _) => new_name.clone(),
+                    _ => {
+                        cx.span_err(span, "Shader variable name must be a string");
+                        name
+                    },
+                }
+            },
+            _ => name,
+        }
+    })
+}
+
 /// Generates the the method body for `gfx::shade::ParamValues::create_link`
 fn method_create(cx: &mut ext::base::ExtCtxt, span: codemap::Span, substr: &generic::Substructure,
                  definition: Gc<ast::StructDef>, link_name: &str) -> Gc<ast::Expr> {
@@ -72,28 +129,23 @@ fn method_create(cx: &mut ext::base::ExtCtxt, span: codemap::Span, substr: &gene
         generic::Struct(ref fields) => {
             let out = definition.fields.iter().zip(fields.iter())
                 .filter(|&(def, _)| is_field_used(def)).map(|(def, f)| {
-                let name = cx.expr_str(span, token::get_ident(f.name.unwrap()));
+                let name = find_name(cx, span, def, token::get_ident(f.name.unwrap()));
+                let name = cx.expr_str(span, name);
                 let input = substr.nonself_args[0];
-                let expr = match classify(&def.node.ty.node) {
+                let (position, error) = match classify_field(def) {
                     //TODO: verify the type match
-                    Ok(ParamUniform) => super::ugh(cx, |cx| quote_expr!(cx,
-                        match $input.val0().iter().position(|u| u.name.as_slice() == $name) {
-                            Some(p) => p as gfx::shade::VarUniform,
-                            None => return Err(gfx::shade::ErrorUniform($name)),
-                        }
-                    )),
-                    Ok(ParamBlock)   => super::ugh(cx, |cx| quote_expr!(cx,
-                        match $input.val1().iter().position(|b| b.name.as_slice() == $name) {
-                            Some(p) => p as gfx::shade::VarBlock,
-                            None => return Err(gfx::shade::ErrorBlock($name)),
-                        }
-                    )),
-                    Ok(ParamTexture) => super::ugh(cx, |cx| quote_expr!(cx,
-                        match $input.val2().iter().position(|t| t.name.as_slice() == $name) {
-                            Some(p) => p as gfx::shade::VarTexture,
-                            None => return Err(gfx::shade::ErrorTexture($name)),
-                        }
-                    )),
+                    Ok(ParamUniform) => super::ugh(cx, |cx| (quote_expr!(cx,
+                        $input.val0().iter().position(|u| u.name.as_slice() == $name)
+                                            .map(|p| p as gfx::shade::VarUniform)
+                    ), quote_expr!(cx, gfx::shade::ErrorUniform($name)))),
+                    Ok(ParamBlock)   => super::ugh(cx, |cx| (quote_expr!(cx,
+                        $input.val1().iter().position(|b| b.name.as_slice() == $name)
+                                            .map(|p| p as gfx::shade::VarBlock)
+                    ), quote_expr!(cx, gfx::shade::ErrorBlock($name)))),
+                    Ok(ParamTexture) => super::ugh(cx, |cx| (quote_expr!(cx,
+                        $input.val2().iter().position(|t| t.name.as_slice() == $name)
+                                            .map(|p| p as gfx::shade::VarTexture)
+                    ), quote_expr!(cx, gfx::shade::ErrorTexture($name)))),
                     Err(_) => {
                         cx.span_err(span, format!(
                             "Invalid uniform: {}",
@@ -106,6 +158,17 @@ fn method_create(cx: &mut ext::base::ExtCtxt, span: codemap::Span, substr: &gene
                             );
                     },
                 };
+                // optional fields keep the `Option`, the others fail the link
+                let expr = if is_optional(def) {
+                    position
+                } else {
+                    super::ugh(cx, |cx| quote_expr!(cx,
+                        match $position {
+                            Some(p) => p,
+                            None => return Err($error),
+                        }
+                    ))
+                };
                 cx.field_imm(f.span, f.name.unwrap(), expr)
             }).collect();
             cx.expr_ok(span, cx.expr_struct_ident(span, link_ident, out))
@@ -127,12 +190,18 @@ fn method_fill(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
             .filter(|&(def, _)| is_field_used(def)).map(|(def, f)| {
                 let out = substr.nonself_args[1];
                 let value_id = f.self_;
-                let var_id = cx.expr_field_access(
+                let link_var = cx.expr_field_access(
                     span,
                     substr.nonself_args[0],
                     f.name.unwrap()
                     );
-                match classify(&def.node.ty.node) {
+                let optional = is_optional(def);
+                let var_id = if optional {
+                    cx.expr_ident(span, cx.ident_of("id"))
+                } else {
+                    link_var
+                };
+                let stmt = match classify_field(def) {
                     Ok(ParamUniform) => super::ugh(cx, |cx| quote_stmt!(cx,
                         $out.uniforms[$var_id as uint] = Some($value_id.to_uniform());
                     )),
@@ -150,6 +219,18 @@ fn method_fill(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                         );
                         cx.stmt_expr(cx.expr_uint(span, 0))
                     },
+                };
+                if optional {
+                    // match $link_var { Some(id) => { $stmt }, _ => () }
+                    let id = cx.ident_of("id");
+                    let arms = vec![
+                        cx.arm(span, vec![cx.pat_some(span, cx.pat_ident(span, id))],
+                               cx.expr_block(cx.block(span, vec![stmt], None))),
+                        cx.arm(span, vec![cx.pat_wild(span)], cx.expr_lit(span, ast::LitNil)),
+                    ];
+                    cx.stmt_expr(cx.expr_match(span, link_var, arms))
+                } else {
+                    stmt
                 }
             }).collect();
             let view = cx.view_use_simple(
@@ -170,10 +251,12 @@ fn method_fill(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
     }
 }
 
-/// A helper function that translates variable type (`i32`, `TextureHandle`, etc)
+/// A helper function that translates field (`i32`, `#[block] MyBuffer`, etc)
 /// into the corresponding shader var id type (`VarUniform`, `VarBlock`, or `VarTexture`)
-fn node_to_var_type(cx: &mut ext::base::ExtCtxt, span: codemap::Span, node: &ast::Ty_) -> Gc<ast::Ty> {
-    let id = match classify(node) {
+fn field_to_var_type(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
+                     field: &ast::StructField) -> Gc<ast::Ty> {
+    let node = &field.node.ty.node;
+    let id = match classify_field(field) {
         Ok(ParamUniform) => "VarUniform",
         Ok(ParamBlock)   => "VarBlock",
         Ok(ParamTexture) => "VarTexture",
@@ -216,14 +299,17 @@ pub fn expand(context: &mut ext::base::ExtCtxt, span: codemap::Span,
             (definition, ast::StructDef {
                 fields: definition.fields.iter()
                     .filter(|&f| is_field_used(f))
-                    .map(|f| codemap::Spanned {
-                        node: ast::StructField_ {
-                            kind: f.node.kind,
-                            id: f.node.id,
-                            ty: node_to_var_type(context, f.span, &f.node.ty.node),
-                            attrs: Vec::new(),
-                        },
-                        span: f.span,
+                    .map(|f| {
+                        let ty = field_to_var_type(context, f.span, f);
+                        codemap::Spanned {
+                            node: ast::StructField_ {
+                                kind: f.node.kind,
+                                id: f.node.id,
+                                ty: if is_optional(f) { context.ty_option(ty) } else { ty },
+                                attrs: Vec::new(),
+                            },
+                            span: f.span,
+                        }
                     }).collect(),
                 ctor_id: None,
                 super_struct: None,
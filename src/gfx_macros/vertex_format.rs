@@ -84,6 +84,38 @@ fn find_modifier(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
     })
 }
 
+/// Scan through the field's attributes and find out if it holds the vertex
+/// position, as marked by the `#[position]` attribute.
+fn is_position(field: &ast::StructField) -> bool {
+    field.node.attrs.iter().any(|attribute| {
+        match attribute.node.value.node {
+            ast::MetaWord(ref word) if word.get() == "position" => {
+                attr::mark_used(attribute);
+                true
+            },
+            _ => false,
+        }
+    })
+}
+
+/// Check if the field is a nested vertex format, that is a path to anything
+/// but a component type, and return the type in that case.
+fn find_nested(field: &ast::StructField) -> Option<Gc<ast::Ty>> {
+    match field.node.ty.node {
+        ast::TyPath(ref p, _, _) => match p.segments.last() {
+            Some(segment) => match segment.identifier.name.as_str() {
+                "f32" | "f64" |
+                "u8" | "u16" | "u32" | "u64" |
+                "i8" | "i16" | "i32" | "i64" |
+                "uint" | "int" => None,
+                _ => Some(field.node.ty),
+            },
+            None => None,
+        },
+        _ => None,
+    }
+}
+
 /// Find a `gfx::attrib::Type` that describes the given type identifier.
 fn decode_type(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                ty_ident: &ast::Ident, modifier: Option<Modifier>) -> Gc<ast::Expr> {
@@ -175,6 +207,24 @@ fn method_body(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                 .map(|(def, &(ident, _))| {
                     let struct_ident = substr.type_ident;
                     let buffer_expr = substr.nonself_args[1];
+                    match find_nested(def) {
+                        // flatten the attributes of the nested format
+                        Some(nested_ty) => return super::ugh(cx, |cx| quote_expr!(cx, {
+                            let offset = unsafe {
+                                &(*(0u as *const $struct_ident)).$ident as *const _ as gfx::attrib::Offset
+                            };
+                            let stride = { use std::mem; mem::size_of::<$struct_ident>() as gfx::attrib::Stride };
+                            let nested: Vec<gfx::Attribute> =
+                                gfx::VertexFormat::generate(None::<$nested_ty>, $buffer_expr);
+                            for at in nested.move_iter() {
+                                let mut at = at;
+                                at.offset += offset;
+                                at.stride = stride;
+                                attributes.push(at);
+                            }
+                        })),
+                        None => (),
+                    }
                     let (count_expr, type_expr) = decode_count_and_type(cx, span, def);
                     let ident_str = token::get_ident(ident);
                     let ident_str = ident_str.get();
@@ -187,6 +237,7 @@ fn method_body(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                                 &(*(0u as *const $struct_ident)).$ident as *const _ as gfx::attrib::Offset
                             },
                             stride: { use std::mem; mem::size_of::<$struct_ident>() as gfx::attrib::Stride },
+                            instance_rate: 0,
                             name: $ident_str.to_string(),
                         });
                     }))
@@ -206,11 +257,80 @@ fn method_body(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
     }
 }
 
+/// Generates the method body for `gfx::VertexFormat::get_position`.
+fn method_position(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
+                   substr: &generic::Substructure, definition: Gc<ast::StructDef>)
+                   -> Gc<ast::Expr> {
+    match *substr.fields {
+        generic::Struct(ref fields) => {
+            let mut positions = definition.fields.iter().zip(fields.iter())
+                .filter(|&(def, _)| is_position(def));
+            match positions.next() {
+                Some((def, f)) => {
+                    if positions.next().is_some() {
+                        cx.span_err(span, "Only one field can be marked as `#[position]`");
+                    }
+                    let value = f.self_;
+                    match def.node.ty.node {
+                        ast::TyFixedLengthVec(_, _) => super::ugh(cx, |cx| quote_expr!(cx, {
+                            let mut position = [0f32, ..3];
+                            for (p, v) in position.mut_iter().zip($value.iter()) {
+                                *p = *v as f32;
+                            }
+                            Some((position[0], position[1], position[2]))
+                        })),
+                        _ => {
+                            cx.span_err(def.span, "The `#[position]` field has to be \
+                                                  a fixed-size vector");
+                            cx.expr_lit(span, ast::LitNil)
+                        },
+                    }
+                },
+                None => quote_expr!(cx, None),
+            }
+        },
+        _ => {
+            cx.span_err(span, "Unable to implement `gfx::VertexFormat::get_position` \
+                              on a non-structure");
+            cx.expr_lit(span, ast::LitNil)
+        }
+    }
+}
+
+/// Check that the struct has a defined layout, given by either `#[repr(C)]` or
+/// `#[packed]`, since the offsets are computed from the field addresses and
+/// sent to the device as such.
+fn has_defined_layout(item: &ast::Item) -> bool {
+    item.attrs.iter().any(|attribute| {
+        match attribute.node.value.node {
+            ast::MetaWord(ref word) => word.get() == "packed",
+            ast::MetaList(ref word, ref items) if word.get() == "repr" =>
+                items.iter().any(|it| match it.node {
+                    ast::MetaWord(ref repr) => repr.get() == "C",
+                    _ => false,
+                }),
+            _ => false,
+        }
+    })
+}
 
 /// Derive a `gfx::VertexFormat` implementation for the `struct`
 pub fn expand(context: &mut ext::base::ExtCtxt, span: codemap::Span,
               meta_item: Gc<ast::MetaItem>, item: Gc<ast::Item>,
               push: |Gc<ast::Item>|) {
+    let definition = match item.node {
+        ast::ItemStruct(definition, _) => definition,
+        _ => {
+            context.span_err(span, "Only free-standing named structs allowed \
+                                   to derive VertexFormat");
+            return;
+        }
+    };
+    if !has_defined_layout(&*item) {
+        context.span_err(span, "A vertex format needs a defined layout, \
+                               add `#[repr(C)]` or `#[packed]` to the struct");
+        return;
+    }
     // `impl gfx::VertexFormat for $item`
     generic::TraitDef {
         span: span,
@@ -255,6 +375,33 @@ pub fn expand(context: &mut ext::base::ExtCtxt, span: codemap::Span,
                 // generate the method body
                 combine_substructure: generic::combine_substructure(method_body),
             },
+            // `fn get_position(&self) -> Option<(f32, f32, f32)>`
+            generic::MethodDef {
+                name: "get_position",
+                generics: generic::ty::LifetimeBounds::empty(),
+                explicit_self: Some(Some(generic::ty::Borrowed(
+                    None, ast::MutImmutable
+                ))),
+                args: Vec::new(),
+                ret_ty: generic::ty::Literal(
+                    generic::ty::Path {
+                        path: vec!["Option"],
+                        lifetime: None,
+                        params: vec![
+                            box generic::ty::Tuple(vec![
+                                generic::ty::Literal(generic::ty::Path::new_local("f32")),
+                                generic::ty::Literal(generic::ty::Path::new_local("f32")),
+                                generic::ty::Literal(generic::ty::Path::new_local("f32")),
+                            ]),
+                        ],
+                        global: false,
+                    },
+                ),
+                attributes: Vec::new(),
+                combine_substructure: generic::combine_substructure(|cx, span, sub|
+                    method_position(cx, span, sub, definition)
+                ),
+            },
         ],
     }.expand(context, meta_item, item, push);
 }
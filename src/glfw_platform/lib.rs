@@ -66,6 +66,11 @@ impl<'a> device::GlProvider for Wrap<'a> {
         let Wrap(provider) = *self;
         provider.get_proc_address(name)
     }
+
+    fn is_extension_supported(&self, extension: &str) -> bool {
+        let Wrap(provider) = *self;
+        provider.extension_supported(extension)
+    }
 }
 
 pub struct Platform<C> {
@@ -0,0 +1,125 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shared setup of the module tests, recording draw lists for the null
+//! back-end.
+
+use std::cell::Cell;
+use device;
+use a = device::attrib;
+use s = device::shade;
+use device::null::NullBackEnd;
+use front::{BackEndHelper, DrawList, FrontEnd};
+use mesh::{Attribute, Mesh, VertexFormat};
+use shade::{CustomShell, NamedCell, ParamDictionary};
+use state::DrawState;
+
+/// A vertex with the attributes of the programs linked by the null back-end.
+#[repr(C)]
+#[deriving(Clone)]
+pub struct Vertex {
+    pub pos: [f32, ..2],
+    pub color: [f32, ..3],
+}
+
+impl VertexFormat for Vertex {
+    fn generate(_: Option<Vertex>, buffer: device::BufferHandle) -> Vec<Attribute> {
+        let attribute = |name: &str, count, offset| Attribute {
+            buffer: buffer,
+            elem_count: count,
+            elem_type: a::Float(a::FloatDefault, a::F32),
+            offset: offset,
+            stride: 20,
+            instance_rate: 0,
+            name: name.to_string(),
+        };
+        vec![attribute("pos", 2, 0), attribute("color", 3, 8)]
+    }
+}
+
+/// Sources of a program doing nothing.
+pub static SOURCE: s::ShaderSource = s::ShaderSource {
+    glsl_120: None,
+    glsl_150: Some(s::StaticBytes(b"#version 150 core\nvoid main() {}")),
+};
+
+/// The vertices of a triangle with a red, a green and a blue corner.
+pub fn triangle() -> Vec<Vertex> {
+    vec![
+        Vertex { pos: [ -0.5, -0.5 ], color: [1.0, 0.0, 0.0] },
+        Vertex { pos: [ 0.5, -0.5 ], color: [0.0, 1.0, 0.0] },
+        Vertex { pos: [ 0.0, 0.5 ], color: [0.0, 0.0, 1.0] }
+    ]
+}
+
+/// A sampler of a 2D texture declared by a program.
+pub fn sampler(name: &str, location: s::Location) -> s::SamplerVar {
+    s::SamplerVar {
+        name: name.to_string(),
+        location: location,
+        base_type: s::BaseF32,
+        sampler_type: s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect),
+    }
+}
+
+/// A single float uniform declared by a program.
+pub fn uniform(name: &str, location: s::Location, container: s::ContainerType)
+               -> s::UniformVar {
+    s::UniformVar {
+        name: name.to_string(),
+        location: location,
+        count: 1,
+        base_type: s::BaseF32,
+        container: container,
+    }
+}
+
+/// A dictionary providing the texture to the `tex` sampler.
+pub fn texture_dict(texture: device::TextureHandle) -> ParamDictionary {
+    ParamDictionary {
+        uniforms: Vec::new(),
+        blocks: Vec::new(),
+        textures: vec![NamedCell {
+            name: "tex".to_string(),
+            value: Cell::new((texture, None)),
+        }],
+    }
+}
+
+/// Create a 64x64 front-end, a triangle mesh and a program without
+/// parameters, the common setup of the draw tests.
+pub fn setup(backend: &mut NullBackEnd) -> (FrontEnd, Mesh, CustomShell<(), ()>) {
+    let frontend = backend.create_frontend(64, 64).unwrap();
+    let mesh = backend.create_mesh(triangle());
+    let program = backend.link_program((), SOURCE.clone(), SOURCE.clone()).unwrap();
+    (frontend, mesh, program)
+}
+
+/// Draw a triangle into the main frame with the given state, and return the list.
+pub fn draw_triangle(backend: &mut NullBackEnd, state: &DrawState) -> DrawList {
+    let (frontend, mesh, program) = setup(backend);
+    let mut list = frontend.create_drawlist();
+    list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, state).unwrap();
+    list
+}
+
+/// Collect what `f` extracts from the commands of the list, in order.
+pub fn commands<T>(list: &DrawList, f: |&device::Command| -> Option<T>) -> Vec<T> {
+    list.as_slice().iter().filter_map(|com| f(com)).collect()
+}
+
+/// Check if any command of the list matches `f`.
+pub fn has_command(list: &DrawList, f: |&device::Command| -> bool) -> bool {
+    list.as_slice().iter().any(|com| f(com))
+}
@@ -17,7 +17,8 @@
 use device;
 use backend = device::back;
 use device::draw::DrawList;
-use device::shade::{ProgramInfo, ShaderSource, Vertex, Fragment, CreateShaderError};
+use device::shade::{ProgramInfo, ShaderSource, Vertex, Fragment, CreateShaderError,
+                    PreprocessError};
 use mesh;
 use shade;
 use shade::{ProgramShell, ShaderParam};
@@ -45,6 +46,9 @@ pub enum MeshError {
     ErrorAttributeMissing,
     /// An attribute's type from the vertex format differed from the type used in the shader.
     ErrorAttributeType,
+    /// An attribute's component count from the vertex format differed from
+    /// the one used in the shader, only reported in the strict mode.
+    ErrorAttributeCount,
 }
 
 /// An error that can happen when trying to draw.
@@ -58,6 +62,10 @@ pub enum DrawError {
     ErrorMesh(MeshError),
     /// Error with the mesh slice
     ErrorSlice,
+    /// Error with the frame planes.
+    ErrorFrame(target::FrameError),
+    /// The draw state is inconsistent, e.g. both blending and a logic operation are enabled.
+    ErrorState,
 }
 
 /// Manager initialization error
@@ -81,11 +89,36 @@ pub enum ProgramError<'a> {
     ErrorLink(()),
     /// Unable to connect parameters
     ErrorParameters(shade::ParameterLinkError<'a>),
+    /// Unable to preprocess the sources
+    ErrorPreprocess(PreprocessError),
+    /// The program uses more samplers (first) than the device has texture
+    /// units (second), so some textures could never be bound
+    ErrorTextureUnits(uint, uint),
+    /// The named uniform is declared more than once, with different types
+    ErrorUniform(String),
+}
+
+/// Check that every sampler of a freshly linked program can get its own
+/// texture unit. Drivers accept such programs, but the draw calls fail.
+pub fn check_texture_units(caps: &device::Capabilities, prog: &device::ProgramHandle)
+                           -> Result<(), ProgramError<'static>> {
+    let textures = prog.get_info().textures.as_slice();
+    let units = caps.get_max_texture_units();
+    if textures.len() > units {
+        error!("Program {} uses {} samplers, but the device only has {} texture units \
+                (the first one left out is '{}')", prog.get_name(), textures.len(), units,
+               textures[units].name);
+        return Err(ErrorTextureUnits(textures.len(), units))
+    }
+    Ok(())
 }
 
 /// Graphics state
 struct State {
-    frame: target::Frame,
+    /// Planes attached to the common frame buffer, unknown at the start of the
+    /// list since other lists may attach their own in between
+    frame: Option<target::Frame>,
+    /// State used by `draw_default`, taken from the front-end
     draw_state: state::DrawState,
 }
 
@@ -101,18 +134,33 @@ pub struct FrontEnd {
 impl FrontEnd {
     /// Create a new draw list
     pub fn create_drawlist(&self) -> DrawList {
+        self.create_drawlist_with_capacity(0)
+    }
+
+    /// Create a draw list with room for `capacity` device commands, each draw
+    /// call taking a few dozens of them. Meant to be created once and `reset`
+    /// every frame, which keeps the storage.
+    pub fn create_drawlist_with_capacity(&self, capacity: uint) -> DrawList {
         DrawList {
-            list: device::DrawList::new(),
+            list: device::DrawList::with_capacity(capacity),
             common_array_buffer: self.common_array_buffer,
             common_frame_buffer: self.common_frame_buffer,
             default_frame_buffer: self.default_frame_buffer,
             state: State {
-                frame: self.main_frame,
-                draw_state: self.default_state,
+                frame: None,
+                draw_state: self.default_state.clone(),
             },
+            validation: false,
+            strict_attributes: false,
         }
     }
 
+    /// Set the draw state used by `draw_default` in the lists created from now
+    /// on. The lists created before keep the previous one.
+    pub fn set_default_state(&mut self, state: state::DrawState) {
+        self.default_state = state;
+    }
+
     /// Return a reference to the main frame buffer
     pub fn get_main_frame(&self) -> &target::Frame {
         &self.main_frame
@@ -139,10 +187,40 @@ pub trait BackEndHelper {
     fn create_frontend(&mut self, width: u16, height: u16) -> Result<FrontEnd, InitError>;
     /// Create a new mesh from the given vertex data.
     /// Convenience function around `create_buffer` and `Mesh::from`.
+    /// The mesh bounds are computed if the format has a `#[position]` field.
     fn create_mesh<T: mesh::VertexFormat + Send>(&mut self, data: Vec<T>) -> mesh::Mesh;
+    /// Create a new mesh like `create_mesh`, also keeping the data for reading
+    /// it back with `Mesh::cpu_data`, at the cost of the memory it takes.
+    fn create_mesh_retained<T: mesh::VertexFormat + Send + Sync>(&mut self, data: Vec<T>)
+                            -> mesh::Mesh;
+    /// Create a new mesh from a borrowed slice of vertex data.
+    /// The data is copied into the buffer, so the caller keeps ownership of it.
+    fn create_mesh_ref<T: mesh::VertexFormat>(&mut self, data: &[T]) -> mesh::Mesh;
+    /// Create a new mesh from the given vertex data and 16-bit indices, whose
+    /// default slice goes through the index buffer.
+    fn create_indexed_mesh<T: mesh::VertexFormat + Send>(&mut self, vertices: Vec<T>,
+                                                          indices: Vec<u16>) -> mesh::Mesh;
+    /// Create a new mesh from the per-vertex data, with the attributes of the
+    /// per-instance data in a second buffer, for use with `draw_instanced`.
+    fn create_mesh_instanced<V: mesh::VertexFormat + Send, I: mesh::VertexFormat + Send>(
+                             &mut self, vertices: Vec<V>, instances: Vec<I>) -> mesh::Mesh;
+    /// Create a single mesh out of the vertex data of several parts, and
+    /// return it with the slice to draw each part. See `mesh::merge`.
+    fn create_merged_mesh<T: mesh::VertexFormat + Clone>(&mut self, parts: &[&[T]])
+                          -> (mesh::Mesh, Vec<mesh::Slice>);
+    /// Compile a vertex and a fragment shader and link them into a program,
+    /// without connecting its parameters. The shaders are deleted once the
+    /// program is linked.
+    fn link_program_source(&mut self, vs_src: ShaderSource, fs_src: ShaderSource)
+                           -> Result<device::ProgramHandle, ProgramError<'static>>;
     /// Create a simple program given a vertex shader with a fragment one.
     fn link_program<'a, L, T: ShaderParam<L>>(&mut self, data: T, vs_src: ShaderSource,
                    fs_src: ShaderSource) -> Result<shade::CustomShell<L, T>, ProgramError>;
+    /// Compile new sources for a program and link it again in place, so that
+    /// the handle and the shells using it stay valid. Fails if the uniforms,
+    /// blocks, textures or attributes have changed.
+    fn reload_program(&mut self, prog: &device::ProgramHandle, vs_src: ShaderSource,
+                      fs_src: ShaderSource) -> Result<(), ProgramError<'static>>;
 }
 
 impl<D, B: device::ApiBackEnd<D>> BackEndHelper for B {
@@ -160,6 +238,17 @@ impl<D, B: device::ApiBackEnd<D>> BackEndHelper for B {
     }
 
     fn create_mesh<T: mesh::VertexFormat + Send>(&mut self, data: Vec<T>) -> mesh::Mesh {
+        self.create_mesh_ref(data.as_slice())
+    }
+
+    fn create_mesh_retained<T: mesh::VertexFormat + Send + Sync>(&mut self, data: Vec<T>)
+                            -> mesh::Mesh {
+        let mut mesh = self.create_mesh_ref(data.as_slice());
+        mesh.keep_cpu_data(data);
+        mesh
+    }
+
+    fn create_mesh_ref<T: mesh::VertexFormat>(&mut self, data: &[T]) -> mesh::Mesh {
         let nv = data.len();
         debug_assert!(nv < {
             use std::num::Bounded;
@@ -168,42 +257,151 @@ impl<D, B: device::ApiBackEnd<D>> BackEndHelper for B {
         });
         let buf = self.create_buffer();
         self.update_buffer(buf, &data, device::UsageStatic);
-        mesh::Mesh::from::<T>(buf, nv as device::VertexCount)
+        let mut mesh = mesh::Mesh::from::<T>(buf, nv as device::VertexCount);
+        mesh.compute_bounds(data);
+        mesh
     }
 
-    fn link_program<'a, L, T: ShaderParam<L>>(&mut self, data: T,
-                   vs_src: ShaderSource, fs_src: ShaderSource)
-                   -> Result<shade::CustomShell<L, T>, ProgramError> {
-        //TODO: integrate connect_program here
+    fn create_indexed_mesh<T: mesh::VertexFormat + Send>(&mut self, vertices: Vec<T>,
+                                                          indices: Vec<u16>) -> mesh::Mesh {
+        let mut mesh = self.create_mesh(vertices);
+        let buf = self.create_buffer();
+        self.update_buffer(buf, &indices, device::UsageStatic);
+        mesh.indices = Some((buf, device::attrib::U16, indices.len() as device::IndexCount));
+        mesh
+    }
+
+    fn create_mesh_instanced<V: mesh::VertexFormat + Send, I: mesh::VertexFormat + Send>(
+                             &mut self, vertices: Vec<V>, instances: Vec<I>) -> mesh::Mesh {
+        let mut mesh = self.create_mesh(vertices);
+        let buf = self.create_buffer();
+        self.update_buffer(buf, &instances, device::UsageStatic);
+        mesh.add_instanced::<I>(buf);
+        mesh
+    }
+
+    fn create_merged_mesh<T: mesh::VertexFormat + Clone>(&mut self, parts: &[&[T]])
+                          -> (mesh::Mesh, Vec<mesh::Slice>) {
+        let (data, slices) = mesh::merge(parts);
+        (self.create_mesh_ref(data.as_slice()), slices)
+    }
+
+    fn link_program_source(&mut self, vs_src: ShaderSource, fs_src: ShaderSource)
+                           -> Result<device::ProgramHandle, ProgramError<'static>> {
         let vs = match self.create_shader(Vertex, vs_src) {
             Ok(s) => s,
             Err(e) => return Err(ErrorVertex(e)),
         };
         let fs = match self.create_shader(Fragment, fs_src) {
             Ok(s) => s,
-            Err(e) => return Err(ErrorFragment(e)),
+            Err(e) => {
+                self.delete_shader(vs);
+                return Err(ErrorFragment(e))
+            },
         };
-        let prog = match self.create_program([vs, fs]) {
+        let prog = self.create_program([vs, fs]);
+        // the program keeps what it needs from the shaders
+        self.delete_shader(vs);
+        self.delete_shader(fs);
+        let prog = match prog {
             Ok(p) => p,
             Err(e) => return Err(ErrorLink(e)),
         };
+        match check_texture_units(self.get_capabilities(), &prog) {
+            Ok(()) => (),
+            Err(e) => {
+                self.delete_program(prog);
+                return Err(e)
+            },
+        }
+        let duplicate = prog.get_info().find_duplicate_uniform().map(|name| name.to_string());
+        match duplicate {
+            Some(name) => {
+                error!("Uniform {} is declared more than once in program {}", name,
+                       prog.get_name());
+                self.delete_program(prog);
+                return Err(ErrorUniform(name))
+            },
+            None => (),
+        }
+        Ok(prog)
+    }
+
+    fn link_program<'a, L, T: ShaderParam<L>>(&mut self, data: T,
+                   vs_src: ShaderSource, fs_src: ShaderSource)
+                   -> Result<shade::CustomShell<L, T>, ProgramError> {
+        //TODO: integrate connect_program here
+        let prog = try!(self.link_program_source(vs_src, fs_src));
         FrontEnd::connect_program(prog, data).map_err(|e| ErrorParameters(e))
     }
+
+    fn reload_program(&mut self, prog: &device::ProgramHandle, vs_src: ShaderSource,
+                      fs_src: ShaderSource) -> Result<(), ProgramError<'static>> {
+        let vs = match self.create_shader(Vertex, vs_src) {
+            Ok(s) => s,
+            Err(e) => return Err(ErrorVertex(e)),
+        };
+        let fs = match self.create_shader(Fragment, fs_src) {
+            Ok(s) => s,
+            Err(e) => {
+                self.delete_shader(vs);
+                return Err(ErrorFragment(e))
+            },
+        };
+        let result = self.relink_program(prog, [vs, fs]);
+        self.delete_shader(vs);
+        self.delete_shader(fs);
+        result.map_err(|e| ErrorLink(e))
+    }
 }
 
 /// Renderer front-end
+///
+/// The commands are recorded into a `device::DrawList`, so the list can be
+/// submitted to any back-end implementing `ApiBackEnd<device::DrawList>`.
 pub struct DrawList {
     list: device::DrawList,
     common_array_buffer: backend::ArrayBuffer,
     common_frame_buffer: backend::FrameBuffer,
     default_frame_buffer: backend::FrameBuffer,
     state: State,
+    validation: bool,
+    strict_attributes: bool,
 }
 
 impl DrawList {
     /// Reset all commands for draw list re-usal.
     pub fn reset(&mut self) {
         self.list.clear();
+        self.state.frame = None;
+    }
+
+    /// Enable or disable checking that the slices passed to `draw` stay
+    /// within the mesh, failing with `ErrorSlice` otherwise. Only the vertex
+    /// range and the range of the mesh's own index buffer are checked, the
+    /// front-end knowing neither the sizes of the other index buffers nor the
+    /// values of the indices. Disabled by default.
+    pub fn set_validation(&mut self, enable: bool) {
+        self.validation = enable;
+    }
+
+    /// Enable or disable the strict mode, where an attribute with a different
+    /// component count in the vertex format and in the shader fails the draw
+    /// call with `ErrorAttributeCount`, instead of only giving a warning.
+    pub fn set_strict_attributes(&mut self, enable: bool) {
+        self.strict_attributes = enable;
+    }
+
+    /// Append the commands of another draw list, to be submitted together
+    /// with this one. Both lists must come from the same front-end.
+    pub fn append(&mut self, other: DrawList) {
+        let DrawList { list, state, common_frame_buffer, .. } = other;
+        assert!(common_frame_buffer == self.common_frame_buffer,
+                "Appended a draw list of another front-end");
+        self.list.append(list);
+        if state.frame.is_some() {
+            self.state.frame = state.frame;
+        }
     }
 
     /// Get the draw list to be submitted.
@@ -217,10 +415,95 @@ impl DrawList {
         self.list.call_clear(data);
     }
 
+    /// Clear the `Frame` like `clear`, but fail if the depth or the stencil is
+    /// cleared while the frame has no such buffer.
+    pub fn try_clear(&mut self, data: device::target::ClearData, frame: &target::Frame)
+                     -> Result<(), DrawError> {
+        if data.depth.is_some() && !frame.has_depth() {
+            return Err(ErrorFrame(target::ErrorClearDepth))
+        }
+        if data.stencil.is_some() && !frame.has_stencil() {
+            return Err(ErrorFrame(target::ErrorClearStencil))
+        }
+        self.clear(data, frame);
+        Ok(())
+    }
+
+    /// Clear a color target of the `Frame` with a signed integer format, which
+    /// `clear` can't do. Fails if the target has no such plane.
+    pub fn clear_color_int(&mut self, index: device::target::ColorIndex, color: [i32, ..4],
+                           frame: &target::Frame) -> Result<(), DrawError> {
+        if !frame.get_color_format(index).map_or(false, |f| f.is_signed_integer()) {
+            return Err(ErrorFrame(target::ErrorClearInteger(index)))
+        }
+        self.bind_frame(frame);
+        self.list.call_clear_color_int(index, color);
+        Ok(())
+    }
+
+    /// Clear a color target of the `Frame` with an unsigned integer format.
+    /// Fails if the target has no such plane.
+    pub fn clear_color_uint(&mut self, index: device::target::ColorIndex, color: [u32, ..4],
+                            frame: &target::Frame) -> Result<(), DrawError> {
+        if !frame.get_color_format(index).map_or(false, |f| f.is_unsigned_integer()) {
+            return Err(ErrorFrame(target::ErrorClearInteger(index)))
+        }
+        self.bind_frame(frame);
+        self.list.call_clear_color_uint(index, color);
+        Ok(())
+    }
+
+    /// Clear the whole `Frame`: color to the given value, depth to 1.0 and
+    /// stencil to 0, when the frame has these buffers.
+    pub fn clear_all(&mut self, color: device::target::Color, frame: &target::Frame) {
+        self.clear(device::target::ClearData {
+            color: Some(color),
+            depth: if frame.has_depth() { Some(1.0) } else { None },
+            stencil: if frame.has_stencil() { Some(0) } else { None },
+        }, frame);
+    }
+
     /// Draw `slice` of `mesh` into `frame`, using a program shell, and a given draw state.
     pub fn draw<P: ProgramShell>(&mut self, mesh: &mesh::Mesh, slice: mesh::Slice,
                                 frame: &target::Frame, prog_shell: &P, state: &state::DrawState)
                                 -> Result<(), DrawError> {
+        self.draw_impl(mesh, slice, None, frame, prog_shell, state)
+    }
+
+    /// Draw like `draw`, using the default draw state of the front-end the
+    /// list was created from.
+    pub fn draw_default<P: ProgramShell>(&mut self, mesh: &mesh::Mesh, slice: mesh::Slice,
+                                         frame: &target::Frame, prog_shell: &P)
+                                         -> Result<(), DrawError> {
+        let state = self.state.draw_state.clone();
+        self.draw_impl(mesh, slice, None, frame, prog_shell, &state)
+    }
+
+    /// Draw `instances` copies of `slice`, advancing the per-instance
+    /// attributes of `mesh` once for each copy.
+    pub fn draw_instanced<P: ProgramShell>(&mut self, mesh: &mesh::Mesh, slice: mesh::Slice,
+                                           instances: device::InstanceCount,
+                                           frame: &target::Frame, prog_shell: &P,
+                                           state: &state::DrawState)
+                                           -> Result<(), DrawError> {
+        self.draw_impl(mesh, slice, Some(instances), frame, prog_shell, state)
+    }
+
+    fn draw_impl<P: ProgramShell>(&mut self, mesh: &mesh::Mesh, slice: mesh::Slice,
+                                  instances: Option<device::InstanceCount>,
+                                  frame: &target::Frame, prog_shell: &P,
+                                  state: &state::DrawState) -> Result<(), DrawError> {
+        if state.logic_op.is_some() && (state.blend.is_some() ||
+                state.blend_targets.iter().any(|&(_, b)| b.is_some())) {
+            return Err(ErrorState)
+        }
+        if self.validation && !slice.is_within(mesh) {
+            return Err(ErrorSlice)
+        }
+        match frame.validate() {
+            Ok(_) => (),
+            Err(e) => return Err(ErrorFrame(e)),
+        }
         self.bind_frame(frame);
         match self.bind_shell(prog_shell) {
             Ok(_) => (),
@@ -231,8 +514,15 @@ impl DrawList {
         self.list.set_scissor(state.scissor);
         self.list.set_depth_stencil(state.depth, state.stencil,
             state.primitive.get_cull_mode());
+        self.list.set_depth_range(state.depth_range);
+        self.list.set_depth_clamp(state.depth_clamp);
+        self.list.set_clip_planes(state.clip_planes);
         self.list.set_blend(state.blend);
+        for &(index, blend) in state.blend_targets.iter() {
+            self.list.set_blend_target(index, blend);
+        }
         self.list.set_color_mask(state.color_mask);
+        self.list.set_logic_op(state.logic_op);
         // bind mesh data
         match self.bind_mesh(mesh, prog_shell.get_program().get_info()) {
             Ok(_) => (),
@@ -241,16 +531,62 @@ impl DrawList {
         // draw
         match slice {
             mesh::VertexSlice(start, end) => {
-                self.list.call_draw(mesh.prim_type, start, end);
+                self.list.call_draw(mesh.prim_type, start, end, instances);
             },
-            mesh::IndexSlice(buf, index, start, end) => {
+            mesh::IndexSlice(buf, index, start, end, base) => {
                 self.list.bind_index(buf.get_name());
-                self.list.call_draw_indexed(mesh.prim_type, index, start, end);
+                self.list.call_draw_indexed(mesh.prim_type, index, start, end, base, instances);
             },
         }
         Ok(())
     }
 
+    /// Draw like `draw`, but with the named uniforms of the program set to the
+    /// given values for this draw call only. Fails if the program has no
+    /// uniform with one of the names.
+    pub fn draw_with<P: ProgramShell>(&mut self, mesh: &mesh::Mesh, slice: mesh::Slice,
+                                      frame: &target::Frame, prog_shell: &P,
+                                      state: &state::DrawState,
+                                      values: &[(&str, device::shade::UniformValue)])
+                                      -> Result<(), DrawError> {
+        let info = prog_shell.get_program().get_info();
+        for &(name, _) in values.iter() {
+            if !info.uniforms.iter().any(|u| u.name.as_slice() == name) {
+                return Err(ErrorShell(ErrorShellUniform(name.to_string())))
+            }
+        }
+        self.draw(mesh, slice, frame, &shade::UniformOverride::new(prog_shell, values), state)
+    }
+
+    /// Count the samples of the following draw calls that pass the depth and
+    /// stencil tests, until `end_occlusion_query`.
+    pub fn begin_occlusion_query(&mut self, query: &device::QueryHandle) {
+        self.list.begin_occlusion_query(query.get_name());
+    }
+
+    /// Stop counting the samples of the current occlusion query.
+    pub fn end_occlusion_query(&mut self) {
+        self.list.end_occlusion_query();
+    }
+
+    /// Record the time at which the previous commands complete into the
+    /// query, to be read with `get_timestamp` on the back-end.
+    pub fn query_timestamp(&mut self, query: &device::QueryHandle) {
+        self.list.query_timestamp(query.get_name());
+    }
+
+    /// Skip the following draw calls, until `end_conditional`, if the given
+    /// occlusion query got no samples.
+    pub fn begin_conditional(&mut self, query: &device::QueryHandle,
+                             mode: device::ConditionalMode) {
+        self.list.begin_conditional(query.get_name(), mode);
+    }
+
+    /// End the current conditional block.
+    pub fn end_conditional(&mut self) {
+        self.list.end_conditional();
+    }
+
     /// Update a buffer with data from a vector.
     pub fn update_buffer_vec<T: Send>(&mut self, buf: device::BufferHandle, data: Vec<T>) {
         self.list.update_buffer(buf.get_name(), (box data) as Box<device::Blob + Send>);
@@ -268,14 +604,32 @@ impl DrawList {
                                  (box data) as Box<device::Blob + Send>);
     }
 
+    /// Bind a texture with an optional sampler to the given texture unit.
+    /// Textures coming from a program shell are assigned to the units `0 .. n`
+    /// on every draw, so explicitly bound textures should use the units above
+    /// in order to stay bound across draw calls.
+    pub fn bind_texture(&mut self, unit: device::TextureSlot, tex: &device::TextureHandle,
+                        sampler: Option<device::SamplerHandle>) {
+        self.list.bind_texture(unit, tex.get_info().kind, tex.get_name(), sampler);
+    }
+
+    /// Bind a level of the texture as an image for load/store access in shaders.
+    /// The binding is not managed by the program shell, so image variables have
+    /// to be given a matching unit with a `layout(binding = ...)` qualifier.
+    pub fn bind_image(&mut self, unit: device::ImageSlot, tex: &device::TextureHandle,
+                      level: device::target::Level, access: device::tex::ImageAccess,
+                      format: device::tex::Format) {
+        self.list.bind_image(unit, tex.get_name(), level, access, format);
+    }
+
     fn bind_target(list: &mut device::DrawList, to: device::target::Target, plane: target::Plane) {
         match plane {
             target::PlaneEmpty =>
                 list.unbind_target(to),
-            target::PlaneSurface(suf) =>
-                list.bind_target_surface(to, suf),
-            target::PlaneTexture(tex, level, layer) =>
-                list.bind_target_texture(to, tex, level, layer),
+            target::PlaneSurface(ref suf) =>
+                list.bind_target_surface(to, suf.get_name()),
+            target::PlaneTexture(ref tex, level, layer) =>
+                list.bind_target_texture(to, tex.get_name(), level, layer),
         }
     }
 
@@ -291,18 +645,28 @@ impl DrawList {
             self.list.bind_frame_buffer(self.default_frame_buffer);
         } else {
             self.list.bind_frame_buffer(self.common_frame_buffer);
-            for (i, (cur, new)) in self.state.frame.colors.iter().zip(frame.colors.iter()).enumerate() {
-                if *cur != *new {
+            let cur = self.state.frame;
+            for (i, new) in frame.colors.iter().enumerate() {
+                if cur.map(|f| f.colors[i]) != Some(*new) {
                     DrawList::bind_target(&mut self.list, device::target::TargetColor(i as u8), *new);
                 }
             }
-            if self.state.frame.depth != frame.depth {
-                DrawList::bind_target(&mut self.list, device::target::TargetDepth, frame.depth);
-            }
-            if self.state.frame.stencil != frame.stencil {
+            let depth_changed = cur.map(|f| f.depth) != Some(frame.depth);
+            // the stencil goes first, since a packed depth plane overrides it,
+            // and it has to be restored when the packed plane goes away
+            if cur.map(|f| f.stencil) != Some(frame.stencil) ||
+                    (depth_changed && cur.map_or(false, |f| f.depth.is_packed())) {
                 DrawList::bind_target(&mut self.list, device::target::TargetStencil, frame.stencil);
             }
-            self.state.frame = *frame;
+            if depth_changed {
+                let to = if frame.depth.is_packed() {
+                    device::target::TargetDepthStencil
+                } else {
+                    device::target::TargetDepth
+                };
+                DrawList::bind_target(&mut self.list, to, frame.depth);
+            }
+            self.state.frame = Some(*frame);
         }
     }
 
@@ -311,15 +675,9 @@ impl DrawList {
         self.list.bind_program(prog.get_name());
         let pinfo = prog.get_info();
         // gather parameters
-        // this is a bit ugly, need to re-think the interface with `#[shader_program]`
-        let mut uniforms = Vec::from_elem(pinfo.uniforms.len(), None);
-        let mut blocks   = Vec::from_elem(pinfo.blocks  .len(), None);
-        let mut textures = Vec::from_elem(pinfo.textures.len(), None);
-        shell.fill_params(shade::ParamValues {
-            uniforms: uniforms.as_mut_slice(),
-            blocks: blocks.as_mut_slice(),
-            textures: textures.as_mut_slice(),
-        });
+        let mut storage = shade::ParamStorage::new(pinfo);
+        shell.fill_params(storage.as_mut_values());
+        let shade::ParamStorage { uniforms, blocks, textures } = storage;
         // bind uniforms
         for (var, option) in pinfo.uniforms.iter().zip(uniforms.move_iter()) {
             match option {
@@ -360,10 +718,26 @@ impl DrawList {
             match mesh.attributes.iter().find(|a| a.name.as_slice() == sat.name.as_slice()) {
                 Some(vat) => match vat.elem_type.is_compatible(sat.base_type) {
                     Ok(_) => {
+                        let count = match sat.container {
+                            device::shade::Single => Some(1u),
+                            device::shade::Vector(d) => Some(d as uint),
+                            device::shade::Matrix(..) => None, // spans several slots
+                        };
+                        match count {
+                            Some(c) if c != vat.elem_count as uint => {
+                                warn!("Attribute {} is declared with {} elements of {}, \
+                                       but the shader reads {} of {}", vat.name,
+                                       vat.elem_count, vat.elem_type, c, sat.base_type);
+                                if self.strict_attributes {
+                                    return Err(ErrorAttributeCount)
+                                }
+                            },
+                            _ => (),
+                        }
                         self.list.bind_attribute(
                             sat.location as device::AttributeSlot,
                             vat.buffer.get_name(), vat.elem_count, vat.elem_type,
-                            vat.stride, vat.offset);
+                            vat.stride, vat.offset, vat.instance_rate);
                     },
                     Err(_) => return Err(ErrorAttributeType)
                 },
@@ -373,3 +747,561 @@ impl DrawList {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::default::Default;
+    use std::rc::Rc;
+    use device;
+    use device::ApiBackEnd;
+    use device::null::NullBackEnd;
+    use device::shade as s;
+    use device::state as ds;
+    use device::target::{ClearData, Color};
+    use device::tex;
+    use fixture::{SOURCE, Vertex, commands, draw_triangle, has_command, sampler, setup,
+                  texture_dict, triangle, uniform};
+    use mesh::{IndexSlice, Mesh};
+    use shade::{NamedCell, ParamDictionary, ProgramShell, texture_param};
+    use state::{DrawState, BlendAlpha};
+    use target::{Frame, PlaneTexture, ErrorClearInteger};
+    use super::{BackEndHelper, DrawList, ErrorAttributeCount, ErrorFrame, ErrorLink, ErrorMesh,
+                ErrorShell, ErrorShellUniform, ErrorSlice, ErrorState, ErrorTextureUnits,
+                ErrorUniform};
+
+    #[test]
+    fn test_texture_units() {
+        let mut backend = NullBackEnd::new();
+        let units = 2;
+        backend.set_max_texture_units(units);
+        backend.set_samplers(range(0, units).map(|i|
+            sampler(format!("tex{}", i).as_slice(), i)
+        ).collect());
+        backend.link_program((), SOURCE.clone(), SOURCE.clone()).unwrap();
+        assert_eq!(backend.get_program_count(), 1);
+        backend.set_samplers(range(0, units + 1).map(|i|
+            sampler(format!("tex{}", i).as_slice(), i)
+        ).collect());
+        match backend.link_program((), SOURCE.clone(), SOURCE.clone()) {
+            Err(ErrorTextureUnits(used, available)) => {
+                assert_eq!((used, available), (units + 1, units));
+            },
+            Err(e) => fail!("Unexpected error {}", e),
+            Ok(_) => fail!("Linked a program using more samplers than texture units"),
+        }
+        // the rejected program is released
+        assert_eq!(backend.get_program_count(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_uniform() {
+        let mut backend = NullBackEnd::new();
+        // samplers share the namespace of the uniforms
+        backend.set_samplers(vec![sampler("tex", 0), sampler("tex", 1)]);
+        match backend.link_program((), SOURCE.clone(), SOURCE.clone()) {
+            Err(ErrorUniform(ref name)) if name.as_slice() == "tex" => (),
+            Err(e) => fail!("Unexpected error {}", e),
+            Ok(_) => fail!("Linked a program declaring a uniform twice"),
+        }
+        assert_eq!(backend.get_program_count(), 0);
+    }
+
+    #[test]
+    fn test_clear_integer() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let mut info = tex::TextureInfo::new();
+        info.format = tex::RGBA8UI;
+        let texture = backend.create_texture(info).unwrap();
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(texture, 0, None);
+
+        let mut list = frontend.create_drawlist();
+        match list.clear_color_int(0, [1, 2, 3, 4], &frame) {
+            Err(ErrorFrame(ErrorClearInteger(0))) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+        match list.clear_color_uint(1, [1, 2, 3, 4], &frame) {
+            Err(ErrorFrame(ErrorClearInteger(1))) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+        list.clear_color_uint(0, [1, 2, 3, 4], &frame).unwrap();
+        let clears = commands(&list, |com| match *com {
+            device::ClearColorUint(..) => Some(format!("{}", com)),
+            _ => None,
+        });
+        assert_eq!(clears, vec!["ClearColorUint(0, [1, 2, 3, 4])".to_string()]);
+    }
+
+    #[test]
+    fn test_default_state() {
+        let mut backend = NullBackEnd::new();
+        let (mut frontend, mesh, program) = setup(&mut backend);
+        frontend.set_default_state(DrawState::ui());
+
+        let mut list = frontend.create_drawlist();
+        list.draw_default(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program).unwrap();
+        // an explicit state still overrides the default one
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program,
+                  &DrawState::new()).unwrap();
+        let blends = commands(&list, |com| match *com {
+            device::SetBlendState(ref blend) => Some(blend.is_some()),
+            _ => None,
+        });
+        assert_eq!(blends, vec![true, false]);
+    }
+
+    #[test]
+    fn test_texture_clone() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, _) = setup(&mut backend);
+        backend.set_samplers(vec![sampler("tex", 0)]);
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+        let copy = texture.clone();
+        let dict = texture_dict(texture);
+        let program = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+        let state = DrawState::new();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state)
+            .unwrap();
+        // both copies refer to the same texture object
+        dict.textures[0].value.set(texture_param(copy));
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state)
+            .unwrap();
+        let names = commands(&list, |com| match *com {
+            device::BindTexture(0, _, name, _) => Some(name),
+            _ => None,
+        });
+        assert_eq!(names, vec![texture.get_name(), texture.get_name()]);
+    }
+
+    #[test]
+    fn test_texture_unit() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+
+        let mut list = frontend.create_drawlist();
+        list.bind_texture(3, &texture, None);
+        match list.as_slice().iter().last() {
+            Some(&device::BindTexture(3, tex::Texture2D, name, None)) =>
+                assert_eq!(name, texture.get_name()),
+            other => fail!("Unexpected command {}", other),
+        }
+    }
+
+    #[test]
+    fn test_bind_image() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let mut info = tex::TextureInfo::new();
+        info.format = tex::RGBA32F;
+        let texture = backend.create_texture(info).unwrap();
+
+        let mut list = frontend.create_drawlist();
+        list.bind_image(1, &texture, 2, tex::WriteOnly, tex::RGBA32F);
+        match list.as_slice().iter().last() {
+            Some(&device::BindImage(1, name, 2, tex::WriteOnly, format)) =>
+                assert_eq!((name, format), (texture.get_name(), tex::RGBA32F)),
+            other => fail!("Unexpected command {}", other),
+        }
+    }
+
+    #[test]
+    fn test_blend_separate() {
+        let mut backend = NullBackEnd::new();
+        let alpha = ds::BlendChannel {
+            equation: ds::FuncMax,
+            source: ds::Factor(ds::Inverse, ds::Zero),
+            destination: ds::Factor(ds::Inverse, ds::Zero),
+        };
+        let state = DrawState::new().blend_separate(Default::default(), alpha);
+        let list = draw_triangle(&mut backend, &state);
+        let blends = commands(&list, |com| match *com {
+            device::SetBlendState(blend) => blend,
+            _ => None,
+        });
+        assert_eq!(blends.len(), 1);
+        assert_eq!(blends[0].color, Default::default());
+        assert_eq!(blends[0].alpha, alpha);
+    }
+
+    #[test]
+    fn test_blend_target() {
+        let mut backend = NullBackEnd::new();
+        let blend = DrawState::new().blend(BlendAlpha).blend.unwrap();
+        let state = DrawState::new().blend_target(1, Some(blend));
+        let list = draw_triangle(&mut backend, &state);
+        let mut blends = list.as_slice().iter().filter(|com| match **com {
+            device::SetBlendState(..) | device::SetBlendTarget(..) => true,
+            _ => false,
+        });
+        match (blends.next(), blends.next(), blends.next()) {
+            (Some(&device::SetBlendState(None)), Some(&device::SetBlendTarget(1, Some(b))),
+             None) => assert_eq!(b, blend),
+            other => fail!("Unexpected blend commands {}", other),
+        }
+    }
+
+    #[test]
+    fn test_logic_op() {
+        let mut backend = NullBackEnd::new();
+        let state = DrawState::new().logic_op(Some(ds::LogicXor));
+        let list = draw_triangle(&mut backend, &state);
+        assert!(has_command(&list, |com| match *com {
+            device::SetLogicOp(Some(ds::LogicXor)) => true,
+            _ => false,
+        }));
+
+        let (frontend, mesh, program) = setup(&mut backend);
+        let blend = DrawState::new().blend(BlendAlpha).blend;
+        let mut list = frontend.create_drawlist();
+        for state in [state.clone().blend(BlendAlpha), state.clone().blend_target(1, blend)]
+                     .iter() {
+            match list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program,
+                            state) {
+                Err(ErrorState) => (),
+                other => fail!("Unexpected result {}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth_range() {
+        let mut backend = NullBackEnd::new();
+        let state = DrawState::new().depth(ds::Greater, true)
+                                    .depth_range(1.0, 0.0)
+                                    .depth_clamp(true);
+        let list = draw_triangle(&mut backend, &state);
+        let ranges = commands(&list, |com| match *com {
+            device::SetDepthRange(range) => Some(range),
+            _ => None,
+        });
+        assert_eq!(ranges, vec![ds::DepthRange { near: 1.0, far: 0.0 }]);
+        assert!(has_command(&list, |com| match *com {
+            device::SetDepthClamp(true) => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_clip_planes() {
+        let mut backend = NullBackEnd::new();
+        let state = DrawState::new().clip_planes((1 << 0) | (1 << 2));
+        let list = draw_triangle(&mut backend, &state);
+        let masks = commands(&list, |com| match *com {
+            device::SetClipPlanes(mask) => Some(mask),
+            _ => None,
+        });
+        assert_eq!(masks, vec![0b101]);
+    }
+
+    #[test]
+    fn test_strict_attributes() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mut mesh, program) = setup(&mut backend);
+        // the shader reads a `vec3` color
+        mesh.attributes[1].elem_count = 4;
+        let state = DrawState::new();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state)
+            .unwrap();
+        list.set_strict_attributes(true);
+        match list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state) {
+            Err(ErrorMesh(ErrorAttributeCount)) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+    }
+
+    #[test]
+    fn test_conditional() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, program) = setup(&mut backend);
+        let query = backend.create_query();
+        let state = DrawState::new();
+
+        let mut list = frontend.create_drawlist();
+        list.begin_occlusion_query(&query);
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state)
+            .unwrap();
+        list.end_occlusion_query();
+        list.begin_conditional(&query, device::ConditionalWait);
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state)
+            .unwrap();
+        list.end_conditional();
+        let names = commands(&list, |com| match *com {
+            device::BeginOcclusionQuery(name) if name == query.get_name() => Some("begin query"),
+            device::EndOcclusionQuery => Some("end query"),
+            device::BeginConditional(name, device::ConditionalWait)
+                if name == query.get_name() => Some("begin conditional"),
+            device::EndConditional => Some("end conditional"),
+            device::Draw(..) => Some("draw"),
+            _ => None,
+        });
+        assert_eq!(names, vec!["begin query", "draw", "end query",
+                               "begin conditional", "draw", "end conditional"]);
+    }
+
+    #[test]
+    fn test_rc_shell() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, program) = setup(&mut backend);
+        let shell = Rc::new(program);
+        let shared = shell.clone();
+        let state = DrawState::new();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &shell, &state).unwrap();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &shared, &state).unwrap();
+        let programs = commands(&list, |com| match *com {
+            device::BindProgram(name) => Some(name),
+            _ => None,
+        });
+        assert_eq!(programs.len(), 2);
+    }
+
+    #[test]
+    fn test_indexed_quad() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let mesh = backend.create_indexed_mesh(vec![
+            Vertex { pos: [ -0.5, -0.5 ], color: [1.0, 0.0, 0.0] },
+            Vertex { pos: [ 0.5, -0.5 ], color: [0.0, 1.0, 0.0] },
+            Vertex { pos: [ -0.5, 0.5 ], color: [0.0, 0.0, 1.0] },
+            Vertex { pos: [ 0.5, 0.5 ], color: [1.0, 1.0, 1.0] }
+        ], vec![0, 1, 2, 2, 1, 3]);
+        let program = backend.link_program((), SOURCE.clone(), SOURCE.clone()).unwrap();
+        let index_buffer = match mesh.indices {
+            Some((buf, device::attrib::U16, 6)) => buf,
+            other => fail!("Unexpected indices {}", other),
+        };
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program,
+                  &DrawState::new()).unwrap();
+        let mut draws = list.as_slice().iter().filter(|com| match **com {
+            device::BindIndex(..) | device::Draw(..) | device::DrawIndexed(..) => true,
+            _ => false,
+        });
+        match (draws.next(), draws.next(), draws.next()) {
+            (Some(&device::BindIndex(name)),
+             Some(&device::DrawIndexed(device::TriangleList, device::attrib::U16, 0, 6, 0, None)),
+             None) => assert_eq!(name, index_buffer.get_name()),
+            other => fail!("Unexpected draw commands {}", other),
+        }
+        assert_eq!(backend.get_frame_stats().draw_count, 0);
+        backend.submit(list.as_slice());
+        assert_eq!(backend.get_frame_stats().draw_count, 1);
+    }
+
+    #[test]
+    fn test_base_vertex() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, _, program) = setup(&mut backend);
+        // sub-meshes packed into shared vertex and index buffers
+        let vertices = backend.create_buffer();
+        let indices = backend.create_buffer();
+        let mesh = Mesh::from::<Vertex>(vertices, 200);
+        let state = DrawState::new();
+        let frame = frontend.get_main_frame();
+
+        let mut list = frontend.create_drawlist();
+        list.set_validation(true);
+        list.draw(&mesh, IndexSlice(indices, device::attrib::U16, 0, 6, 100), frame,
+                  &program, &state).unwrap();
+        let bases = commands(&list, |com| match *com {
+            device::DrawIndexed(_, device::attrib::U16, 0, 6, base, None) => Some(base),
+            _ => None,
+        });
+        assert_eq!(bases, vec![100]);
+        // a base vertex past the end of the mesh
+        match list.draw(&mesh, IndexSlice(indices, device::attrib::U16, 0, 6, 200), frame,
+                        &program, &state) {
+            Err(ErrorSlice) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+    }
+
+    #[test]
+    fn test_clear_read_only_depth() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, program) = setup(&mut backend);
+        let state = DrawState::new().depth(ds::LessEqual, false);
+        let frame = frontend.get_main_frame();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), frame, &program, &state).unwrap();
+        // the clear is not masked by the read-only depth of the last draw
+        list.clear(ClearData { color: None, depth: Some(1.0), stencil: None }, frame);
+        list.draw(&mesh, mesh.get_slice(), frame, &program, &state).unwrap();
+        let names = commands(&list, |com| match *com {
+            device::SetDepthStencilState(Some(depth), _, _) if !depth.write => Some("read-only"),
+            device::Clear(data) if data.depth == Some(1.0) => Some("clear"),
+            _ => None,
+        });
+        assert_eq!(names, vec!["read-only", "clear", "read-only"]);
+    }
+
+    #[test]
+    fn test_draw_with() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, _) = setup(&mut backend);
+        backend.set_uniforms(vec![uniform("u_Model", 3, s::Matrix(s::ColumnMajor, 4, 4))]);
+        let identity = [[1.0f32, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0],
+                        [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        let dict = ParamDictionary {
+            uniforms: vec![NamedCell {
+                name: "u_Model".to_string(),
+                value: Cell::new(s::ValueF32Matrix(identity)),
+            }],
+            blocks: Vec::new(),
+            textures: Vec::new(),
+        };
+        let program = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+        let state = DrawState::new();
+        let frame = frontend.get_main_frame();
+        let mut model = identity;
+        model[3][0] = 2.0;
+
+        let mut list = frontend.create_drawlist();
+        list.draw_with(&mesh, mesh.get_slice(), frame, &program, &state,
+                       [("u_Model", s::ValueF32Matrix(model))]).unwrap();
+        list.draw(&mesh, mesh.get_slice(), frame, &program, &state).unwrap();
+        let offsets = commands(&list, |com| match *com {
+            device::BindUniform(3, s::ValueF32Matrix(m)) => Some(m[3][0]),
+            _ => None,
+        });
+        // the inline value only applies to its own draw call
+        assert_eq!(offsets, vec![2.0, 0.0]);
+        match list.draw_with(&mesh, mesh.get_slice(), frame, &program, &state,
+                             [("u_View", s::ValueF32Matrix(model))]) {
+            Err(ErrorShell(ErrorShellUniform(ref name))) if name.as_slice() == "u_View" => (),
+            other => fail!("Unexpected result {}", other),
+        }
+    }
+
+    #[test]
+    fn test_reload_program() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, _) = setup(&mut backend);
+        backend.set_samplers(vec![sampler("tex", 0)]);
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+        let dict = texture_dict(texture);
+        let program = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+        backend.reload_program(program.get_program(), SOURCE.clone(), SOURCE.clone()).unwrap();
+        // the new sources declare another sampler
+        backend.set_samplers(vec![sampler("tex", 0), sampler("shadow", 1)]);
+        match backend.reload_program(program.get_program(), SOURCE.clone(), SOURCE.clone()) {
+            Err(ErrorLink(())) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+        assert_eq!(backend.get_shader_count(), 0);
+        // the program is still the one linked first, along with its links
+        assert_eq!(program.get_program().get_info().textures.len(), 1);
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program,
+                  &DrawState::new()).unwrap();
+        assert!(has_command(&list, |com| match *com {
+            device::BindTexture(0, _, name, _) => name == texture.get_name(),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_create_mesh_retained() {
+        let mut backend = NullBackEnd::new();
+        let mesh = backend.create_mesh(triangle());
+        assert!(mesh.cpu_data::<Vertex>().is_none());
+        let mesh = backend.create_mesh_retained(triangle());
+        assert_eq!(mesh.num_vertices, 3);
+        let data = mesh.cpu_data::<Vertex>().unwrap();
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[2].pos.as_slice(), [0.0f32, 0.5].as_slice());
+    }
+
+    #[test]
+    fn test_serialize_drawlist() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, _) = setup(&mut backend);
+        backend.set_uniforms(vec![uniform("u_Color", 2, s::Vector(4))]);
+        backend.set_samplers(vec![sampler("tex", 0)]);
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+        let sampler = backend.create_sampler(tex::SamplerInfo::new(tex::Trilinear, tex::Clamp));
+        let dict = ParamDictionary {
+            uniforms: vec![NamedCell {
+                name: "u_Color".to_string(),
+                value: Cell::new(s::ValueF32Vec([1.0, 0.5, 0.25, 1.0])),
+            }],
+            blocks: Vec::new(),
+            textures: vec![NamedCell {
+                name: "tex".to_string(),
+                value: Cell::new((texture, Some(sampler))),
+            }],
+        };
+        let program = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+
+        let mut list = frontend.create_drawlist();
+        list.clear(ClearData {
+            color: Some(Color([0.3, 0.3, 0.3, 1.0])),
+            depth: Some(1.0),
+            stencil: None,
+        }, frontend.get_main_frame());
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program,
+                  &DrawState::new()).unwrap();
+        assert!(has_command(&list, |com| match *com {
+            device::SetPrimitiveState(_) => true,
+            _ => false,
+        }));
+        assert!(has_command(&list, |com| match *com {
+            device::BindTexture(0, _, _, Some(_)) => true,
+            _ => false,
+        }));
+
+        let data = list.as_slice().serialize();
+        let replay = device::DrawList::deserialize(data.as_slice()).unwrap();
+        assert_eq!(replay.serialize(), data);
+        let original = commands(&list, |com| Some(format!("{}", com)));
+        let decoded: Vec<String> = replay.iter().map(|com| format!("{}", com)).collect();
+        assert_eq!(decoded, original);
+        // a truncated stream is rejected instead of decoding garbage
+        assert_eq!(device::DrawList::deserialize(data.slice_to(data.len() - 1)).err(),
+                   Some(device::serial::ErrorTruncated));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let texture = backend.create_texture(tex::TextureInfo::new()).unwrap();
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(texture, 0, None);
+        let data = ClearData { color: Some(Color::new()), depth: None, stencil: None };
+        let count_binds = |list: &DrawList| commands(list, |com| match *com {
+            device::BindTargetTexture(..) => Some(()),
+            _ => None,
+        }).len();
+
+        let mut world = frontend.create_drawlist();
+        world.clear(data, frontend.get_main_frame());
+        let mut ui = frontend.create_drawlist();
+        ui.clear(data, &frame);
+        world.append(ui);
+        assert_eq!(count_binds(&world), 1);
+        // the planes attached by the appended list are known to this one
+        world.clear(data, &frame);
+        assert_eq!(count_binds(&world), 1);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_append_other_frontend() {
+        let mut backend = NullBackEnd::new();
+        let first = backend.create_frontend(64, 64).unwrap();
+        let second = backend.create_frontend(64, 64).unwrap();
+        let mut list = first.create_drawlist();
+        list.append(second.create_drawlist());
+    }
+}
@@ -38,3 +38,5 @@ pub mod shade;
 pub mod state;
 /// Render targets
 pub mod target;
+#[cfg(test)]
+mod fixture;
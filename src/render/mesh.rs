@@ -20,6 +20,9 @@
 //! create a mesh is to use the `#[vertex_format]` attribute on a struct, upload them into a
 //! `Buffer`, and then use `Mesh::from`.
 
+use std::any::{Any, AnyRefExt};
+use std::fmt;
+use std::sync::Arc;
 use d = device;
 use a = device::attrib;
 
@@ -36,18 +39,123 @@ pub struct Attribute {
     pub offset: a::Offset,
     /// Stride in bytes between consecutive vertices
     pub stride: a::Stride,
+    /// Number of instances drawn before advancing to the next element, or 0
+    /// to advance with each vertex
+    pub instance_rate: a::InstanceRate,
     /// A name to match the shader input
     pub name: String,
 }
 
+/// A point in the model space, as used by the mesh bounds.
+pub type Vec3 = (f32, f32, f32);
+
 /// A trait implemented automatically for user vertex structure by
 /// `#[vertex_format] attribute
 pub trait VertexFormat {
     /// Create the attributes for this type, using the given buffer.
     fn generate(Option<Self>, buffer: d::BufferHandle) -> Vec<Attribute>;
+    /// Get the position of this vertex, taken from the field marked as
+    /// `#[position]`. Missing components are set to zero.
+    fn get_position(&self) -> Option<Vec3> {
+        None
+    }
+}
+
+/// Describe the attributes of a vertex format, for inspection at run time.
+/// The attributes are not bound to any actual buffer: their buffer handle is
+/// a placeholder, to be replaced before using them in a `Mesh`.
+pub fn describe<V: VertexFormat>() -> Vec<Attribute> {
+    VertexFormat::generate(None::<V>, d::make_fake_buffer())
+}
+
+/// Compute the axis-aligned bounding box of the given vertices, as a `(min, max)` pair.
+/// Returns `None` if there are no vertices, or the format has no `#[position]` field.
+pub fn calculate_bounds<V: VertexFormat>(data: &[V]) -> Option<(Vec3, Vec3)> {
+    data.iter().filter_map(|v| v.get_position()).fold(None, |bounds, (x, y, z)| {
+        Some(match bounds {
+            None => ((x, y, z), (x, y, z)),
+            Some(((x0, y0, z0), (x1, y1, z1))) => (
+                (x0.min(x), y0.min(y), z0.min(z)),
+                (x1.max(x), y1.max(y), z1.max(z)),
+            ),
+        })
+    })
+}
+
+/// Concatenate the vertex data of several meshes of the same format, and
+/// return it along with the slice of each part in the combined data.
+/// Drawing the parts through their slices of a single mesh saves the
+/// buffer changes in between.
+pub fn merge<V: Clone>(parts: &[&[V]]) -> (Vec<V>, Vec<Slice>) {
+    let mut data = Vec::with_capacity(parts.iter().fold(0, |n, p| n + p.len()));
+    let mut slices = Vec::with_capacity(parts.len());
+    for part in parts.iter() {
+        let start = data.len() as d::VertexCount;
+        data.push_all(*part);
+        slices.push(VertexSlice(start, data.len() as d::VertexCount));
+    }
+    (data, slices)
+}
+
+/// Result of a ray cast against a mesh.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Hit {
+    /// Distance from the ray origin, in units of the ray direction length.
+    pub distance: f32,
+    /// Index of the triangle that was hit.
+    pub triangle: uint,
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    (a.val0() - b.val0(), a.val1() - b.val1(), a.val2() - b.val2())
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a.val0() * b.val0() + a.val1() * b.val1() + a.val2() * b.val2()
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    let ((ax, ay, az), (bx, by, bz)) = (a, b);
+    (ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx)
+}
+
+/// Intersect a ray with a triangle, using the Moller-Trumbore algorithm.
+/// Returns the distance along the ray, if it hits the front or the back face.
+fn intersect_triangle(origin: Vec3, dir: Vec3, v0: Vec3, v1: Vec3, v2: Vec3) -> Option<f32> {
+    let epsilon = 1.0e-6f32;
+    let (e1, e2) = (sub(v1, v0), sub(v2, v0));
+    let p = cross(dir, e2);
+    let det = dot(e1, p);
+    if det.abs() < epsilon {
+        return None // the ray is parallel to the triangle
+    }
+    let t = sub(origin, v0);
+    let u = dot(t, p) / det;
+    if u < 0.0 || u > 1.0 {
+        return None
+    }
+    let q = cross(t, e1);
+    let v = dot(dir, q) / det;
+    if v < 0.0 || u + v > 1.0 {
+        return None
+    }
+    let distance = dot(e2, q) / det;
+    if distance >= 0.0 { Some(distance) } else { None }
+}
+
+/// Vertex data kept on the CPU side. The vertex format is erased, so that
+/// `Mesh` doesn't depend on it, and the data is shared between the clones.
+#[deriving(Clone)]
+pub struct CpuData(Arc<Box<Any + Send + Sync>>);
+
+impl fmt::Show for CpuData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CpuData")
+    }
 }
 
 /// Describes geometry to render.
+/// Cloning a mesh is cheap, the clone refers to the same vertex buffers.
 #[deriving(Clone, Show)]
 pub struct Mesh {
     /// What primitives to form out of the vertex data.
@@ -56,6 +164,13 @@ pub struct Mesh {
     pub num_vertices: d::VertexCount,
     /// Vertex attributes to use.
     pub attributes: Vec<Attribute>,
+    /// Axis-aligned bounding box of the vertices, see `bounds()`.
+    bounds: Option<(Vec3, Vec3)>,
+    /// Index buffer along with the type and the number of its indices.
+    /// If set, the default slice of the mesh goes through it.
+    pub indices: Option<(d::BufferHandle, d::IndexType, d::IndexCount)>,
+    /// Copy of the vertex data, if it was kept when creating the mesh.
+    pub cpu_copy: Option<CpuData>,
 }
 
 impl Mesh {
@@ -65,6 +180,9 @@ impl Mesh {
             prim_type: d::TriangleList,
             num_vertices: nv,
             attributes: Vec::new(),
+            bounds: None,
+            indices: None,
+            cpu_copy: None,
         }
     }
 
@@ -74,12 +192,88 @@ impl Mesh {
             prim_type: d::TriangleList,
             num_vertices: nv,
             attributes: VertexFormat::generate(None::<V>, buf),
+            bounds: None,
+            indices: None,
+            cpu_copy: None,
+        }
+    }
+
+    /// Get the axis-aligned bounding box of the vertices, as a `(min, max)` pair.
+    /// Only known if the mesh was created from the vertex data with a `#[position]` field.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        self.bounds
+    }
+
+    /// Compute the bounding box from the given vertex data, see `calculate_bounds`.
+    pub fn compute_bounds<V: VertexFormat>(&mut self, data: &[V]) {
+        self.bounds = calculate_bounds(data);
+    }
+
+    /// Add the attributes of a per-instance format, read from the given
+    /// buffer, which advance once for each instance drawn.
+    pub fn add_instanced<I: VertexFormat>(&mut self, buf: d::BufferHandle) {
+        let attributes: Vec<Attribute> = VertexFormat::generate(None::<I>, buf);
+        for at in attributes.move_iter() {
+            let mut at = at;
+            at.instance_rate = 1;
+            self.attributes.push(at);
+        }
+    }
+
+    /// Keep the vertex data on the CPU side, for reading it back with `cpu_data`.
+    pub fn keep_cpu_data<V: VertexFormat + Send + Sync>(&mut self, data: Vec<V>) {
+        self.cpu_copy = Some(CpuData(Arc::new(box data as Box<Any + Send + Sync>)));
+    }
+
+    /// Get the vertex data kept on the CPU side. Returns `None` if it was not
+    /// kept, or if it has a different format than `V`.
+    pub fn cpu_data<'a, V: VertexFormat + Send + Sync>(&'a self) -> Option<&'a [V]> {
+        match self.cpu_copy {
+            Some(CpuData(ref data)) => {
+                let any: &Any = &***data;
+                any.downcast_ref::<Vec<V>>().map(|v| v.as_slice())
+            },
+            None => None,
         }
     }
 
-    /// Return a vertex slice of the whole mesh
+    /// Cast a ray against the triangles of the vertex data kept on the CPU
+    /// side, and return the nearest hit. Only non-indexed triangle lists with
+    /// a `#[position]` field can be picked, since the indices are not kept.
+    pub fn raycast<V: VertexFormat + Send + Sync>(&self, origin: Vec3, dir: Vec3) -> Option<Hit> {
+        if self.prim_type != d::TriangleList || self.indices.is_some() {
+            return None
+        }
+        let data = match self.cpu_data::<V>() {
+            Some(data) => data,
+            None => return None,
+        };
+        let mut nearest: Option<Hit> = None;
+        for (i, tri) in data.chunks(3).enumerate() {
+            if tri.len() < 3 {
+                break
+            }
+            let (v0, v1, v2) = match (tri[0].get_position(), tri[1].get_position(),
+                                      tri[2].get_position()) {
+                (Some(v0), Some(v1), Some(v2)) => (v0, v1, v2),
+                _ => return None,
+            };
+            match intersect_triangle(origin, dir, v0, v1, v2) {
+                Some(distance) if nearest.as_ref().map_or(true, |h| distance < h.distance) => {
+                    nearest = Some(Hit { distance: distance, triangle: i });
+                },
+                _ => (),
+            }
+        }
+        nearest
+    }
+
+    /// Return a slice of the whole mesh, going through the index buffer if there is one
     pub fn get_slice(&self) -> Slice {
-        VertexSlice(0, self.num_vertices)
+        match self.indices {
+            Some((buf, index_type, count)) => IndexSlice(buf, index_type, 0, count, 0),
+            None => VertexSlice(0, self.num_vertices),
+        }
     }
 }
 
@@ -94,8 +288,28 @@ pub enum Slice  {
     /// when drawing a square, two triangles are needed.  Using only `VertexSlice`, one would need
     /// 6 separate vertices, 3 for each triangle. However, two of the vertices will be identical,
     /// wasting space for the duplicated attributes.  Instead, the `Mesh` can store 4 vertices and
-    /// an `IndexSlice` can be used instead.
-    IndexSlice(d::BufferHandle, d::IndexType, d::IndexCount, d::IndexCount),
+    /// an `IndexSlice` can be used instead. The last field is the base vertex,
+    /// added to every index, which allows several sub-meshes to share the
+    /// same buffers.
+    IndexSlice(d::BufferHandle, d::IndexType, d::IndexCount, d::IndexCount, i32),
+}
+
+impl Slice {
+    /// Check that the slice stays within the data of the `Mesh`. An index
+    /// slice can only be checked against the mesh's own index buffer, other
+    /// index buffers are assumed to be large enough. A positive base vertex
+    /// has to leave some vertices of the mesh to index.
+    pub fn is_within(&self, mesh: &Mesh) -> bool {
+        match *self {
+            VertexSlice(start, end) => start <= end && end <= mesh.num_vertices,
+            IndexSlice(buf, _, start, end, base) => start <= end &&
+                (base < 0 || (base as d::VertexCount) < mesh.num_vertices) &&
+                match mesh.indices {
+                    Some((ibuf, _, count)) if ibuf == buf => end <= count,
+                    _ => true,
+                },
+        }
+    }
 }
 
 /// A slice of a mesh, with a given material.
@@ -106,3 +320,92 @@ pub struct SubMesh {
     /// Slice of the mesh to use.
     pub slice: Slice,
 }
+
+#[cfg(test)]
+mod tests {
+    use device;
+    use super::{Mesh, VertexFormat, Attribute, Vec3, Hit, VertexSlice, IndexSlice, merge};
+
+    #[test]
+    fn test_slice_bounds() {
+        let buf = device::make_fake_buffer();
+        let mut mesh = Mesh::new(8);
+        assert!(VertexSlice(2, 8).is_within(&mesh));
+        assert!(!VertexSlice(2, 9).is_within(&mesh));
+        assert!(!VertexSlice(4, 2).is_within(&mesh));
+        mesh.indices = Some((buf, device::attrib::U16, 12));
+        assert!(IndexSlice(buf, device::attrib::U16, 0, 12, 4).is_within(&mesh));
+        assert!(!IndexSlice(buf, device::attrib::U16, 6, 1000000, 0).is_within(&mesh));
+        // the base vertex skips all the vertices of the mesh
+        assert!(!IndexSlice(buf, device::attrib::U16, 0, 12, 8).is_within(&mesh));
+    }
+
+    #[test]
+    fn test_show() {
+        let mesh = Mesh::new(37);
+        let text = format!("{}", mesh);
+        assert!(text.as_slice().contains("num_vertices: 37"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let first = [0u8, 1, 2];
+        let second = [3u8, 4, 5];
+        let (data, slices) = merge([first.as_slice(), second.as_slice()]);
+        assert_eq!(data, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(slices.len(), 2);
+        let mesh = Mesh::new(data.len() as device::VertexCount);
+        for (slice, &(start, end)) in slices.iter().zip([(0, 3), (3, 6)].iter()) {
+            assert!(slice.is_within(&mesh));
+            match *slice {
+                VertexSlice(s, e) => assert_eq!((s, e), (start, end)),
+                _ => fail!("Expected a vertex slice, got {}", slice),
+            }
+        }
+    }
+
+    #[deriving(Clone, PartialEq, Show)]
+    struct Point(f32);
+
+    impl VertexFormat for Point {
+        fn generate(_: Option<Point>, _: device::BufferHandle) -> Vec<Attribute> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_cpu_data() {
+        let mut mesh = Mesh::new(3);
+        assert_eq!(mesh.cpu_data::<Point>(), None);
+        mesh.keep_cpu_data(vec![Point(0.0), Point(1.0), Point(2.0)]);
+        let copy = mesh.clone();
+        assert_eq!(copy.cpu_data::<Point>().map(|data| data[1].clone()), Some(Point(1.0)));
+    }
+
+    #[deriving(Clone)]
+    struct Position(f32, f32, f32);
+
+    impl VertexFormat for Position {
+        fn generate(_: Option<Position>, _: device::BufferHandle) -> Vec<Attribute> {
+            Vec::new()
+        }
+        fn get_position(&self) -> Option<Vec3> {
+            let Position(x, y, z) = *self;
+            Some((x, y, z))
+        }
+    }
+
+    #[test]
+    fn test_raycast() {
+        let mut mesh = Mesh::new(3);
+        mesh.keep_cpu_data(vec![
+            Position(0.0, 0.0, 0.0),
+            Position(1.0, 0.0, 0.0),
+            Position(0.0, 1.0, 0.0),
+        ]);
+        assert_eq!(mesh.raycast::<Position>((0.25, 0.25, 2.0), (0.0, 0.0, -1.0)),
+                   Some(Hit { distance: 2.0, triangle: 0 }));
+        assert_eq!(mesh.raycast::<Position>((2.0, 2.0, 2.0), (0.0, 0.0, -1.0)), None);
+        assert_eq!(mesh.raycast::<Position>((0.25, 0.25, 2.0), (0.0, 0.0, 1.0)), None);
+    }
+}
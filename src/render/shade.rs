@@ -15,14 +15,22 @@
 //! Shader parameter handling.
 
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::{Arc, RWLock};
 use s = device::shade;
-use device::{BufferHandle, ProgramHandle, SamplerHandle, TextureHandle};
+use device::{ApiBackEnd, BufferHandle, ProgramHandle, SamplerHandle, TextureHandle};
+use front::{BackEndHelper, ProgramError, ErrorPreprocess};
 
 /// Helper trait to transform base types into their corresponding uniforms
 pub trait ToUniform {
     /// Create a `UniformValue` representing this value.
     fn to_uniform(&self) -> s::UniformValue;
+    /// Create a `UniformValue` representing this value, with matrices stored
+    /// row by row. Same as `to_uniform` for the other values.
+    fn to_uniform_transposed(&self) -> s::UniformValue {
+        self.to_uniform()
+    }
 }
 
 impl ToUniform for i32 {
@@ -48,10 +56,35 @@ impl ToUniform for [f32, ..4] {
         s::ValueF32Vec(*self)
     }
 }
+
+impl ToUniform for (f32, f32) {
+    fn to_uniform(&self) -> s::UniformValue {
+        let (x, y) = *self;
+        s::ValueF32Vec2([x, y])
+    }
+}
+
+impl ToUniform for (f32, f32, f32) {
+    fn to_uniform(&self) -> s::UniformValue {
+        let (x, y, z) = *self;
+        s::ValueF32Vec3([x, y, z])
+    }
+}
+
+impl ToUniform for (f32, f32, f32, f32) {
+    fn to_uniform(&self) -> s::UniformValue {
+        let (x, y, z, w) = *self;
+        s::ValueF32Vec([x, y, z, w])
+    }
+}
+
 impl ToUniform for [[f32, ..4], ..4] {
     fn to_uniform(&self) -> s::UniformValue {
         s::ValueF32Matrix(*self)
     }
+    fn to_uniform_transposed(&self) -> s::UniformValue {
+        s::ValueF32MatrixTransposed(*self)
+    }
 }
 
 /// Variable index of a uniform.
@@ -66,6 +99,16 @@ pub type VarTexture = u8;
 /// A texture parameter: consists of a texture handle with an optional sampler.
 pub type TextureParam = (TextureHandle, Option<SamplerHandle>);
 
+/// Create a texture parameter sampling the texture with its own settings.
+pub fn texture_param(tex: TextureHandle) -> TextureParam {
+    (tex, None)
+}
+
+/// Create a texture parameter sampling the texture with the given sampler.
+pub fn sampled_texture_param(tex: TextureHandle, sampler: SamplerHandle) -> TextureParam {
+    (tex, Some(sampler))
+}
+
 /// Borrowed parts of the `ProgramMeta`, used for data link construction
 pub type ParamLinkInput<'a> = (
     &'a [s::UniformVar],
@@ -84,6 +127,36 @@ pub struct ParamValues<'a> {
     pub textures: &'a mut [Option<TextureParam>],
 }
 
+/// An owned storage for shader parameter values, sized after a program.
+pub struct ParamStorage {
+    /// uniform values to be provided
+    pub uniforms: Vec<Option<s::UniformValue>>,
+    /// uniform buffers to be provided
+    pub blocks  : Vec<Option<BufferHandle>>,
+    /// textures to be provided
+    pub textures: Vec<Option<TextureParam>>,
+}
+
+impl ParamStorage {
+    /// Create an empty storage, with one slot per parameter of the program.
+    pub fn new(info: &s::ProgramInfo) -> ParamStorage {
+        ParamStorage {
+            uniforms: Vec::from_elem(info.uniforms.len(), None),
+            blocks  : Vec::from_elem(info.blocks  .len(), None),
+            textures: Vec::from_elem(info.textures.len(), None),
+        }
+    }
+
+    /// Borrow the storage, for the program shell to fill it.
+    pub fn as_mut_values<'a>(&'a mut self) -> ParamValues<'a> {
+        ParamValues {
+            uniforms: self.uniforms.as_mut_slice(),
+            blocks: self.blocks.as_mut_slice(),
+            textures: self.textures.as_mut_slice(),
+        }
+    }
+}
+
 /// Encloses a shader program with its parameter
 pub trait ProgramShell {
     /// Get the contained program
@@ -107,6 +180,61 @@ impl ProgramShell for ProgramHandle {
     }
 }
 
+impl<P: ProgramShell> ProgramShell for Rc<P> {
+    fn get_program(&self) -> &ProgramHandle {
+        self.deref().get_program()
+    }
+
+    fn fill_params(&self, params: ParamValues) {
+        self.deref().fill_params(params)
+    }
+}
+
+/// Replace the values of the named uniforms, ignoring the unknown names.
+fn fill_overrides(info: &s::ProgramInfo, values: &[(&str, s::UniformValue)],
+                  uniforms: &mut [Option<s::UniformValue>]) {
+    for &(name, ref value) in values.iter() {
+        match info.uniforms.iter().position(|u| u.name.as_slice() == name) {
+            Some(i) => uniforms[i] = Some(value.clone()),
+            None => (),
+        }
+    }
+}
+
+/// A program shell with some of its uniform values replaced, which allows
+/// setting one-off values for a single draw call without a dictionary.
+pub struct UniformOverride<'a, P> {
+    shell: &'a P,
+    values: &'a [(&'a str, s::UniformValue)],
+}
+
+impl<'a, P: ProgramShell> UniformOverride<'a, P> {
+    /// Override the named uniforms of the shell with the given values.
+    pub fn new(shell: &'a P, values: &'a [(&'a str, s::UniformValue)])
+               -> UniformOverride<'a, P> {
+        UniformOverride {
+            shell: shell,
+            values: values,
+        }
+    }
+}
+
+impl<'a, P: ProgramShell> ProgramShell for UniformOverride<'a, P> {
+    fn get_program(&self) -> &ProgramHandle {
+        self.shell.get_program()
+    }
+
+    fn fill_params(&self, params: ParamValues) {
+        let ParamValues { uniforms, blocks, textures } = params;
+        self.shell.fill_params(ParamValues {
+            uniforms: &mut *uniforms,
+            blocks: blocks,
+            textures: textures,
+        });
+        fill_overrides(self.get_program().get_info(), self.values, uniforms);
+    }
+}
+
 /// An error type on either the parameter storage or the program side
 #[deriving(Clone, PartialEq, Show)]
 pub enum ParameterError<'a> {
@@ -118,6 +246,12 @@ pub enum ParameterError<'a> {
     ErrorBlock(&'a str),
     /// Error with the named texture.
     ErrorTexture(&'a str),
+    /// The named uniform of the program has no parameter to read from
+    ErrorMissingUniform(String),
+    /// The named uniform block of the program has no parameter to read from
+    ErrorMissingBlock(String),
+    /// The named texture of the program has no parameter to read from
+    ErrorMissingTexture(String),
 }
 
 /// An error type for the link creation
@@ -141,15 +275,15 @@ impl ShaderParam<()> for () {
     fn create_link(&self, (uniforms, blocks, textures): ParamLinkInput)
                    -> Result<(), ParameterError<'static>> {
         match uniforms.head() {
-            Some(_) => return Err(ErrorUniform("_")),
+            Some(var) => return Err(ErrorMissingUniform(var.name.clone())),
             None => (),
         }
         match blocks.head() {
-            Some(_) => return Err(ErrorBlock("_")),
+            Some(var) => return Err(ErrorMissingBlock(var.name.clone())),
             None => (),
         }
         match textures.head() {
-            Some(_) => return Err(ErrorTexture("_")),
+            Some(var) => return Err(ErrorMissingTexture(var.name.clone())),
             None => (),
         }
         Ok(())
@@ -217,27 +351,64 @@ pub struct ParamDictionary {
 
 /// An associated link structure for `ParamDictionary` that redirects program
 /// input to the relevant dictionary cell.
+#[deriving(Clone)]
 pub struct ParamDictionaryLink {
     uniforms: Vec<uint>,
     blocks: Vec<uint>,
     textures: Vec<uint>,
 }
 
+impl ParamDictionaryLink {
+    /// Link each program variable to the dictionary cell of the same name,
+    /// given the names of the uniform, block and texture cells.
+    fn new((in_uni, in_buf, in_tex): ParamLinkInput, uniforms: Vec<&str>, blocks: Vec<&str>,
+           textures: Vec<&str>) -> Result<ParamDictionaryLink, ParameterError<'static>> {
+        fn find(name: &String, cells: &[&str]) -> Option<uint> {
+            cells.iter().position(|c| *c == name.as_slice())
+        }
+        let mut link = ParamDictionaryLink {
+            uniforms: Vec::with_capacity(in_uni.len()),
+            blocks: Vec::with_capacity(in_buf.len()),
+            textures: Vec::with_capacity(in_tex.len()),
+        };
+        for var in in_uni.iter() {
+            match find(&var.name, uniforms.as_slice()) {
+                Some(id) => link.uniforms.push(id),
+                None => {
+                    error!("Uniform '{}' is missing from the dictionary", var.name);
+                    return Err(ErrorMissingUniform(var.name.clone()))
+                },
+            }
+        }
+        for var in in_buf.iter() {
+            match find(&var.name, blocks.as_slice()) {
+                Some(id) => link.blocks.push(id),
+                None => {
+                    error!("Uniform block '{}' is missing from the dictionary", var.name);
+                    return Err(ErrorMissingBlock(var.name.clone()))
+                },
+            }
+        }
+        for var in in_tex.iter() {
+            match find(&var.name, textures.as_slice()) {
+                Some(id) => link.textures.push(id),
+                None => {
+                    error!("Texture '{}' is missing from the dictionary", var.name);
+                    return Err(ErrorMissingTexture(var.name.clone()))
+                },
+            }
+        }
+        Ok(link)
+    }
+}
+
 impl<'a> ShaderParam<ParamDictionaryLink> for &'a ParamDictionary {
-    fn create_link(&self, (in_uni, in_buf, in_tex): ParamLinkInput)
+    fn create_link(&self, input: ParamLinkInput)
                    -> Result<ParamDictionaryLink, ParameterError<'static>> {
-        //TODO: proper error checks
-        Ok(ParamDictionaryLink {
-            uniforms: in_uni.iter().map(|var|
-                self.uniforms.iter().position(|c| c.name == var.name).unwrap()
-            ).collect(),
-            blocks: in_buf.iter().map(|var|
-                self.blocks  .iter().position(|c| c.name == var.name).unwrap()
-            ).collect(),
-            textures: in_tex.iter().map(|var|
-                self.textures.iter().position(|c| c.name == var.name).unwrap()
-            ).collect(),
-        })
+        ParamDictionaryLink::new(input,
+            self.uniforms.iter().map(|c| c.name.as_slice()).collect(),
+            self.blocks.iter().map(|c| c.name.as_slice()).collect(),
+            self.textures.iter().map(|c| c.name.as_slice()).collect())
     }
 
     fn fill_params(&self, link: &ParamDictionaryLink, out: ParamValues) {
@@ -263,3 +434,314 @@ impl ShaderParam<ParamDictionaryLink> for Rc<ParamDictionary> {
         self.deref().fill_params(link, out)
     }
 }
+
+/// A named lock containing arbitrary value, which can be shared between tasks
+pub struct NamedLock<T> {
+    /// Name
+    pub name: String,
+    /// Value
+    pub value: RWLock<T>,
+}
+
+/// A dictionary of parameters that can be updated from other tasks, while
+/// the rendering task reads it. Meant to be shared through an `Arc`.
+pub struct SharedParamDictionary {
+    /// Uniform dictionary
+    pub uniforms: Vec<NamedLock<s::UniformValue>>,
+    /// Block dictionary
+    pub blocks: Vec<NamedLock<BufferHandle>>,
+    /// Texture dictionary
+    pub textures: Vec<NamedLock<TextureParam>>,
+}
+
+impl<'a> ShaderParam<ParamDictionaryLink> for &'a SharedParamDictionary {
+    fn create_link(&self, input: ParamLinkInput)
+                   -> Result<ParamDictionaryLink, ParameterError<'static>> {
+        ParamDictionaryLink::new(input,
+            self.uniforms.iter().map(|c| c.name.as_slice()).collect(),
+            self.blocks.iter().map(|c| c.name.as_slice()).collect(),
+            self.textures.iter().map(|c| c.name.as_slice()).collect())
+    }
+
+    fn fill_params(&self, link: &ParamDictionaryLink, out: ParamValues) {
+        for (&id, var) in link.uniforms.iter().zip(out.uniforms.mut_iter()) {
+            *var = Some(*self.uniforms[id].value.read());
+        }
+        for (&id, var) in link.blocks.iter().zip(out.blocks.mut_iter()) {
+            *var = Some(*self.blocks[id].value.read());
+        }
+        for (&id, var) in link.textures.iter().zip(out.textures.mut_iter()) {
+            *var = Some(*self.textures[id].value.read());
+        }
+    }
+}
+
+impl ShaderParam<ParamDictionaryLink> for Arc<SharedParamDictionary> {
+    fn create_link(&self, input: ParamLinkInput) -> Result<ParamDictionaryLink,
+                   ParameterError<'static>> {
+        self.deref().create_link(input)
+    }
+
+    fn fill_params(&self, link: &ParamDictionaryLink, out: ParamValues) {
+        self.deref().fill_params(link, out)
+    }
+}
+
+/// Vertex and fragment sources with the sorted defines injected into them
+type CacheKey = (s::ShaderSource, s::ShaderSource, Vec<(String, String)>);
+
+fn make_key(vs_src: &s::ShaderSource, fs_src: &s::ShaderSource, defines: &[(&str, &str)])
+            -> CacheKey {
+    let mut defs: Vec<(String, String)> = defines.iter().map(|&(name, value)|
+        (name.to_string(), value.to_string())
+    ).collect();
+    defs.sort();
+    (vs_src.clone(), fs_src.clone(), defs)
+}
+
+/// A cache of linked programs, which links each variant of a program only
+/// once. Variants are told apart by their sources and defines, regardless of
+/// the order of the defines.
+pub struct ShaderCache {
+    programs: HashMap<CacheKey, ProgramHandle>,
+}
+
+impl ShaderCache {
+    /// Create an empty cache.
+    pub fn new() -> ShaderCache {
+        ShaderCache {
+            programs: HashMap::new(),
+        }
+    }
+
+    /// Number of programs linked so far.
+    pub fn len(&self) -> uint {
+        self.programs.len()
+    }
+
+    /// Return the program previously linked from the given sources and
+    /// defines, or link it with the back-end on the first request.
+    pub fn get_or_link<D, B: ApiBackEnd<D>>(&mut self, backend: &mut B,
+                       vs_src: &s::ShaderSource, fs_src: &s::ShaderSource,
+                       defines: &[(&str, &str)])
+                       -> Result<ProgramHandle, ProgramError<'static>> {
+        let key = make_key(vs_src, fs_src, defines);
+        match self.programs.find(&key) {
+            Some(prog) => return Ok(prog.clone()),
+            None => (),
+        }
+        let (vs_src, fs_src) = match (vs_src.with_defines(defines), fs_src.with_defines(defines)) {
+            (Ok(vs), Ok(fs)) => (vs, fs),
+            (Err(e), _) | (_, Err(e)) => return Err(ErrorPreprocess(e)),
+        };
+        let prog = try!(backend.link_program_source(vs_src, fs_src));
+        self.programs.insert(key, prog.clone());
+        Ok(prog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::{Arc, RWLock};
+    use s = device::shade;
+    use device::null::NullBackEnd;
+    use fixture::SOURCE;
+    use super::{NamedCell, NamedLock, ParamDictionary, SharedParamDictionary};
+    use super::{ParamStorage, ShaderParam, ToUniform, fill_overrides, make_key};
+    use super::{ErrorMissingUniform, ErrorMissingTexture, ShaderCache};
+
+    fn make_info() -> s::ProgramInfo {
+        s::ProgramInfo {
+            attributes: Vec::new(),
+            uniforms: vec![s::UniformVar {
+                name: "color".to_string(),
+                location: 0,
+                count: 1,
+                base_type: s::BaseF32,
+                container: s::Vector(4),
+            }],
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_storage() {
+        let info = make_info();
+        let dict = ParamDictionary {
+            uniforms: vec![NamedCell {
+                name: "color".to_string(),
+                value: Cell::new(s::ValueF32Vec([1.0, ..4])),
+            }],
+            blocks: Vec::new(),
+            textures: Vec::new(),
+        };
+        let mut storage = ParamStorage::new(&info);
+        assert_eq!(storage.uniforms.len(), 1);
+        assert!(storage.blocks.is_empty() && storage.textures.is_empty());
+        let link = (&dict).create_link((info.uniforms.as_slice(), info.blocks.as_slice(),
+                                        info.textures.as_slice())).unwrap();
+        (&dict).fill_params(&link, storage.as_mut_values());
+        assert!(storage.uniforms[0].is_some());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let info = make_info();
+        let mut storage = ParamStorage::new(&info);
+        let values = [("color", s::ValueF32Vec([0.5, ..4])), ("unknown", s::ValueI32(1))];
+        fill_overrides(&info, values.as_slice(), storage.uniforms.as_mut_slice());
+        match storage.uniforms[0] {
+            Some(s::ValueF32Vec(v)) => assert_eq!(v[0], 0.5),
+            ref other => fail!("Unexpected uniform value: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_dictionary_missing() {
+        let mut info = make_info();
+        let dict = ParamDictionary {
+            uniforms: Vec::new(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+        };
+        {
+            let input = (info.uniforms.as_slice(), info.blocks.as_slice(),
+                         info.textures.as_slice());
+            assert_eq!((&dict).create_link(input).err(),
+                       Some(ErrorMissingUniform("color".to_string())));
+        }
+        info.uniforms.clear();
+        info.textures.push(s::SamplerVar {
+            name: "tex".to_string(),
+            location: 1,
+            base_type: s::BaseF32,
+            sampler_type: s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect),
+        });
+        let shared = Arc::new(SharedParamDictionary {
+            uniforms: Vec::new(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+        });
+        let input = (info.uniforms.as_slice(), info.blocks.as_slice(), info.textures.as_slice());
+        assert_eq!(shared.create_link(input).err(), Some(ErrorMissingTexture("tex".to_string())));
+    }
+
+    #[test]
+    fn test_shared_dictionary() {
+        let info = make_info();
+        let dict = Arc::new(SharedParamDictionary {
+            uniforms: vec![NamedLock {
+                name: "color".to_string(),
+                value: RWLock::new(s::ValueF32(0.0)),
+            }],
+            blocks: Vec::new(),
+            textures: Vec::new(),
+        });
+        let link = dict.create_link((info.uniforms.as_slice(), info.blocks.as_slice(),
+                                     info.textures.as_slice())).unwrap();
+        let worker_dict = dict.clone();
+        let (tx, rx) = channel();
+        spawn(proc() {
+            *worker_dict.uniforms[0].value.write() = s::ValueF32(0.5);
+            tx.send(());
+        });
+        rx.recv();
+        let mut storage = ParamStorage::new(&info);
+        dict.fill_params(&link, storage.as_mut_values());
+        match storage.uniforms[0] {
+            Some(s::ValueF32(v)) => assert_eq!(v, 0.5),
+            ref other => fail!("Unexpected uniform value: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let source = s::ShaderSource {
+            glsl_120: Some(s::StaticBytes("void main() {}".as_bytes())),
+            glsl_150: None,
+        };
+        let a = make_key(&source, &source, [("A", "1"), ("B", "0")]);
+        let b = make_key(&source, &source, [("B", "0"), ("A", "1")]);
+        let c = make_key(&source, &source, [("A", "1")]);
+        assert!(a == b);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn test_tuple_uniforms() {
+        fn check<T: ToUniform>(value: T, size: u8, expected: &[f32]) {
+            let var = s::UniformVar {
+                name: "v".to_string(),
+                location: 0,
+                count: 1,
+                base_type: s::BaseF32,
+                container: s::Vector(size),
+            };
+            let uniform = value.to_uniform();
+            assert!(var.is_compatible(&uniform).is_ok());
+            let other = s::UniformVar { container: s::Vector(size % 4 + 1), .. var.clone() };
+            assert!(other.is_compatible(&uniform).is_err());
+            match uniform {
+                s::ValueF32Vec2(v) => assert_eq!(v.as_slice(), expected),
+                s::ValueF32Vec3(v) => assert_eq!(v.as_slice(), expected),
+                s::ValueF32Vec(v) => assert_eq!(v.as_slice(), expected),
+                other => fail!("Unexpected uniform value {}", other),
+            }
+        }
+        check((1.0f32, 2.0f32), 2, &[1.0, 2.0]);
+        check((1.0f32, 2.0f32, 3.0f32), 3, &[1.0, 2.0, 3.0]);
+        check((1.0f32, 2.0f32, 3.0f32, 4.0f32), 4, &[1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_transposed_uniform() {
+        let rows = [[1.0f32, 0.0, 0.0, 5.0],
+                    [0.0, 1.0, 0.0, 6.0],
+                    [0.0, 0.0, 1.0, 7.0],
+                    [0.0, 0.0, 0.0, 1.0]];
+        match rows.to_uniform_transposed() {
+            s::ValueF32MatrixTransposed(m) => assert_eq!(m[0].as_slice(), rows[0].as_slice()),
+            other => fail!("Unexpected uniform value {}", other),
+        }
+        assert!(rows.to_uniform().is_same_type(&s::ValueF32Matrix(rows)));
+        assert!(!rows.to_uniform_transposed().is_same_type(&s::ValueF32Matrix(rows)));
+    }
+
+    #[test]
+    fn test_shader_cache() {
+        let mut backend = NullBackEnd::new();
+        let mut cache = ShaderCache::new();
+        let a = cache.get_or_link(&mut backend, &SOURCE, &SOURCE, [("LIGHTS", "4")]).unwrap();
+        let b = cache.get_or_link(&mut backend, &SOURCE, &SOURCE, [("LIGHTS", "4")]).unwrap();
+        assert_eq!(a.get_name(), b.get_name());
+        assert_eq!((backend.get_link_count(), cache.len()), (1, 1));
+        let c = cache.get_or_link(&mut backend, &SOURCE, &SOURCE, [("LIGHTS", "8")]).unwrap();
+        assert!(c.get_name() != a.get_name());
+        assert_eq!((backend.get_link_count(), cache.len()), (2, 2));
+        // the shaders are released once linked
+        assert_eq!(backend.get_shader_count(), 0);
+    }
+
+    #[test]
+    fn test_shader_cache_defines() {
+        static OTHER: s::ShaderSource = s::ShaderSource {
+            glsl_120: None,
+            glsl_150: Some(s::StaticBytes(b"#version 150 core\nvoid main() { discard; }")),
+        };
+        let mut backend = NullBackEnd::new();
+        let mut cache = ShaderCache::new();
+        let defines = [("SHADOWS", "1"), ("LIGHTS", "4")];
+        let reordered = [("LIGHTS", "4"), ("SHADOWS", "1")];
+        let a = cache.get_or_link(&mut backend, &SOURCE, &SOURCE, defines).unwrap();
+        // the order of the defines doesn't make another variant
+        let b = cache.get_or_link(&mut backend, &SOURCE, &SOURCE, reordered).unwrap();
+        assert_eq!(a.get_name(), b.get_name());
+        assert_eq!(backend.get_link_count(), 1);
+        // but other sources do
+        cache.get_or_link(&mut backend, &SOURCE, &OTHER, defines).unwrap();
+        assert_eq!(backend.get_link_count(), 2);
+    }
+}
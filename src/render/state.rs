@@ -16,11 +16,15 @@
 //!
 //! Configures primitive assembly (PA), rasterizer, and output merger (OM) blocks.
 
+use std::default::Default;
+use std::fmt;
 use s = device::state;
-use device::target::{Color, Rect, Stencil};
+use device::target::{Color, ColorIndex, Rect, Stencil};
 
-/// An assembly of states that affect regular draw calls
-#[deriving(Clone, PartialEq, Show)]
+/// An assembly of states that affect regular draw calls.
+/// Floating-point values are compared by their bits, so that states can be
+/// hashed and used as keys for sorting and de-duplication.
+#[deriving(Clone, PartialEq, Eq, Hash)]
 pub struct DrawState {
     /// How to rasterize geometric primitives.
     pub primitive: s::Primitive,
@@ -31,11 +35,69 @@ pub struct DrawState {
     pub stencil: Option<s::Stencil>,
     /// Depth test to use. If None, no depth testing is done.
     pub depth: Option<s::Depth>,
+    /// Mapping of the depth values, applied independently of the depth test.
+    pub depth_range: s::DepthRange,
+    /// Clamp the fragment depth instead of clipping primitives against the near and far planes.
+    pub depth_clamp: bool,
+    /// User clip planes to enable, one bit per `gl_ClipDistance` element.
+    pub clip_planes: s::ClipPlaneMask,
     /// Blend function to use. If None, no blending is done.
     pub blend: Option<s::Blend>,
+    /// Constant blend color, given to the blend functions set after it too.
+    pub blend_value: Color,
+    /// Blend functions overriding `blend` for the given color targets.
+    pub blend_targets: Vec<(ColorIndex, Option<s::Blend>)>,
     /// Color mask to use. Each flag indicates that the given color channel can be written to, and
     /// they can be OR'd together.
     pub color_mask: s::ColorMask,
+    /// Logic operation to use. If set, blending has to be disabled.
+    pub logic_op: Option<s::LogicOp>,
+}
+
+impl fmt::Show for DrawState {
+    /// Only the enabled states are listed, to keep the output compact.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "DrawState({}", self.primitive));
+        match self.scissor {
+            Some(ref rect) => try!(write!(f, ", scissor: {}", rect)),
+            None => (),
+        }
+        match self.stencil {
+            Some(ref stencil) => try!(write!(f, ", stencil: {}", stencil)),
+            None => (),
+        }
+        match self.depth {
+            Some(ref depth) => try!(write!(f, ", depth: {}", depth)),
+            None => (),
+        }
+        if self.depth_range != Default::default() {
+            try!(write!(f, ", depth_range: {}", self.depth_range));
+        }
+        if self.depth_clamp {
+            try!(write!(f, ", depth_clamp"));
+        }
+        if self.clip_planes != 0 {
+            try!(write!(f, ", clip_planes: {:#x}", self.clip_planes));
+        }
+        match self.blend {
+            Some(ref blend) => try!(write!(f, ", blend: {}", blend)),
+            None => (),
+        }
+        if self.blend_value != Color::new() {
+            try!(write!(f, ", blend_value: {}", self.blend_value));
+        }
+        if !self.blend_targets.is_empty() {
+            try!(write!(f, ", blend_targets: {}", self.blend_targets));
+        }
+        if self.color_mask != s::MaskAll {
+            try!(write!(f, ", color_mask: {}", self.color_mask));
+        }
+        match self.logic_op {
+            Some(ref op) => try!(write!(f, ", logic_op: {}", op)),
+            None => (),
+        }
+        write!(f, ")")
+    }
 }
 
 /// Blend function presets for ease of use.
@@ -48,6 +110,10 @@ pub enum BlendPreset {
     /// usual transparency effect: mixes the two colors using a fraction of each one specified by
     /// the alpha of the source.
     BlendAlpha,
+    /// Same as `BlendAlpha`, but for colors that are already multiplied by
+    /// their alpha channel, so the source is added as is. The alpha channels
+    /// are blended the same way, which keeps the result premultiplied.
+    BlendPremultiplied,
 }
 
 impl DrawState {
@@ -63,11 +129,49 @@ impl DrawState {
             scissor: None,
             stencil: None,
             depth: None,
+            depth_range: Default::default(),
+            depth_clamp: false,
+            clip_planes: 0,
             blend: None,
+            blend_value: Color::new(),
+            blend_targets: Vec::new(),
             color_mask: s::MaskAll,
+            logic_op: None,
         }
     }
 
+    /// Create a `DrawState` suited for 3D rendering: same as `new()`, but with
+    /// the `Less` depth test and depth writing enabled. `new()` leaves depth
+    /// testing off, which only suits 2D content drawn in order.
+    pub fn new_3d() -> DrawState {
+        DrawState::new().depth(s::Less, true)
+    }
+
+    /// Create a `DrawState` suited for 2D and UI rendering, drawn in order:
+    ///
+    /// - blending with `BlendPremultiplied`, for textures and text with
+    ///   premultiplied alpha
+    /// - no depth test nor depth writes, the later draws going on top
+    /// - no face culling, so mirrored sprites are still visible
+    ///
+    /// The scissor test is left off, chain `scissor(Some(rect))` to clip a
+    /// widget to its area.
+    pub fn ui() -> DrawState {
+        DrawState::new().cull(s::CullNothing).blend(BlendPremultiplied)
+    }
+
+    /// Set the face culling mode, switching the primitive rasterization to `Fill`
+    pub fn cull(mut self, mode: s::CullMode) -> DrawState {
+        self.primitive.method = s::Fill(mode);
+        self
+    }
+
+    /// Set the scissor rectangle, or disable the scissor test with `None`
+    pub fn scissor(mut self, rect: Option<Rect>) -> DrawState {
+        self.scissor = rect;
+        self
+    }
+
     /// Set the stencil test to a simple expression
     pub fn stencil(mut self, fun: s::Comparison, value: Stencil) -> DrawState {
         let side = s::StencilSide {
@@ -95,8 +199,68 @@ impl DrawState {
         self
     }
 
+    /// Set the depth range, mapping the near and far planes to the given window depths
+    pub fn depth_range(mut self, near: f32, far: f32) -> DrawState {
+        self.depth_range = s::DepthRange {
+            near: near,
+            far: far,
+        };
+        self
+    }
+
+    /// Enable or disable the depth clamping
+    pub fn depth_clamp(mut self, enable: bool) -> DrawState {
+        self.depth_clamp = enable;
+        self
+    }
+
+    /// Enable the user clip planes given by the mask, bit `i` standing for the
+    /// clip distance `i` written by the vertex shader
+    pub fn clip_planes(mut self, mask: s::ClipPlaneMask) -> DrawState {
+        self.clip_planes = mask;
+        self
+    }
+
+    /// Set the constant blend color, used by the `ConstColor` and `ConstAlpha`
+    /// blend factors. It applies to the current blend mode, and is kept for the
+    /// ones set afterwards if blending is disabled for now.
+    pub fn blend_color(mut self, color: Color) -> DrawState {
+        self.blend_value = color;
+        match self.blend {
+            Some(ref mut b) => b.value = color,
+            None => (),
+        }
+        self
+    }
+
+    /// Set the blend mode with separate equations and factors for the color and
+    /// alpha channels. The constant blend color is preserved.
+    pub fn blend_separate(mut self, color: s::BlendChannel, alpha: s::BlendChannel) -> DrawState {
+        self.blend = Some(s::Blend {
+            color: color,
+            alpha: alpha,
+            value: self.blend_value,
+        });
+        self
+    }
+
+    /// Override the blend mode for a single color target, `None` disabling
+    /// blending for it. Requires the device to support per-target blending.
+    pub fn blend_target(mut self, index: ColorIndex, blend: Option<s::Blend>) -> DrawState {
+        self.blend_targets.retain(|&(i, _)| i != index);
+        self.blend_targets.push((index, blend));
+        self
+    }
+
+    /// Set the logic operation, replacing blending on the color targets.
+    pub fn logic_op(mut self, op: Option<s::LogicOp>) -> DrawState {
+        self.logic_op = op;
+        self
+    }
+
     /// Set the blend mode to one of the presets
     pub fn blend(mut self, preset: BlendPreset) -> DrawState {
+        let value = self.blend_value;
         self.blend = Some(match preset {
             BlendAdditive => s::Blend {
                 color: s::BlendChannel {
@@ -109,7 +273,7 @@ impl DrawState {
                     source: s::Factor(s::Inverse, s::Zero),
                     destination: s::Factor(s::Inverse, s::Zero),
                 },
-                value: Color::new(),
+                value: value,
             },
             BlendAlpha => s::Blend {
                 color: s::BlendChannel {
@@ -122,9 +286,106 @@ impl DrawState {
                     source: s::Factor(s::Inverse, s::Zero),
                     destination: s::Factor(s::Inverse, s::Zero),
                 },
-                value: Color::new(),
+                value: value,
+            },
+            BlendPremultiplied => s::Blend {
+                color: s::BlendChannel {
+                    equation: s::FuncAdd,
+                    source: s::Factor(s::Inverse, s::Zero),
+                    destination: s::Factor(s::Inverse, s::SourceAlpha),
+                },
+                alpha: s::BlendChannel {
+                    equation: s::FuncAdd,
+                    source: s::Factor(s::Inverse, s::Zero),
+                    destination: s::Factor(s::Inverse, s::SourceAlpha),
+                },
+                value: value,
             },
         });
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::hash::hash;
+    use s = device::state;
+    use device::target::{Color, Rect};
+    use super::{DrawState, BlendAlpha};
+    use super::BlendPremultiplied;
+
+    #[test]
+    fn test_equal_hash() {
+        let a = DrawState::new().depth(s::Less, true).blend(BlendAlpha);
+        let b = DrawState::new().depth(s::Less, true).blend(BlendAlpha);
+        assert!(a == b);
+        assert_eq!(hash(&a), hash(&b));
+        let c = DrawState::new().depth(s::Less, false).blend(BlendAlpha);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn test_reversed_depth() {
+        let state = DrawState::new().depth(s::Greater, true)
+                                    .depth_range(1.0, 0.0)
+                                    .depth_clamp(true);
+        assert_eq!(state.depth_range, s::DepthRange { near: 1.0, far: 0.0 });
+        assert!(state.depth_clamp);
+        assert!(state != DrawState::new().depth(s::Greater, true));
+        let text = format!("{}", state);
+        assert!(text.as_slice().contains("depth_range: DepthRange { near: 1, far: 0 }"));
+        assert!(text.as_slice().contains("depth_clamp"));
+    }
+
+    #[test]
+    fn test_blend_color() {
+        let color = Color([0.5, 0.0, 0.0, 0.25]);
+        let before = DrawState::new().blend_color(color).blend(BlendAlpha);
+        let after = DrawState::new().blend(BlendAlpha).blend_color(color);
+        assert_eq!(before.blend.unwrap().value, color);
+        assert!(before == after);
+    }
+
+    #[test]
+    fn test_new_3d() {
+        let state = DrawState::new_3d();
+        assert_eq!(state.depth, Some(s::Depth { fun: s::Less, write: true }));
+        assert_eq!(state.primitive.method, s::Fill(s::CullBack));
+        assert!(DrawState::new().depth.is_none());
+    }
+
+    #[test]
+    fn test_chaining() {
+        let rect = Rect { x: 0, y: 0, w: 10, h: 20 };
+        let state = DrawState::new().depth(s::LessEqual, false)
+                                    .cull(s::CullFront)
+                                    .scissor(Some(rect))
+                                    .blend(BlendAlpha);
+        assert_eq!(state.depth, Some(s::Depth { fun: s::LessEqual, write: false }));
+        assert_eq!(state.primitive.method, s::Fill(s::CullFront));
+        assert_eq!(state.scissor, Some(rect));
+        assert!(state.blend.is_some());
+    }
+
+    #[test]
+    fn test_show_compact() {
+        let state = DrawState::new().depth(s::LessEqual, true);
+        let text = format!("{}", state);
+        assert!(text.as_slice().starts_with("DrawState(Primitive {"));
+        assert!(text.as_slice().contains("depth: Depth { fun: LessEqual, write: true }"));
+        assert!(!text.as_slice().contains("blend"));
+        assert!(!text.as_slice().contains("stencil"));
+    }
+
+    #[test]
+    fn test_ui() {
+        let state = DrawState::ui();
+        let blend = state.blend.unwrap();
+        assert_eq!(blend.color.source, s::Factor(s::Inverse, s::Zero));
+        assert_eq!(blend.color.destination, s::Factor(s::Inverse, s::SourceAlpha));
+        assert_eq!(blend.alpha, blend.color);
+        assert_eq!(state.blend, DrawState::new().blend(BlendPremultiplied).blend);
+        assert!(state.depth.is_none());
+        assert_eq!(state.primitive.method, s::Fill(s::CullNothing));
+    }
+}
@@ -14,8 +14,9 @@
 
 //! Render target specification.
 
+use device;
 use t = device::target;
-use backend = device::back;
+use device::tex::Format;
 
 static MAX_COLOR_TARGETS: uint = 4;
 
@@ -25,10 +26,55 @@ pub enum Plane {
     /// No buffer, the results will not be stored.
     PlaneEmpty,
     /// Render to a `Surface` (corresponds to a renderbuffer in GL).
-    PlaneSurface(backend::Surface),
+    PlaneSurface(device::SurfaceHandle),
     /// Render to a texture at a specific mipmap level
     /// If `Layer` is set, it is selecting a single 2D slice of a given 3D texture
-    PlaneTexture(backend::Texture, t::Level, Option<t::Layer>),
+    PlaneTexture(device::TextureHandle, t::Level, Option<t::Layer>),
+}
+
+impl Plane {
+    /// Get the format of the buffer, if there is any.
+    pub fn get_format(&self) -> Option<Format> {
+        match *self {
+            PlaneEmpty => None,
+            PlaneSurface(ref suf) => Some(suf.get_info().format),
+            PlaneTexture(ref tex, _, _) => Some(tex.get_info().format),
+        }
+    }
+
+    /// Check if the buffer packs depth and stencil together.
+    pub fn is_packed(&self) -> bool {
+        self.get_format().map_or(false, |f| f.is_depth() && f.has_stencil())
+    }
+}
+
+/// An error with the planes of a `Frame`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum FrameError {
+    /// A plane with a depth or stencil format was bound to the given color target.
+    ErrorColorFormat(t::ColorIndex),
+    /// The depth plane doesn't have a depth format.
+    ErrorDepthFormat,
+    /// The stencil plane doesn't have a stencil format.
+    ErrorStencilFormat,
+    /// The depth plane already packs the stencil, so the stencil plane has to be empty.
+    ErrorStencilPacked,
+    /// The depth is cleared, but the frame has no depth plane.
+    ErrorClearDepth,
+    /// The stencil is cleared, but the frame has no stencil plane.
+    ErrorClearStencil,
+    /// The given color target is cleared with integers, but has no plane
+    /// of a matching integer format.
+    ErrorClearInteger(t::ColorIndex),
+}
+
+/// Which frame buffer a `Frame` renders to, see `Frame::get_kind`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum FrameKind {
+    /// The default frame buffer, whose buffers belong to the window.
+    DefaultFrame,
+    /// A frame buffer object, with the given number of planes bound to it.
+    CustomFrame(uint),
 }
 
 /// A complete `Frame`, which is the result of rendering.
@@ -41,10 +87,24 @@ pub struct Frame {
     pub colors: [Plane, ..MAX_COLOR_TARGETS],
     /// The depth buffer for this frame.
     pub depth: Plane,
-    /// The stencil buffer for this frame.
+    /// The stencil buffer for this frame. Has to be empty if the depth buffer
+    /// packs the stencil already.
     pub stencil: Plane,
 }
 
+// manual impl due to array...
+impl Clone for Frame {
+    fn clone(&self) -> Frame {
+        Frame {
+            width: self.width,
+            height: self.height,
+            colors: self.colors,
+            depth: self.depth,
+            stencil: self.stencil,
+        }
+    }
+}
+
 impl Frame {
     /// Create an empty `Frame`, which corresponds to the 'default framebuffer', which for now
     /// renders directly to the window that was created with the OpenGL context.
@@ -65,4 +125,267 @@ impl Frame {
         self.depth == PlaneEmpty &&
         self.stencil == PlaneEmpty
     }
+
+    /// Count the planes bound to this frame. The default frame has none, since
+    /// its buffers belong to the window rather than to a frame buffer object.
+    pub fn count_attachments(&self) -> uint {
+        let colors = self.colors.iter().filter(|&p| *p != PlaneEmpty).count();
+        let others = [self.depth, self.stencil].iter().filter(|&p| *p != PlaneEmpty).count();
+        colors + others
+    }
+
+    /// Tell the default frame apart from the custom ones, which the back-end
+    /// renders to through a frame buffer object.
+    pub fn get_kind(&self) -> FrameKind {
+        if self.is_default() {
+            DefaultFrame
+        } else {
+            CustomFrame(self.count_attachments())
+        }
+    }
+
+    /// Check if the frame has a depth buffer. The default frame is assumed to
+    /// have one, provided by the window.
+    pub fn has_depth(&self) -> bool {
+        self.is_default() || self.depth != PlaneEmpty
+    }
+
+    /// Check if the frame has a stencil buffer, either as a separate plane or
+    /// packed with the depth. The default frame is assumed to have one.
+    pub fn has_stencil(&self) -> bool {
+        self.is_default() || self.stencil != PlaneEmpty || self.depth.is_packed()
+    }
+
+    /// Get the format of the plane bound to a color target, if there is any.
+    pub fn get_color_format(&self, index: t::ColorIndex) -> Option<Format> {
+        match self.colors.get(index as uint) {
+            Some(plane) => plane.get_format(),
+            None => None,
+        }
+    }
+
+    /// Check that each plane has a format matching the target it is bound to.
+    pub fn validate(&self) -> Result<(), FrameError> {
+        for (i, plane) in self.colors.iter().enumerate() {
+            match plane.get_format() {
+                Some(format) if format.is_depth() || format.has_stencil() =>
+                    return Err(ErrorColorFormat(i as t::ColorIndex)),
+                _ => (),
+            }
+        }
+        match self.depth.get_format() {
+            Some(format) if !format.is_depth() => return Err(ErrorDepthFormat),
+            _ => (),
+        }
+        match self.stencil.get_format() {
+            Some(_) if self.depth.is_packed() => Err(ErrorStencilPacked),
+            Some(format) if !format.has_stencil() => Err(ErrorStencilFormat),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use device;
+    use device::ApiBackEnd;
+    use device::null::NullBackEnd;
+    use device::target::{ClearData, Color, TargetColor, TargetDepth, TargetStencil};
+    use device::tex;
+    use fixture::{SOURCE, commands, has_command, sampler, setup, texture_dict};
+    use front::{BackEndHelper, ErrorFrame};
+    use state::DrawState;
+    use super::{Frame, PlaneTexture, PlaneSurface, DefaultFrame, CustomFrame};
+    use super::{ErrorColorFormat, ErrorStencilPacked, ErrorClearDepth, ErrorClearStencil};
+
+    fn texture(backend: &mut NullBackEnd, format: tex::Format) -> device::TextureHandle {
+        let mut info = tex::TextureInfo::new();
+        info.width = 64;
+        info.height = 64;
+        info.format = format;
+        backend.create_texture(info).unwrap()
+    }
+
+    fn surface(backend: &mut NullBackEnd, format: tex::Format) -> device::SurfaceHandle {
+        backend.create_surface(tex::SurfaceInfo {
+            width: 64,
+            height: 64,
+            format: format,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_default_frame() {
+        let frame = Frame::new(640, 480);
+        assert!(frame.is_default());
+        assert_eq!(frame.count_attachments(), 0);
+        let mut custom = Frame::new(64, 64);
+        custom.colors[0] = PlaneTexture(device::make_fake_texture(), 0, None);
+        assert!(!custom.is_default());
+        assert_eq!(custom.count_attachments(), 1);
+        assert_eq!((custom.width, custom.height), (64, 64));
+        match frame.get_kind() {
+            DefaultFrame => (),
+            CustomFrame(count) => fail!("The window frame has {} attachments", count),
+        }
+        match custom.get_kind() {
+            DefaultFrame => fail!("A frame with a texture is the window frame"),
+            CustomFrame(count) => assert_eq!(count, 1),
+        }
+    }
+
+    #[test]
+    fn test_color_only() {
+        let mut frame = Frame::new(64, 64);
+        assert!(frame.has_depth() && frame.has_stencil());
+        frame.colors[0] = PlaneTexture(device::make_fake_texture(), 0, None);
+        assert!(!frame.has_depth());
+        assert!(!frame.has_stencil());
+    }
+
+    #[test]
+    fn test_float_target() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, scene) = setup(&mut backend);
+        let hdr = texture(&mut backend, tex::RGBA16F);
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(hdr, 0, None);
+        backend.set_samplers(vec![sampler("tex", 0)]);
+        let dict = texture_dict(hdr);
+        let tonemap = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+        let state = DrawState::new();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), &frame, &scene, &state).unwrap();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &tonemap, &state).unwrap();
+        let uses = commands(&list, |com| match *com {
+            device::BindTargetTexture(TargetColor(0), name, 0, None) => Some(("target", name)),
+            device::BindTexture(0, _, name, _) => Some(("sample", name)),
+            _ => None,
+        });
+        assert_eq!(uses, vec![("target", hdr.get_name()), ("sample", hdr.get_name())]);
+    }
+
+    #[test]
+    fn test_depth_texture() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, caster) = setup(&mut backend);
+        let shadow = texture(&mut backend, tex::DEPTH24);
+        let mut frame = Frame::new(64, 64);
+        frame.depth = PlaneTexture(shadow, 0, None);
+        backend.set_samplers(vec![sampler("tex", 0)]);
+        let dict = texture_dict(shadow);
+        let receiver = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+        let state = DrawState::new_3d();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), &frame, &caster, &state).unwrap();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &receiver, &state).unwrap();
+        let uses = commands(&list, |com| match *com {
+            device::BindTargetTexture(TargetDepth, name, 0, None) => Some(("target", name)),
+            device::BindTexture(0, _, name, _) => Some(("sample", name)),
+            _ => None,
+        });
+        assert_eq!(uses, vec![("target", shadow.get_name()), ("sample", shadow.get_name())]);
+
+        // a depth texture can't be a color target
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(shadow, 0, None);
+        match list.draw(&mesh, mesh.get_slice(), &frame, &caster, &state) {
+            Err(ErrorFrame(ErrorColorFormat(0))) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+    }
+
+    #[test]
+    fn test_separate_stencil() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, program) = setup(&mut backend);
+        let depth = surface(&mut backend, tex::DEPTH24);
+        let stencil = surface(&mut backend, tex::STENCIL8);
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneSurface(surface(&mut backend, tex::RGBA8));
+        frame.depth = PlaneSurface(depth);
+        frame.stencil = PlaneSurface(stencil);
+        let state = DrawState::new_3d();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), &frame, &program, &state).unwrap();
+        let binds = commands(&list, |com| match *com {
+            device::BindTargetSurface(TargetStencil, name) => Some(("stencil", name)),
+            device::BindTargetSurface(TargetDepth, name) => Some(("depth", name)),
+            _ => None,
+        });
+        assert_eq!(binds, vec![("stencil", stencil.get_name()), ("depth", depth.get_name())]);
+
+        // a packed depth plane leaves no room for a separate stencil one
+        frame.depth = PlaneSurface(surface(&mut backend, tex::DEPTH24STENCIL8));
+        match list.draw(&mesh, mesh.get_slice(), &frame, &program, &state) {
+            Err(ErrorFrame(ErrorStencilPacked)) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+    }
+
+    #[test]
+    fn test_clear_all() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(texture(&mut backend, tex::RGBA8), 0, None);
+        let color = Color([0.1, 0.2, 0.3, 1.0]);
+
+        let mut list = frontend.create_drawlist();
+        list.clear_all(color, frontend.get_main_frame());
+        list.clear_all(color, &frame);
+        let clears = commands(&list, |com| match *com {
+            device::Clear(data) => Some((data.color, data.depth, data.stencil)),
+            _ => None,
+        });
+        assert_eq!(clears, vec![(Some(color), Some(1.0), Some(0)), (Some(color), None, None)]);
+    }
+
+    #[test]
+    fn test_try_clear() {
+        let mut backend = NullBackEnd::new();
+        let frontend = backend.create_frontend(64, 64).unwrap();
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(texture(&mut backend, tex::RGBA8), 0, None);
+        let data = |depth, stencil| ClearData { color: None, depth: depth, stencil: stencil };
+
+        let mut list = frontend.create_drawlist();
+        match list.try_clear(data(Some(1.0), None), &frame) {
+            Err(ErrorFrame(ErrorClearDepth)) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+        match list.try_clear(data(None, Some(0)), &frame) {
+            Err(ErrorFrame(ErrorClearStencil)) => (),
+            other => fail!("Unexpected result {}", other),
+        }
+        // nothing is recorded for the failed clears
+        assert!(!has_command(&list, |com| match *com {
+            device::Clear(_) => true,
+            _ => false,
+        }));
+        list.try_clear(data(Some(1.0), Some(0)), frontend.get_main_frame()).unwrap();
+    }
+
+    #[test]
+    fn test_frame_buffer_order() {
+        let mut backend = NullBackEnd::new();
+        let (frontend, mesh, program) = setup(&mut backend);
+        let mut frame = Frame::new(64, 64);
+        frame.colors[0] = PlaneTexture(texture(&mut backend, tex::RGBA8), 0, None);
+        let state = DrawState::new();
+
+        let mut list = frontend.create_drawlist();
+        list.draw(&mesh, mesh.get_slice(), &frame, &program, &state).unwrap();
+        list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state).unwrap();
+        list.draw(&mesh, mesh.get_slice(), &frame, &program, &state).unwrap();
+        let binds = commands(&list, |com| match *com {
+            device::BindFrameBuffer(fbo) => Some(fbo),
+            _ => None,
+        });
+        assert!(binds[0] != 0);
+        assert_eq!(binds, vec![binds[0], 0, binds[0]]);
+    }
 }
@@ -0,0 +1,161 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![crate_name = "backend"]
+
+#![feature(phase)]
+
+#[phase(plugin)]
+extern crate gfx_macros;
+extern crate gfx;
+extern crate device;
+
+use std::cell::Cell;
+use device::{shade, tex};
+use device::{ApiBackEnd, GraphicsContext, TextureHandle};
+use device::null::NullBackEnd;
+use ListTrait = device::draw::DrawList;
+use gfx::BackEndHelper;
+
+#[repr(C)]
+#[vertex_format]
+struct Vertex {
+    pos: [f32, ..2],
+    color: [f32, ..3],
+}
+
+/// A context recording when it is made current.
+struct MockContext<'a> {
+    id: uint,
+    current: &'a Cell<uint>,
+}
+
+impl<'a> GraphicsContext<NullBackEnd> for MockContext<'a> {
+    fn swap_buffers(&self) {}
+    fn make_current(&self) {
+        self.current.set(self.id);
+    }
+}
+
+fn sampler(name: &str, location: uint) -> shade::SamplerVar {
+    shade::SamplerVar {
+        name: name.to_string(),
+        location: location,
+        base_type: shade::BaseF32,
+        sampler_type: shade::Sampler2D(shade::NoArray, shade::NoShadow, shade::NoMultiSample,
+                                       shade::NoRect),
+    }
+}
+
+static SOURCE: gfx::ShaderSource = shaders! {
+GLSL_150: b"#version 150 core\nvoid main() {}"
+};
+
+fn make_triangle(backend: &mut NullBackEnd) -> gfx::Mesh {
+    backend.create_mesh(vec![
+        Vertex { pos: [ -0.5, -0.5 ], color: [1.0, 0.0, 0.0] },
+        Vertex { pos: [ 0.5, -0.5 ], color: [0.0, 1.0, 0.0]  },
+        Vertex { pos: [ 0.0, 0.5 ], color: [0.0, 0.0, 1.0]  }
+    ])
+}
+
+/// A dictionary providing the texture to the `tex` sampler.
+fn texture_dict(texture: TextureHandle) -> gfx::shade::ParamDictionary {
+    gfx::shade::ParamDictionary {
+        uniforms: Vec::new(),
+        blocks: Vec::new(),
+        textures: vec![gfx::shade::NamedCell {
+            name: "tex".to_string(),
+            value: Cell::new((texture, None)),
+        }],
+    }
+}
+
+#[test]
+fn test_null_triangle() {
+    let mut backend = NullBackEnd::new();
+    let frontend = backend.create_frontend(64, 64).unwrap();
+    let mesh = make_triangle(&mut backend);
+    let program = backend.link_program((), SOURCE.clone(), SOURCE.clone()).unwrap();
+    let state = gfx::DrawState::new();
+
+    let mut list = frontend.create_drawlist();
+    list.clear(gfx::ClearData {
+        color: Some(gfx::Color([0.3, 0.3, 0.3, 1.0])),
+        depth: None,
+        stencil: None,
+    }, frontend.get_main_frame());
+    list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program, &state)
+        .unwrap();
+    backend.submit(list.as_slice());
+
+    assert!(backend.get_command_count() > 2);
+    let stats = backend.get_frame_stats();
+    assert_eq!((stats.draw_count, stats.vertex_count), (1, 3));
+}
+
+#[test]
+fn test_switch_context() {
+    let current = Cell::new(0u);
+    let first = MockContext { id: 1, current: &current };
+    let second = MockContext { id: 2, current: &current };
+    let mut backend = NullBackEnd::new();
+    let mut list = device::DrawList::new();
+    list.call_draw(device::TriangleList, 0, 3, None);
+
+    device::submit_to(&first, &mut backend, &list);
+    assert_eq!((current.get(), backend.get_command_count()), (1, 1));
+    device::submit_to(&second, &mut backend, &list);
+    assert_eq!((current.get(), backend.get_command_count()), (2, 2));
+}
+
+#[test]
+fn test_import() {
+    let mut backend = NullBackEnd::new();
+    let frontend = backend.create_frontend(64, 64).unwrap();
+    backend.set_samplers(vec![sampler("tex", 0)]);
+    // names of objects created by another library
+    let buffer = backend.import_buffer(1000);
+    let texture = backend.import_texture(1001, tex::TextureInfo::new());
+    let mesh = gfx::Mesh::from::<Vertex>(buffer, 3);
+    let dict = texture_dict(texture);
+    let program = backend.link_program(&dict, SOURCE.clone(), SOURCE.clone()).unwrap();
+    let mut list = frontend.create_drawlist();
+    list.draw(&mesh, mesh.get_slice(), frontend.get_main_frame(), &program,
+              &gfx::DrawState::new()).unwrap();
+    assert!(list.as_slice().iter().any(|com| match *com {
+        device::BindAttribute(_, 1000, _, _, _, _, _) => true,
+        _ => false,
+    }));
+    assert!(list.as_slice().iter().any(|com| match *com {
+        device::BindTexture(0, _, 1001, _) => true,
+        _ => false,
+    }));
+    backend.submit(list.as_slice());
+    assert_eq!(backend.get_frame_stats().draw_count, 1);
+}
+
+#[test]
+fn test_link_program_async() {
+    let mut backend = NullBackEnd::new();
+    backend.set_samplers(vec![sampler("tex", 0)]);
+    let vs = backend.create_shader(shade::Vertex, SOURCE.clone()).unwrap();
+    let fs = backend.create_shader(shade::Fragment, SOURCE.clone()).unwrap();
+    let pending = backend.link_program_async(&[vs, fs]);
+    assert_eq!(backend.get_link_count(), 0);
+    while !backend.is_program_ready(&pending) {}
+    let program = backend.finish_program(pending).unwrap();
+    assert_eq!(backend.get_link_count(), 1);
+    assert_eq!(program.get_info().textures.len(), 1);
+}
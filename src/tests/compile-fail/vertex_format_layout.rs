@@ -0,0 +1,27 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![feature(phase)]
+
+#[phase(plugin)]
+extern crate gfx_macros;
+extern crate gfx;
+
+// the field offsets are only meaningful with a defined layout
+#[vertex_format]
+struct Vertex { //~ ERROR A vertex format needs a defined layout
+    a_Pos: [f32, ..2],
+}
+
+fn main() {}
@@ -19,6 +19,10 @@
 #[phase(plugin)]
 extern crate gfx_macros;
 extern crate gfx;
+extern crate device;
+
+use s = device::shade;
+use gfx::shade::ShaderParam;
 
 #[shader_param(MyShell)]
 struct MyParam {
@@ -29,3 +33,153 @@ struct MyParam {
     #[unused]
     e: f32,
 }
+
+#[shader_param]
+struct TestParam {
+    alpha: f32,
+    tex: gfx::shade::TextureParam,
+}
+
+#[test]
+fn test_link() {
+    let uniforms = vec![s::UniformVar {
+        name: "alpha".to_string(),
+        location: 0,
+        count: 1,
+        base_type: s::BaseF32,
+        container: s::Single,
+    }];
+    let textures = vec![s::SamplerVar {
+        name: "tex".to_string(),
+        location: 1,
+        base_type: s::BaseF32,
+        sampler_type: s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect),
+    }];
+    let param = TestParam {
+        alpha: 0.5,
+        tex: gfx::shade::texture_param(device::make_fake_texture()),
+    };
+    let link = param.create_link((uniforms.as_slice(), &[], textures.as_slice())).unwrap();
+    let mut uniform_values = vec![None];
+    let mut texture_values = vec![None];
+    param.fill_params(&link, gfx::shade::ParamValues {
+        uniforms: uniform_values.as_mut_slice(),
+        blocks: &mut [],
+        textures: texture_values.as_mut_slice(),
+    });
+    match uniform_values[0] {
+        Some(s::ValueF32(v)) => assert_eq!(v, 0.5),
+        ref other => fail!("Unexpected uniform value: {}", other),
+    }
+    assert_eq!(texture_values[0], Some((device::make_fake_texture(), None)));
+    // an unknown uniform name fails the link
+    let missing = vec![s::UniformVar { name: "beta".to_string(), .. uniforms[0].clone() }];
+    assert_eq!(param.create_link((missing.as_slice(), &[], textures.as_slice())).err(),
+               Some(gfx::shade::ErrorUniform("alpha")));
+}
+
+#[shader_param]
+struct RenamedParam {
+    #[name = "u_MVP"]
+    model_view_proj: [[f32, ..4], ..4],
+}
+
+#[test]
+fn test_link_renamed() {
+    let uniforms = vec![s::UniformVar {
+        name: "u_MVP".to_string(),
+        location: 0,
+        count: 1,
+        base_type: s::BaseF32,
+        container: s::Matrix(s::ColumnMajor, 4, 4),
+    }];
+    let param = RenamedParam {
+        model_view_proj: [[0.0, ..4], ..4],
+    };
+    assert!(param.create_link((uniforms.as_slice(), &[], &[])).is_ok());
+    assert_eq!(param.create_link((&[], &[], &[])).err(),
+               Some(gfx::shade::ErrorUniform("u_MVP")));
+}
+
+type MyBuffer = gfx::BufferHandle;
+
+#[shader_param]
+struct AnnotatedParam {
+    scale: f32,
+    #[block]
+    transform: MyBuffer,
+    #[texture]
+    diffuse: gfx::shade::TextureParam,
+}
+
+#[test]
+fn test_fill_annotated() {
+    let uniforms = vec![s::UniformVar {
+        name: "scale".to_string(),
+        location: 0,
+        count: 1,
+        base_type: s::BaseF32,
+        container: s::Single,
+    }];
+    let blocks = vec![s::BlockVar {
+        name: "transform".to_string(),
+        size: 64,
+        usage: 1,
+        members: Vec::new(),
+    }];
+    let textures = vec![s::SamplerVar {
+        name: "diffuse".to_string(),
+        location: 1,
+        base_type: s::BaseF32,
+        sampler_type: s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect),
+    }];
+    let param = AnnotatedParam {
+        scale: 2.0,
+        transform: device::make_fake_buffer(),
+        diffuse: (device::make_fake_texture(), None),
+    };
+    let link = param.create_link((uniforms.as_slice(), blocks.as_slice(),
+                                  textures.as_slice())).unwrap();
+    let mut uniform_values = vec![None];
+    let mut block_values = vec![None];
+    let mut texture_values = vec![None];
+    param.fill_params(&link, gfx::shade::ParamValues {
+        uniforms: uniform_values.as_mut_slice(),
+        blocks: block_values.as_mut_slice(),
+        textures: texture_values.as_mut_slice(),
+    });
+    assert!(uniform_values[0].is_some());
+    assert!(block_values[0] == Some(device::make_fake_buffer()));
+    assert!(texture_values[0].is_some());
+}
+
+#[shader_param]
+struct SharedParam {
+    color: [f32, ..4],
+    #[optional]
+    fog: f32,
+}
+
+#[test]
+fn test_link_optional() {
+    let uniforms = vec![s::UniformVar {
+        name: "color".to_string(),
+        location: 0,
+        count: 1,
+        base_type: s::BaseF32,
+        container: s::Vector(4),
+    }];
+    let param = SharedParam {
+        color: [1.0, ..4],
+        fog: 0.5,
+    };
+    let link = param.create_link((uniforms.as_slice(), &[], &[])).unwrap();
+    assert!(link.fog.is_none());
+    let mut uniform_values = vec![None];
+    param.fill_params(&link, gfx::shade::ParamValues {
+        uniforms: uniform_values.as_mut_slice(),
+        blocks: &mut [],
+        textures: &mut [],
+    });
+    assert!(uniform_values[0].is_some());
+}
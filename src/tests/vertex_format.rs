@@ -22,6 +22,7 @@ extern crate gfx;
 extern crate device;
 
 use a = gfx::attrib;
+use gfx::VertexFormat;
 
 #[packed]
 #[vertex_format]
@@ -48,6 +49,7 @@ fn test_vertex_format() {
             elem_type: a::Float(a::FloatDefault, a::F32),
             offset: 0,
             stride: stride,
+            instance_rate: 0,
             name: "a0".to_string(),
         },
         gfx::Attribute {
@@ -56,6 +58,7 @@ fn test_vertex_format() {
             elem_type: a::Int(a::IntNormalized, a::U16, a::Signed),
             offset: 8,
             stride: stride,
+            instance_rate: 0,
             name: "a1".to_string(),
         },
         gfx::Attribute {
@@ -64,6 +67,7 @@ fn test_vertex_format() {
             elem_type: a::Int(a::IntAsFloat, a::U8, a::Signed),
             offset: 10,
             stride: stride,
+            instance_rate: 0,
             name: "a2".to_string(),
         },
         gfx::Attribute {
@@ -72,7 +76,122 @@ fn test_vertex_format() {
             elem_type: a::Float(a::FloatPrecision, a::F64),
             offset: 14,
             stride: stride,
+            instance_rate: 0,
             name: "a3".to_string(),
         }
     ]);
 }
+
+#[packed]
+#[vertex_format]
+struct Transform {
+    offset: [f32, ..2],
+    scale: f32,
+}
+
+#[packed]
+#[vertex_format]
+struct NestedVertex {
+    color: [u8, ..4],
+    transform: Transform,
+}
+
+#[test]
+fn test_nested() {
+    let buf = device::make_fake_buffer();
+    let mesh = gfx::Mesh::from::<NestedVertex>(buf, 0);
+    let stride = 16 as a::Stride;
+
+    assert_eq!(mesh.attributes.iter().map(|at| (at.name.as_slice(), at.offset, at.stride))
+                                     .collect::<Vec<(&str, a::Offset, a::Stride)>>(),
+               vec![("color", 0, stride), ("offset", 4, stride), ("scale", 12, stride)]);
+}
+
+#[repr(C)]
+#[vertex_format]
+struct PosVertex {
+    #[position]
+    pos: [i8, ..2],
+    color: [f32, ..3],
+}
+
+#[test]
+fn test_position_bounds() {
+    let data = vec![
+        PosVertex { pos: [-1, 2], color: [1.0, 0.0, 0.0] },
+        PosVertex { pos: [3, -4], color: [0.0, 1.0, 0.0] },
+        PosVertex { pos: [0, 1], color: [0.0, 0.0, 1.0] },
+    ];
+    assert_eq!(data[1].get_position(), Some((3.0, -4.0, 0.0)));
+    assert_eq!(gfx::calculate_bounds(data.as_slice()),
+               Some(((-1.0, -4.0, 0.0), (3.0, 2.0, 0.0))));
+    assert_eq!(gfx::calculate_bounds::<MyVertex>([]), None);
+    let mut mesh = gfx::Mesh::new(3);
+    assert_eq!(mesh.bounds(), None);
+    mesh.compute_bounds(data.as_slice());
+    assert_eq!(mesh.bounds(), Some(((-1.0, -4.0, 0.0), (3.0, 2.0, 0.0))));
+}
+
+#[repr(C)]
+#[vertex_format]
+struct TriangleVertex {
+    pos: [f32, ..2],
+    color: [f32, ..3],
+}
+
+#[test]
+fn test_describe() {
+    let attributes = gfx::describe::<TriangleVertex>();
+    assert_eq!(attributes.iter().map(|at| (at.name.as_slice(), at.elem_count, at.offset, at.stride))
+                                .collect::<Vec<(&str, a::Count, a::Offset, a::Stride)>>(),
+               vec![("pos", 2, 0, 20), ("color", 3, 8, 20)]);
+    assert!(attributes.iter().all(|at| at.elem_type == a::Float(a::FloatDefault, a::F32)));
+}
+
+/// A vertex format implemented by hand, without the `#[vertex_format]` macro.
+struct ManualVertex {
+    _height: f32,
+}
+
+impl VertexFormat for ManualVertex {
+    fn generate(_: Option<ManualVertex>, buffer: gfx::BufferHandle) -> Vec<gfx::Attribute> {
+        vec![gfx::Attribute {
+            buffer: buffer,
+            elem_count: 1,
+            elem_type: a::Float(a::FloatDefault, a::F32),
+            offset: 0,
+            stride: 4,
+            instance_rate: 0,
+            name: "height".to_string(),
+        }]
+    }
+}
+
+#[test]
+fn test_manual_format() {
+    let buf = device::make_fake_buffer();
+    let mesh = gfx::Mesh::from::<ManualVertex>(buf, 3);
+    assert_eq!(mesh.num_vertices, 3);
+    assert_eq!(mesh.attributes.len(), 1);
+    assert_eq!(mesh.attributes[0].name, "height".to_string());
+    assert_eq!(mesh.attributes[0].buffer, buf);
+}
+
+#[repr(C)]
+#[vertex_format]
+struct InstanceData {
+    offset: [f32, ..3],
+    scale: f32,
+}
+
+#[test]
+fn test_instanced() {
+    let vertex_buf = device::make_fake_buffer();
+    let instance_buf = device::make_fake_buffer();
+    let mut mesh = gfx::Mesh::from::<PosVertex>(vertex_buf, 3);
+    mesh.add_instanced::<InstanceData>(instance_buf);
+    assert_eq!(mesh.attributes.iter().map(|at| (at.name.as_slice(), at.instance_rate))
+                                     .collect::<Vec<(&str, a::InstanceRate)>>(),
+               vec![("pos", 0), ("color", 0), ("offset", 1), ("scale", 1)]);
+    assert_eq!(mesh.attributes[2].stride, 16);
+}